@@ -1,17 +1,43 @@
-use std::path::PathBuf;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 use crossterm::event::{Event, EventStream, KeyCode, KeyEventKind, KeyModifiers};
 use futures::StreamExt;
-use gauchito_core::document::{Document, ViewId};
+use gauchito_core::changeset::ChangeSet;
+use gauchito_core::document::{Document, DocumentId, ViewId};
+use gauchito_core::fileio::{self, DiskStamp};
+use gauchito_core::history::Coalesce;
+use gauchito_core::swap::{self, SwapFile};
 use gauchito_script::{ScriptRuntime, SharedState};
-use gauchito_ui::{EditorState, Effect};
+use gauchito_ui::{EditorState, Effect, SplitDirection};
 use ratatui::prelude::*;
+use ratatui::widgets::Paragraph;
+use ropey::Rope;
 
 use gauchito_ui::{Cursor, Pane, PromptOverlay, StatusLine};
 
+/// How often modified documents are written to their swap files.
+const SWAP_INTERVAL: Duration = Duration::from_secs(2);
+/// How often open files are checked for changes made by other programs.
+const DISK_INTERVAL: Duration = Duration::from_secs(1);
+/// Shown at the right end of the statusline while the focused document has
+/// unsaved changes.
+const MODIFIED_FLAG: &str = " [+]";
+
 pub struct App {
     state: SharedState,
     script: ScriptRuntime,
+    swap_dir: PathBuf,
+    /// File and revision each document's swap file was last written for.
+    swapped: HashMap<DocumentId, (PathBuf, u64)>,
+    /// A crash leftover waiting for the user to pick recover / diff / discard.
+    pending_swap: Option<(DocumentId, SwapFile)>,
+    /// A modified document whose file changed on disk, waiting for the user
+    /// to pick reload / keep.
+    pending_reload: Option<DocumentId>,
+    /// Undo grouping from the config, given to every document opened.
+    undo_rules: Coalesce,
 }
 
 impl App {
@@ -27,13 +53,16 @@ impl App {
         let initial_mode = script.initial_mode();
         let components = script.component_registry();
 
-        let doc = match path {
+        let undo_rules = script.undo_rules();
+        let mut doc = match path {
             Some(p) => gauchito_core::fileio::load(p)?,
             None => Document::new(),
         };
+        doc.coalesce = undo_rules;
 
         let doc_id = doc.id;
         let mut state = EditorState::new(view_id, initial_mode, components);
+        state.prompt = read_only_warning(&doc);
 
         state.add_document(doc);
         state.add_view(view_id, doc_id);
@@ -41,11 +70,23 @@ impl App {
         let state = gauchito_script::shared(state);
         script.run_initial_mode_callback(&state);
 
-        Ok(App { state, script })
+        let mut app = App {
+            state,
+            script,
+            swap_dir: gauchito_paths::swap_dir(),
+            swapped: HashMap::new(),
+            pending_swap: None,
+            pending_reload: None,
+            undo_rules,
+        };
+        app.check_swap(doc_id);
+        Ok(app)
     }
 
     pub async fn run(&mut self, terminal: &mut ratatui::DefaultTerminal) -> anyhow::Result<()> {
         let mut event_stream = EventStream::new();
+        let mut swap_timer = tokio::time::interval(SWAP_INTERVAL);
+        let mut disk_timer = tokio::time::interval(DISK_INTERVAL);
 
         loop {
             terminal.draw(|f| {
@@ -63,7 +104,18 @@ impl App {
                 Cursor::apply_style(&state);
 
                 if has_statusline {
-                    StatusLine::render(f, chunks[1], &state);
+                    let flag = if state.focused_doc().is_modified() {
+                        MODIFIED_FLAG
+                    } else {
+                        ""
+                    };
+                    let [line, flag_area] = Layout::horizontal([
+                        Constraint::Min(0),
+                        Constraint::Length(flag.len() as u16),
+                    ])
+                    .areas(chunks[1]);
+                    StatusLine::render(f, line, &state);
+                    f.render_widget(Paragraph::new(flag), flag_area);
                 }
 
                 PromptOverlay::render(f, f.area(), &state);
@@ -75,6 +127,15 @@ impl App {
                         if key.kind == KeyEventKind::Press {
                             let (key_name, is_printable) = key_event_to_name(key.code, key.modifiers);
 
+                            if let Some((doc_id, found)) = self.pending_swap.take() {
+                                self.resolve_swap(doc_id, found, &key_name);
+                                continue;
+                            }
+                            if let Some(doc_id) = self.pending_reload.take() {
+                                self.resolve_reload(doc_id, &key_name);
+                                continue;
+                            }
+
                             let effects = self.script.dispatch_key(
                                 &key_name,
                                 is_printable,
@@ -82,11 +143,15 @@ impl App {
                             );
 
                             if self.process_effects(effects)? {
+                                self.remove_swaps();
+                                self.script.save_registers();
                                 return Ok(());
                             }
                         }
                     }
                 }
+                _ = swap_timer.tick() => self.write_swaps(),
+                _ = disk_timer.tick() => self.check_disk(),
             }
         }
     }
@@ -100,8 +165,15 @@ impl App {
                 Effect::Quit => return Ok(true),
                 Effect::Edit(_, _) => {}
                 Effect::OpenFile(path) => {
-                    let doc = gauchito_core::fileio::load(path)?;
-                    self.state.borrow_mut().open_document(doc);
+                    let mut doc = gauchito_core::fileio::load(path)?;
+                    doc.coalesce = self.undo_rules;
+                    let doc_id = doc.id;
+                    {
+                        let mut state = self.state.borrow_mut();
+                        state.prompt = read_only_warning(&doc);
+                        state.open_document(doc);
+                    }
+                    self.check_swap(doc_id);
                 }
                 Effect::CloseView => {
                     if self.state.borrow_mut().close_view() {
@@ -118,6 +190,212 @@ impl App {
         }
         Ok(false)
     }
+
+    // ── Swap files ────────────────────────────────────────────────────────────
+
+    /// Look for a swap file left by someone else for a just-opened document.
+    /// A live owner means another instance is editing the file: open it
+    /// read-only. A dead one means a crash: ask what to do with it. Otherwise
+    /// claim the swap file for this instance.
+    fn check_swap(&mut self, doc_id: DocumentId) {
+        let (path, text, revision) = {
+            let mut guard = self.state.borrow_mut();
+            let state = &mut *guard;
+            let Some(doc) = state.documents.get_mut(&doc_id) else {
+                return;
+            };
+            let Some(path) = doc.path.clone() else {
+                return;
+            };
+
+            match swap::read(&self.swap_dir, &path) {
+                Ok(Some(found)) if !found.is_ours() && found.owner_alive() => {
+                    doc.read_only = true;
+                    state.prompt = Some(format!(
+                        "{}: being edited by another gauchito (pid {}), opened read-only",
+                        doc.name(),
+                        found.pid
+                    ));
+                    return;
+                }
+                Ok(Some(found)) if !found.is_ours() && found.text != doc.text.to_string() => {
+                    state.prompt = Some(format!(
+                        "{}: swap file found. [r]ecover, [d]iff against disk, or [x] discard",
+                        doc.name()
+                    ));
+                    self.pending_swap = Some((doc_id, found));
+                    return;
+                }
+                Ok(_) => {}
+                Err(e) => tracing::warn!("reading swap file for {}: {e}", path.display()),
+            }
+
+            if doc.read_only {
+                return;
+            }
+            (path, doc.text.clone(), doc.revision)
+        };
+
+        // Nothing to recover: claim it.
+        self.write_swap(&path, &text, doc_id, revision);
+    }
+
+    /// Act on the key pressed at the swap prompt. Any key other than `r`,
+    /// `d` or `x` asks again.
+    fn resolve_swap(&mut self, doc_id: DocumentId, found: SwapFile, key: &str) {
+        let mut guard = self.state.borrow_mut();
+        let state = &mut *guard;
+        match key {
+            "r" => {
+                // An ordinary edit, so undo goes back to the disk version.
+                // Only what differs is replaced, so selections and marks in
+                // the rest stay put.
+                let text = &state.documents[&doc_id].text;
+                let changes = ChangeSet::from_diff(&text.slice(..), &found.text);
+                gauchito_script::apply_edit(state, doc_id, changes);
+            }
+            "d" => {
+                // Side by side: disk on the left, the swap's text on the right.
+                let mut recovered = Document::from_rope(Rope::from_str(&found.text), None);
+                recovered.options = state.documents[&doc_id].options.clone();
+                recovered.coalesce = self.undo_rules;
+                // Unsaved work, so closing it asks first.
+                recovered.mark_unsaved();
+                state.split_focused(SplitDirection::Vertical);
+                state.open_document(recovered);
+            }
+            "x" => {}
+            _ => {
+                drop(guard);
+                self.pending_swap = Some((doc_id, found));
+                return;
+            }
+        }
+        state.prompt = None;
+
+        let doc = &state.documents[&doc_id];
+        if let Some(path) = doc.path.clone() {
+            let (text, revision) = (doc.text.clone(), doc.revision);
+            drop(guard);
+            self.write_swap(&path, &text, doc_id, revision);
+        }
+    }
+
+    /// Update the swap file of every document edited or renamed since its
+    /// last write.
+    fn write_swaps(&mut self) {
+        if self.pending_swap.is_some() {
+            return;
+        }
+        let dirty: Vec<_> = self
+            .state
+            .borrow()
+            .documents
+            .values()
+            .filter(|doc| !doc.read_only)
+            .filter_map(|doc| Some((doc.path.clone()?, doc.text.clone(), doc.id, doc.revision)))
+            .filter(|(path, _, doc_id, revision)| {
+                self.swapped.get(doc_id) != Some(&(path.clone(), *revision))
+            })
+            .collect();
+
+        for (path, text, doc_id, revision) in dirty {
+            self.write_swap(&path, &text, doc_id, revision);
+        }
+    }
+
+    /// Write the swap file for `path`. One the document had under another
+    /// path (before a save-as) is removed.
+    fn write_swap(&mut self, path: &Path, text: &Rope, doc_id: DocumentId, revision: u64) {
+        if let Err(e) = swap::write(&self.swap_dir, path, text) {
+            tracing::warn!("writing swap file for {}: {e}", path.display());
+            return;
+        }
+        let old = self.swapped.insert(doc_id, (path.to_path_buf(), revision));
+        if let Some((old, _)) = old
+            && old != path
+            && let Err(e) = swap::remove(&self.swap_dir, &old)
+        {
+            tracing::warn!("removing swap file for {}: {e}", old.display());
+        }
+    }
+
+    /// Delete the swap files this instance owns; called on a clean quit.
+    fn remove_swaps(&self) {
+        for (path, _) in self.swapped.values() {
+            if let Err(e) = swap::remove(&self.swap_dir, path) {
+                tracing::warn!("removing swap file for {}: {e}", path.display());
+            }
+        }
+    }
+
+    // ── External changes ──────────────────────────────────────────────────────
+
+    /// Catch files rewritten by other programs. Clean documents reload right
+    /// away; a modified one asks first, one document at a time.
+    fn check_disk(&mut self) {
+        if self.pending_swap.is_some() || self.pending_reload.is_some() {
+            return;
+        }
+        let mut guard = self.state.borrow_mut();
+        let state = &mut *guard;
+        let mut changed: Vec<DocumentId> = state
+            .documents
+            .values()
+            .filter(|doc| fileio::changed_on_disk(doc))
+            .map(|doc| doc.id)
+            .collect();
+        changed.sort_unstable();
+
+        for doc_id in changed {
+            let doc = state.documents.get_mut(&doc_id).unwrap();
+            let Some(path) = doc.path.clone() else {
+                continue;
+            };
+            doc.disk = DiskStamp::of(&path);
+            if doc.disk.is_none() {
+                state.prompt = Some(format!("{}: deleted on disk", doc.name()));
+            } else if doc.is_modified() {
+                state.prompt = Some(format!(
+                    "{}: changed on disk. [r]eload (undo gets your edits back) or [k]eep yours",
+                    doc.name()
+                ));
+                self.pending_reload = Some(doc_id);
+                return;
+            } else if let Err(e) = fileio::reload(doc) {
+                state.prompt = Some(format!("{}: reload failed: {e}", doc.name()));
+            }
+        }
+    }
+
+    /// Act on the key pressed at the reload prompt. `k` keeps the buffer as
+    /// it is; the next save overwrites the file.
+    fn resolve_reload(&mut self, doc_id: DocumentId, key: &str) {
+        let mut guard = self.state.borrow_mut();
+        let state = &mut *guard;
+        let Some(doc) = state.documents.get_mut(&doc_id) else {
+            return;
+        };
+        match key {
+            "r" => {
+                state.prompt = fileio::reload(doc)
+                    .err()
+                    .map(|e| format!("{}: reload failed: {e}", doc.name()));
+            }
+            "k" => state.prompt = None,
+            _ => self.pending_reload = Some(doc_id),
+        }
+    }
+}
+
+/// Shown in the prompt line when a file opens read-only.
+fn read_only_warning(doc: &Document) -> Option<String> {
+    doc.read_only.then(|| {
+        format!(
+            "{}: binary or undecodable file, opened read-only",
+            doc.name()
+        )
+    })
 }
 
 // TODO: move this to its own file or crate
@@ -141,6 +419,7 @@ fn key_event_to_name(code: KeyCode, modifiers: KeyModifiers) -> (String, Option<
         KeyCode::Backspace => ("backspace".to_string(), None),
         KeyCode::Delete => ("del".to_string(), None),
         KeyCode::Tab => ("tab".to_string(), None),
+        KeyCode::BackTab => ("backtab".to_string(), None),
         KeyCode::Left => ("left".to_string(), None),
         KeyCode::Right => ("right".to_string(), None),
         KeyCode::Up => ("up".to_string(), None),
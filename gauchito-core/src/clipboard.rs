@@ -0,0 +1,398 @@
+//! System clipboard: text shared with other programs, behind the `+` and `*`
+//! registers.
+//!
+//! Providers:
+//!
+//! - [`Provider::Command`] runs external programs (`wl-copy` / `wl-paste`,
+//!   `xclip`, `pbcopy` / `pbpaste`, or whatever the config names), handing
+//!   them the text on stdin to copy and reading it from stdout to paste.
+//! - [`Provider::Osc52`] sends the text to the terminal in an OSC 52 escape,
+//!   which also works over ssh. Terminals rarely answer a paste request, so
+//!   pasting gives back what was copied from here last.
+//! - [`Provider::None`] keeps the text inside the editor.
+//!
+//! [`Provider::detect`] picks one from the environment.
+
+use std::fs::OpenOptions;
+use std::io::{self, Read, Write};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::registers::Register;
+
+/// How long a clipboard program gets before it's killed, so a stuck one
+/// (an X server that never answers) doesn't hang the editor.
+const TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Which of the two X11 / Wayland selections: `+` is the clipboard, `*` the
+/// primary selection (what's highlighted, pasted with the middle button).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    Clipboard,
+    Primary,
+}
+
+impl Target {
+    pub fn of_register(name: char) -> Option<Target> {
+        match name {
+            '+' => Some(Target::Clipboard),
+            '*' => Some(Target::Primary),
+            _ => None,
+        }
+    }
+}
+
+/// Programs copying from stdin and pasting to stdout, as argv lists.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Commands {
+    pub copy: Vec<String>,
+    pub paste: Vec<String>,
+    /// For the primary selection. Without them it shares the clipboard.
+    pub primary_copy: Option<Vec<String>>,
+    pub primary_paste: Option<Vec<String>>,
+}
+
+impl Commands {
+    fn copy_for(&self, target: Target) -> &[String] {
+        match (target, &self.primary_copy) {
+            (Target::Primary, Some(argv)) => argv,
+            _ => &self.copy,
+        }
+    }
+
+    fn paste_for(&self, target: Target) -> &[String] {
+        match (target, &self.primary_paste) {
+            (Target::Primary, Some(argv)) => argv,
+            _ => &self.paste,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Provider {
+    #[default]
+    None,
+    Osc52,
+    Command(Commands),
+}
+
+impl Provider {
+    /// The first that fits: wl-clipboard under Wayland, xclip or xsel under
+    /// X11, pbcopy on macOS, and OSC 52 otherwise.
+    pub fn detect() -> Provider {
+        let set = |var: &str| std::env::var_os(var).is_some_and(|v| !v.is_empty());
+        let commands = |copy: &[&str], paste: &[&str], primary: Option<(&[&str], &[&str])>| {
+            Provider::Command(Commands {
+                copy: argv(copy),
+                paste: argv(paste),
+                primary_copy: primary.map(|(copy, _)| argv(copy)),
+                primary_paste: primary.map(|(_, paste)| argv(paste)),
+            })
+        };
+
+        if set("WAYLAND_DISPLAY") && on_path("wl-copy") && on_path("wl-paste") {
+            return commands(
+                &["wl-copy"],
+                &["wl-paste", "--no-newline"],
+                Some((
+                    &["wl-copy", "--primary"],
+                    &["wl-paste", "--no-newline", "--primary"],
+                )),
+            );
+        }
+        if set("DISPLAY") && on_path("xclip") {
+            return commands(
+                &["xclip", "-selection", "clipboard", "-in"],
+                &["xclip", "-selection", "clipboard", "-out"],
+                Some((
+                    &["xclip", "-selection", "primary", "-in"],
+                    &["xclip", "-selection", "primary", "-out"],
+                )),
+            );
+        }
+        if set("DISPLAY") && on_path("xsel") {
+            return commands(
+                &["xsel", "--clipboard", "--input"],
+                &["xsel", "--clipboard", "--output"],
+                Some((
+                    &["xsel", "--primary", "--input"],
+                    &["xsel", "--primary", "--output"],
+                )),
+            );
+        }
+        if on_path("pbcopy") && on_path("pbpaste") {
+            return commands(&["pbcopy"], &["pbpaste"], None);
+        }
+        Provider::Osc52
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Clipboard {
+    provider: Provider,
+    /// Last copied from here, per target. Pasting the same text back gets
+    /// its per-cursor entries again, and providers that can't paste use it.
+    copied: [Option<Register>; 2],
+}
+
+impl Clipboard {
+    pub fn new(provider: Provider) -> Self {
+        Clipboard {
+            provider,
+            copied: [None, None],
+        }
+    }
+
+    pub fn copy(&mut self, target: Target, value: Register) -> io::Result<()> {
+        let text = value.joined();
+        match &self.provider {
+            Provider::None => {}
+            Provider::Osc52 => write_tty(osc52(target, &text).as_bytes()).map_err(failed)?,
+            Provider::Command(commands) => {
+                run_copy(commands.copy_for(target), &text).map_err(failed)?
+            }
+        }
+        self.copied[target as usize] = Some(value);
+        Ok(())
+    }
+
+    /// What `target` holds now, or None when it's empty. Text from other
+    /// programs is one entry, linewise when it ends in a newline.
+    pub fn paste(&self, target: Target) -> io::Result<Option<Register>> {
+        let copied = self.copied[target as usize].as_ref();
+        let text = match &self.provider {
+            Provider::Command(commands) => run_paste(commands.paste_for(target)).map_err(failed)?,
+            _ => return Ok(copied.cloned()),
+        };
+        if let Some(reg) = copied
+            && reg.joined() == text
+        {
+            return Ok(Some(reg.clone()));
+        }
+        if text.is_empty() {
+            return Ok(None);
+        }
+        let linewise = text.ends_with('\n');
+        Ok(Some(Register::new(vec![text], linewise)))
+    }
+}
+
+/// The escape asking the terminal to put `text` on `target`.
+pub fn osc52(target: Target, text: &str) -> String {
+    let which = match target {
+        Target::Clipboard => 'c',
+        Target::Primary => 'p',
+    };
+    format!("\x1b]52;{which};{}\x07", base64(text.as_bytes()))
+}
+
+// ── Internals ───────────────────────────────────────────────────────────────
+
+fn failed(e: io::Error) -> io::Error {
+    io::Error::new(e.kind(), format!("clipboard: {e}"))
+}
+
+fn argv(args: &[&str]) -> Vec<String> {
+    args.iter().map(|a| a.to_string()).collect()
+}
+
+fn on_path(program: &str) -> bool {
+    std::env::var_os("PATH")
+        .is_some_and(|paths| std::env::split_paths(&paths).any(|dir| dir.join(program).is_file()))
+}
+
+/// The controlling terminal, not stdout, so it works while the UI owns it.
+fn write_tty(bytes: &[u8]) -> io::Result<()> {
+    let mut tty = OpenOptions::new().write(true).open("/dev/tty")?;
+    tty.write_all(bytes)?;
+    tty.flush()
+}
+
+fn command(argv: &[String]) -> io::Result<Command> {
+    let (program, args) = argv
+        .split_first()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "empty clipboard command"))?;
+    let mut cmd = Command::new(program);
+    cmd.args(args).stderr(Stdio::null());
+    Ok(cmd)
+}
+
+fn run_copy(argv: &[String], text: &str) -> io::Result<()> {
+    let mut child = command(argv)?
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()?;
+    // Written from a thread so a program that stops reading can't block us
+    // past the timeout. Dropping stdin closes it, so the program sees the
+    // end of the text.
+    let mut stdin = child.stdin.take().unwrap();
+    let bytes = text.as_bytes().to_vec();
+    let writer = thread::spawn(move || stdin.write_all(&bytes));
+    let status = wait(&mut child, TIMEOUT)?;
+    succeeded(argv, status)?;
+    writer.join().unwrap()
+}
+
+fn run_paste(argv: &[String]) -> io::Result<String> {
+    let mut child = command(argv)?
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .spawn()?;
+    let mut stdout = child.stdout.take().unwrap();
+    let reader = thread::spawn(move || {
+        let mut out = Vec::new();
+        stdout.read_to_end(&mut out).map(|_| out)
+    });
+    let status = wait(&mut child, TIMEOUT)?;
+    succeeded(argv, status)?;
+    let out = reader.join().unwrap()?;
+    Ok(String::from_utf8_lossy(&out).into_owned())
+}
+
+/// Wait for `child` to exit, killing it after `timeout`. Either way it's
+/// reaped before returning.
+fn wait(child: &mut Child, timeout: Duration) -> io::Result<ExitStatus> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(status);
+        }
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            child.wait()?;
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!("no answer in {}s", timeout.as_secs_f32()),
+            ));
+        }
+        thread::sleep(Duration::from_millis(5));
+    }
+}
+
+fn succeeded(argv: &[String], status: ExitStatus) -> io::Result<()> {
+    if status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!("{} failed: {status}", argv[0])))
+    }
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// A fresh, empty directory for one test.
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("gauchito-clip-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// A provider whose "clipboard" is a file in `dir`.
+    fn stub(dir: &std::path::Path) -> Provider {
+        let file = dir.join("board").display().to_string();
+        Provider::Command(Commands {
+            copy: argv(&["sh", "-c", &format!("cat > '{file}'")]),
+            paste: argv(&["sh", "-c", &format!("cat '{file}' 2>/dev/null || true")]),
+            primary_copy: None,
+            primary_paste: None,
+        })
+    }
+
+    #[test]
+    fn escape_sequence() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64("añ".as_bytes()), "YcOx");
+        assert_eq!(osc52(Target::Clipboard, "hi"), "\x1b]52;c;aGk=\x07");
+        assert_eq!(osc52(Target::Primary, "hi"), "\x1b]52;p;aGk=\x07");
+    }
+
+    #[test]
+    fn command_round_trip() {
+        let dir = scratch("round-trip");
+        let mut clip = Clipboard::new(stub(&dir));
+        assert_eq!(clip.paste(Target::Clipboard).unwrap(), None);
+
+        let two = Register::new(vec!["one".into(), "two".into()], false);
+        clip.copy(Target::Clipboard, two.clone()).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(dir.join("board")).unwrap(),
+            "one\ntwo"
+        );
+        // Our own text comes back with its entries.
+        assert_eq!(clip.paste(Target::Clipboard).unwrap(), Some(two));
+
+        // Someone else copied a line.
+        std::fs::write(dir.join("board"), "theirs\n").unwrap();
+        assert_eq!(
+            clip.paste(Target::Primary).unwrap(),
+            Some(Register::new(vec!["theirs\n".into()], true))
+        );
+    }
+
+    #[test]
+    fn failing_command() {
+        let mut clip = Clipboard::new(Provider::Command(Commands {
+            copy: argv(&["sh", "-c", "exit 3"]),
+            paste: Vec::new(),
+            primary_copy: None,
+            primary_paste: None,
+        }));
+        let value = Register::new(vec!["x".into()], false);
+        assert!(clip.copy(Target::Clipboard, value).is_err());
+        assert!(clip.paste(Target::Clipboard).is_err());
+    }
+
+    #[test]
+    fn hung_command_is_killed() {
+        let mut child = command(&argv(&["sleep", "10"])).unwrap().spawn().unwrap();
+        let start = Instant::now();
+        let err = wait(&mut child, Duration::from_millis(50)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        assert!(start.elapsed() < Duration::from_secs(5));
+        // Already reaped: nothing left to wait for.
+        assert!(child.try_wait().unwrap().is_some());
+    }
+
+    #[test]
+    fn command_not_reading_its_input() {
+        // More than a pipe holds, to a program that never reads it.
+        let text = "x".repeat(1 << 20);
+        assert!(run_copy(&argv(&["sh", "-c", "exit 1"]), &text).is_err());
+        assert!(run_copy(&argv(&["true"]), &text).is_err());
+    }
+
+    #[test]
+    fn without_a_provider() {
+        let mut clip = Clipboard::new(Provider::None);
+        let value = Register::new(vec!["kept".into()], false);
+        clip.copy(Target::Primary, value.clone()).unwrap();
+        assert_eq!(clip.paste(Target::Primary).unwrap(), Some(value));
+        assert_eq!(clip.paste(Target::Clipboard).unwrap(), None);
+    }
+}
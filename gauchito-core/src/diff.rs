@@ -0,0 +1,363 @@
+//! Minimal edits between two versions of a text.
+//!
+//! [`myers`] is the O(ND) shortest-edit-script algorithm, in its linear
+//! space form, over any sequence of comparable items, returning the replaced
+//! regions as [`Hunk`]s.
+//! [`char_hunks`] runs it over lines, then over the chars of each changed
+//! run of lines, so a whole-text rewrite touches only what really changed
+//! and anchors everywhere else stay put. [`mutations`] and
+//! [`ChangeSet::from_diff`] package the result for the two edit paths.
+
+use std::ops::Range;
+
+use ropey::{Rope, RopeSlice};
+
+use crate::changeset::{ChangeBuilder, ChangeSet};
+use crate::mutation::Mutation;
+
+/// A changed run of lines longer than this, old plus new chars, is replaced
+/// whole instead of diffed char by char.
+const REFINE_LIMIT: usize = 10_000;
+
+/// `old[old]` was replaced by `new[new]`; either side may be empty.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hunk {
+    pub old: Range<usize>,
+    pub new: Range<usize>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Equal,
+    Delete,
+    Insert,
+}
+
+/// The hunks turning `old` into `new`, in order, with a minimal number of
+/// deleted plus inserted items.
+pub fn myers<T: PartialEq>(old: &[T], new: &[T]) -> Vec<Hunk> {
+    // Common ends cost nothing and keep the search small.
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let a = &old[prefix..old.len() - suffix];
+    let b = &new[prefix..new.len() - suffix];
+
+    let mut hunks = Vec::new();
+    let (mut i, mut j) = (prefix, prefix);
+    let mut start: Option<(usize, usize)> = None;
+    for op in edit_script(a, b) {
+        if op == Op::Equal {
+            if let Some((si, sj)) = start.take() {
+                hunks.push(Hunk {
+                    old: si..i,
+                    new: sj..j,
+                });
+            }
+            i += 1;
+            j += 1;
+            continue;
+        }
+        start.get_or_insert((i, j));
+        match op {
+            Op::Delete => i += 1,
+            _ => j += 1,
+        }
+    }
+    if let Some((si, sj)) = start {
+        hunks.push(Hunk {
+            old: si..i,
+            new: sj..j,
+        });
+    }
+    hunks
+}
+
+/// Edit script from `a` to `b`, in linear space: split at the middle of a
+/// shortest path ([`middle_snake`]) and solve the two halves the same way.
+fn edit_script<T: PartialEq>(a: &[T], b: &[T]) -> Vec<Op> {
+    let mut ops = Vec::with_capacity(a.len() + b.len());
+    script_into(a, b, &mut ops);
+    ops
+}
+
+fn script_into<T: PartialEq>(a: &[T], b: &[T], ops: &mut Vec<Op>) {
+    let prefix = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+    let (a, b) = (&a[prefix..], &b[prefix..]);
+    let suffix = a
+        .iter()
+        .rev()
+        .zip(b.iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    let (a, b) = (&a[..a.len() - suffix], &b[..b.len() - suffix]);
+
+    ops.extend((0..prefix).map(|_| Op::Equal));
+    if a.is_empty() {
+        ops.extend(b.iter().map(|_| Op::Insert));
+    } else if b.is_empty() {
+        ops.extend(a.iter().map(|_| Op::Delete));
+    } else {
+        let (x, y) = middle_snake(a, b);
+        script_into(&a[..x], &b[..y], ops);
+        script_into(&a[x..], &b[y..], ops);
+    }
+    ops.extend((0..suffix).map(|_| Op::Equal));
+}
+
+/// A point `(x, y)` on a shortest edit path from `a` to `b`, about halfway
+/// along it: searched from both ends at once until the two searches meet.
+/// `fwd[k]` holds the furthest x reached on diagonal `k = x - y`, `bwd[k]`
+/// the same counted from the ends; only the latest round is kept. Diagonals
+/// that ran off the grid are skipped from then on.
+fn middle_snake<T: PartialEq>(a: &[T], b: &[T]) -> (usize, usize) {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let max = (n + m + 1) / 2;
+    let offset = max + 1;
+    let len = 2 * offset + 1;
+    let mut fwd = vec![-1isize; len as usize];
+    let mut bwd = vec![-1isize; len as usize];
+    fwd[offset as usize + 1] = 0;
+    bwd[offset as usize + 1] = 0;
+    // The searches can only meet on a forward round if this is odd.
+    let delta = n - m;
+    let odd = delta % 2 != 0;
+    // Diagonals trimmed from each end of the range, forward and backward.
+    let (mut fwd_lo, mut fwd_hi, mut bwd_lo, mut bwd_hi) = (0, 0, 0, 0);
+
+    for d in 0..=max {
+        for k in (-d + fwd_lo..=d - fwd_hi).step_by(2) {
+            let i = (k + offset) as usize;
+            let mut x = if k == -d || (k != d && fwd[i - 1] < fwd[i + 1]) {
+                fwd[i + 1]
+            } else {
+                fwd[i - 1] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            fwd[i] = x;
+            if x > n {
+                fwd_hi += 2;
+            } else if y > m {
+                fwd_lo += 2;
+            } else if odd {
+                // Diagonal k as the backward search numbers it.
+                let j = delta - k + offset;
+                if (0..len).contains(&j) && bwd[j as usize] != -1 && x >= n - bwd[j as usize] {
+                    return (x as usize, y as usize);
+                }
+            }
+        }
+        for k in (-d + bwd_lo..=d - bwd_hi).step_by(2) {
+            let i = (k + offset) as usize;
+            let mut x = if k == -d || (k != d && bwd[i - 1] < bwd[i + 1]) {
+                bwd[i + 1]
+            } else {
+                bwd[i - 1] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && a[(n - x - 1) as usize] == b[(m - y - 1) as usize] {
+                x += 1;
+                y += 1;
+            }
+            bwd[i] = x;
+            if x > n {
+                bwd_hi += 2;
+            } else if y > m {
+                bwd_lo += 2;
+            } else if !odd {
+                let j = delta - k + offset;
+                if (0..len).contains(&j) && fwd[j as usize] != -1 && fwd[j as usize] >= n - x {
+                    let fx = fwd[j as usize];
+                    return (fx as usize, (fx - (j - offset)) as usize);
+                }
+            }
+        }
+    }
+    unreachable!("the searches meet within (n + m + 1) / 2 rounds")
+}
+
+/// Char ranges turning `old` into `new`, in order: changed lines first,
+/// then the changed chars within them.
+pub fn char_hunks(old: &RopeSlice, new: &RopeSlice) -> Vec<Hunk> {
+    let old_lines: Vec<RopeSlice> = old.lines().collect();
+    let new_lines: Vec<RopeSlice> = new.lines().collect();
+
+    let mut hunks = Vec::new();
+    for h in myers(&old_lines, &new_lines) {
+        let old_chars = old.line_to_char(h.old.start)..old.line_to_char(h.old.end);
+        let new_chars = new.line_to_char(h.new.start)..new.line_to_char(h.new.end);
+        if old_chars.is_empty()
+            || new_chars.is_empty()
+            || old_chars.len() + new_chars.len() > REFINE_LIMIT
+        {
+            hunks.push(Hunk {
+                old: old_chars,
+                new: new_chars,
+            });
+            continue;
+        }
+
+        let a: Vec<char> = old.slice(old_chars.clone()).chars().collect();
+        let b: Vec<char> = new.slice(new_chars.clone()).chars().collect();
+        hunks.extend(myers(&a, &b).into_iter().map(|c| Hunk {
+            old: old_chars.start + c.old.start..old_chars.start + c.old.end,
+            new: new_chars.start + c.new.start..new_chars.start + c.new.end,
+        }));
+    }
+    hunks
+}
+
+/// Splices turning `old` into `new`, per [`char_hunks`]. They're ordered last
+/// to first, so each one's offsets are still valid when it is applied after
+/// the ones before it.
+pub fn mutations(old: &Rope, new: &Rope) -> Vec<Mutation> {
+    char_hunks(&old.slice(..), &new.slice(..))
+        .into_iter()
+        .rev()
+        .map(|h| Mutation::new(h.old.start, h.old.end, new.slice(h.new).to_string()))
+        .collect()
+}
+
+impl ChangeSet {
+    /// The changeset turning `old` into `new`, per [`char_hunks`]: a
+    /// whole-buffer rewrite (formatter output, a reload) that leaves
+    /// unchanged text, and the anchors in it, alone.
+    pub fn from_diff(old: &RopeSlice, new: &str) -> ChangeSet {
+        let new = Rope::from_str(new);
+        let mut b = ChangeBuilder::new(old.len_chars());
+        for h in char_hunks(old, &new.slice(..)) {
+            b.advance_to(h.old.start);
+            if !h.old.is_empty() {
+                b.delete(h.old.len());
+            }
+            if !h.new.is_empty() {
+                b.insert(&new.slice(h.new).to_string());
+            }
+        }
+        b.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::Document;
+
+    fn apply(old: &str, new: &str) -> (String, usize) {
+        let mut rope = Rope::from_str(old);
+        let mutations = mutations(&rope, &Rope::from_str(new));
+        for m in &mutations {
+            m.apply(&mut rope);
+        }
+        (rope.to_string(), mutations.len())
+    }
+
+    #[test]
+    fn hunks_are_minimal() {
+        let old: Vec<char> = "abcabba".chars().collect();
+        let new: Vec<char> = "cbabac".chars().collect();
+        let hunks = myers(&old, &new);
+        let cost: usize = hunks.iter().map(|h| h.old.len() + h.new.len()).sum();
+        assert_eq!(cost, 5);
+
+        assert_eq!(myers(&[1, 2, 3], &[1, 2, 3]), vec![]);
+        assert_eq!(
+            myers(&[1, 2, 3], &[1, 9, 3]),
+            vec![Hunk {
+                old: 1..2,
+                new: 1..2
+            }]
+        );
+        assert_eq!(
+            myers::<u8>(&[], &[1]),
+            vec![Hunk {
+                old: 0..0,
+                new: 0..1
+            }]
+        );
+    }
+
+    #[test]
+    fn nothing_in_common() {
+        // Every item differs: D is as large as it gets.
+        let old = vec![0u8; 1000];
+        let new = vec![1u8; 800];
+        assert_eq!(
+            myers(&old, &new),
+            vec![Hunk {
+                old: 0..1000,
+                new: 0..800
+            }]
+        );
+    }
+
+    #[test]
+    fn mutations_rebuild_the_new_text() {
+        let cases = [
+            ("a\nb\nc\n", "a\nB\nc\n", 1),
+            ("a\nb\nc\n", "a\nc\n", 1),
+            ("a\nb\nc\n", "x\na\nb\nc\ny\n", 2),
+            ("a\nb", "a\nb\n", 1),
+            ("", "new\n", 1),
+            ("same\n", "same\n", 0),
+        ];
+        for (old, new, count) in cases {
+            assert_eq!(
+                apply(old, new),
+                (new.to_string(), count),
+                "{old:?} → {new:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn only_changed_chars_are_spliced() {
+        let old = Rope::from_str("keep\nchange\nkeep too\n");
+        let new = Rope::from_str("keep\nchanged a lot\nkeep too\n");
+        let mutations = mutations(&old, &new);
+        assert_eq!(mutations.len(), 1);
+        assert_eq!((mutations[0].p(), mutations[0].q()), (11, 11));
+        assert_eq!(mutations[0].t(), "d a lot");
+    }
+
+    #[test]
+    fn from_diff_keeps_anchors_in_unchanged_text() {
+        let mut doc = Document::from_rope(Rope::from_str("one\ntwo\nthree\n"), None);
+        let before = doc.anchors.add(1);
+        let after = doc.anchors.add(10);
+
+        let cs = ChangeSet::from_diff(&doc.text.slice(..), "one\n2\nthree!\n");
+        doc.apply_mutations(&cs);
+        assert_eq!(doc.text.to_string(), "one\n2\nthree!\n");
+        assert_eq!(doc.anchors.offset(before), 1);
+        assert_eq!(doc.anchors.offset(after), 8);
+    }
+
+    #[test]
+    fn char_hunks_are_in_old_and_new_offsets() {
+        let old = Rope::from_str("one\ntwo\nthree\n");
+        let new = Rope::from_str("one\ntwO\nthree!\n");
+        let hunks = char_hunks(&old.slice(..), &new.slice(..));
+        assert_eq!(
+            hunks,
+            vec![
+                Hunk {
+                    old: 6..7,
+                    new: 6..7
+                },
+                Hunk {
+                    old: 13..13,
+                    new: 13..14
+                },
+            ]
+        );
+    }
+}
@@ -3,10 +3,12 @@ use std::path::PathBuf;
 use ropey::Rope;
 
 use crate::anchor::AnchorTable;
-use crate::history::{History, SelectionSnapshot, Transaction};
+use crate::fileio::DiskStamp;
+use crate::history::{Coalesce, History, SelectionSnapshot, Transaction};
 pub use crate::ids::{DocumentId, ViewId};
 use crate::mutation::Mutation;
 use crate::options::{DocumentOptions, PartialDocumentOptions};
+use crate::syntax::{Language, Syntax, SyntaxTree};
 
 pub struct Document {
     pub id: DocumentId,
@@ -14,7 +16,21 @@ pub struct Document {
     pub anchors: AnchorTable,
     pub path: Option<PathBuf>,
     pub options: DocumentOptions,
+    /// Set for files that couldn't be loaded losslessly (binary content,
+    /// undecodable bytes). Edits are refused and `fileio::write` fails.
+    pub read_only: bool,
+    /// The file as of the last load, save or reload; a different stamp on
+    /// disk means something else wrote it.
+    pub disk: Option<DiskStamp>,
     pub revision: u64,
+    pub syntax: Option<Syntax>,
+    /// The `revision` the syntax tree matches. Edits applied to the rope
+    /// directly, rather than through [`Document::apply_mutations`] or
+    /// between [`Document::will_edit`] and [`Document::edited`], move
+    /// `revision` on without it.
+    parsed_at: u64,
+    /// How consecutive edits are grouped into undo steps.
+    pub coalesce: Coalesce,
     history: History,
 }
 
@@ -30,7 +46,12 @@ impl Document {
             anchors: AnchorTable::new(),
             path: None,
             options: options.unwrap_or_default(),
+            read_only: false,
+            disk: None,
             revision: 0,
+            syntax: None,
+            parsed_at: 0,
+            coalesce: Coalesce::default(),
             history: History::new(),
         }
     }
@@ -41,18 +62,111 @@ impl Document {
         self.options = partial.resolve(current);
     }
 
+    /// Attach a grammar and parse the current text. `None` detaches it.
+    pub fn set_language(&mut self, language: Option<Language>) {
+        self.syntax = language.and_then(|l| Syntax::new(l, &self.text));
+        self.parsed_at = self.revision;
+    }
+
+    /// The syntax tree of the current text, parsed again first if the text
+    /// was edited behind its back.
+    pub fn syntax_tree(&mut self) -> Option<&SyntaxTree> {
+        if self.parsed_at != self.revision {
+            if let Some(syntax) = &mut self.syntax {
+                syntax.parse_again(&self.text);
+            }
+            self.parsed_at = self.revision;
+        }
+        self.syntax.as_ref().map(Syntax::tree)
+    }
+
+    /// Apply `atoms` in order to the text, anchors and syntax tree. Returns
+    /// the inverse of each atom, in the same order.
+    pub fn apply_mutations(&mut self, atoms: &[Mutation]) -> Vec<Mutation> {
+        // Incremental edits only make sense on a tree that matches the text.
+        self.syntax_tree();
+        let inverses = atoms.iter().map(|atom| self.apply_atom(atom)).collect();
+
+        if let Some(syntax) = &mut self.syntax {
+            syntax.reparse(&self.text);
+        }
+        self.parsed_at = self.revision;
+
+        inverses
+    }
+
+    /// Tell the syntax tree about `atoms`, which something other than
+    /// [`Document::apply_mutations`] (`EditorState::apply_edit`) is about to
+    /// apply to the text. Pass what this returns to [`Document::edited`]
+    /// once they are.
+    pub fn will_edit(&mut self, atoms: &[Mutation]) -> u64 {
+        self.syntax_tree();
+        if let Some(syntax) = &mut self.syntax {
+            // Each atom is relative to the text the ones before it leave.
+            let mut text = self.text.clone();
+            for atom in atoms {
+                syntax.edit(&text, atom);
+                atom.apply(&mut text);
+            }
+        }
+        self.revision
+    }
+
+    /// The atoms given to [`Document::will_edit`], which returned `before`,
+    /// were applied: reparse what they touched.
+    pub fn edited(&mut self, before: u64) {
+        if let Some(syntax) = &mut self.syntax {
+            if self.revision == before {
+                // Refused (a read-only document): the tree was told of
+                // edits the text never got.
+                syntax.parse_again(&self.text);
+            } else {
+                syntax.reparse(&self.text);
+            }
+        }
+        self.parsed_at = self.revision;
+    }
+
+    fn apply_atom(&mut self, atom: &Mutation) -> Mutation {
+        // The tree edit is computed against the pre-splice text.
+        if let Some(syntax) = &mut self.syntax {
+            syntax.edit(&self.text, atom);
+        }
+
+        let inverse = atom.apply(&mut self.text);
+        self.anchors.apply_atom(atom);
+        self.revision += 1;
+
+        inverse
+    }
+
+    pub fn history(&self) -> &History {
+        &self.history
+    }
+
+    /// Replace the undo history, e.g. with one restored from an undo file.
+    /// It must have been recorded against the current text.
+    pub fn set_history(&mut self, history: History) {
+        self.history = history;
+    }
+
+    /// Record an edit, merged into the previous undo step when
+    /// `self.coalesce` allows.
     pub fn commit(&mut self, transaction: Transaction) {
-        self.history.commit(transaction);
+        self.history.commit_coalescing(transaction, &self.coalesce);
+    }
+
+    /// Make the next edit start a new undo step. Explicit transactions are
+    /// sealed on both sides so they never merge with what's around them.
+    pub fn seal_history(&mut self) {
+        self.history.seal();
     }
 
     pub fn undo(&mut self) -> Option<(Vec<Mutation>, Option<SelectionSnapshot>)> {
         let (inverses, snap) = self.history.undo()?;
 
-        for atom in inverses.iter().rev() {
-            atom.apply(&mut self.text);
-            self.anchors.apply_atom(atom);
-            self.revision += 1;
-        }
+        let reversed: Vec<Mutation> = inverses.iter().rev().cloned().collect();
+        self.apply_mutations(&reversed);
 
         Some((inverses, snap))
     }
@@ -60,15 +174,39 @@ impl Document {
     pub fn redo(&mut self) -> Option<(Vec<Mutation>, Option<SelectionSnapshot>)> {
         let (atoms, snap) = self.history.redo()?;
 
-        for atom in &atoms {
-            atom.apply(&mut self.text);
-            self.anchors.apply_atom(atom);
-            self.revision += 1;
-        }
+        self.apply_mutations(&atoms);
 
         Some((atoms, snap))
     }
 
+    /// Jump to any revision of the undo tree (see [`History::goto`]).
+    pub fn goto_revision(
+        &mut self,
+        revision: usize,
+    ) -> Option<(Vec<Mutation>, Option<SelectionSnapshot>)> {
+        let (atoms, snap) = self.history.goto(revision)?;
+
+        self.apply_mutations(&atoms);
+
+        Some((atoms, snap))
+    }
+
+    /// Record a save at the current revision.
+    pub fn mark_saved(&mut self) {
+        self.history.mark_saved();
+    }
+
+    /// Count as modified until the next save, whatever undo does.
+    pub fn mark_unsaved(&mut self) {
+        self.history.mark_unsaved();
+    }
+
+    /// The text differs from what was last saved (or loaded), judged by
+    /// position in the undo tree rather than by comparing text.
+    pub fn is_modified(&self) -> bool {
+        self.history.is_modified()
+    }
+
     pub fn name(&self) -> &str {
         self.path
             .as_ref()
@@ -77,3 +215,62 @@ impl Document {
             .unwrap_or("scratch")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sexp(doc: &mut Document) -> String {
+        doc.syntax_tree().unwrap().root().to_sexp()
+    }
+
+    #[test]
+    fn tree_follows_edits_made_behind_its_back() {
+        let mut doc = Document::from_rope(Rope::from_str("fn a() {}\nfn b() {}\n"), None);
+        doc.set_language(Some(Language::Rust));
+
+        // What an editor applying a change straight to the rope does.
+        let cut = Mutation::new(3, 20, String::new());
+        cut.apply(&mut doc.text);
+        doc.anchors.apply_atom(&cut);
+        doc.revision += 1;
+
+        let fresh = Syntax::new(Language::Rust, &doc.text).unwrap();
+        assert_eq!(sexp(&mut doc), fresh.tree().root().to_sexp());
+
+        // And incremental edits pick up from the fresh tree.
+        doc.apply_mutations(&[Mutation::new(3, 3, "c() {}".into())]);
+        let fresh = Syntax::new(Language::Rust, &doc.text).unwrap();
+        assert_eq!(doc.text.to_string(), "fn c() {}");
+        assert_eq!(sexp(&mut doc), fresh.tree().root().to_sexp());
+    }
+
+    #[test]
+    fn tree_follows_edits_announced_first() {
+        let mut doc = Document::from_rope(Rope::from_str("fn a() {}\n"), None);
+        doc.set_language(Some(Language::Rust));
+
+        // Two atoms, the second relative to what the first leaves.
+        let atoms = [
+            Mutation::new(3, 4, "bc".into()),
+            Mutation::new(10, 10, "\nfn d() {}".into()),
+        ];
+        let before = doc.will_edit(&atoms);
+        for atom in &atoms {
+            atom.apply(&mut doc.text);
+            doc.revision += 1;
+        }
+        doc.edited(before);
+        assert_eq!(doc.parsed_at, doc.revision);
+
+        let fresh = Syntax::new(Language::Rust, &doc.text).unwrap();
+        assert_eq!(doc.text.to_string(), "fn bc() {}\nfn d() {}\n");
+        assert_eq!(sexp(&mut doc), fresh.tree().root().to_sexp());
+
+        // Announced but never applied.
+        let before = doc.will_edit(&[Mutation::new(0, 11, String::new())]);
+        doc.edited(before);
+        let fresh = Syntax::new(Language::Rust, &doc.text).unwrap();
+        assert_eq!(sexp(&mut doc), fresh.tree().root().to_sexp());
+    }
+}
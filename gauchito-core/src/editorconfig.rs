@@ -1,7 +1,11 @@
-// TODO: todo el flujo de lang config / editorconfig / infer / standarization on load no se hace
 use std::path::Path;
 
-use ec4rs::property::{EndOfLine, FinalNewline, TrimTrailingWs};
+use ec4rs::property::{
+    Charset, EndOfLine, FinalNewline, IndentSize, IndentStyle as EcIndentStyle,
+    MaxLineLen, TabWidth, TrimTrailingWs,
+};
+use encoding_rs::{Encoding, UTF_8, UTF_16BE, UTF_16LE, WINDOWS_1252};
+
 use crate::options::LineEnding;
 
 /// Overrides from `.editorconfig`. `None` means "not specified, use sniffed value".
@@ -10,6 +14,16 @@ pub struct EditorConfigRules {
     pub line_ending: Option<LineEnding>,
     pub final_newline: Option<bool>,
     pub trim_trailing_whitespace: Option<bool>,
+    /// `indent_style`: `Some(true)` for tabs, `Some(false)` for spaces.
+    pub indent_tabs: Option<bool>,
+    /// `indent_size`, with `tab` already resolved to `tab_width`.
+    pub indent_size: Option<usize>,
+    pub tab_width: Option<usize>,
+    /// `charset`, used when the file has no BOM.
+    pub encoding: Option<&'static Encoding>,
+    /// `charset = utf-8-bom` forces a BOM; plain `utf-8` forbids one.
+    pub bom: Option<bool>,
+    pub max_line_length: Option<usize>,
 }
 
 /// Read `.editorconfig` files for the given path and convert to our rules type.
@@ -34,9 +48,50 @@ pub fn rules_for(path: &Path) -> EditorConfigRules {
         .ok()
         .map(|TrimTrailingWs::Value(b)| b);
 
+    let indent_tabs = props
+        .get::<EcIndentStyle>()
+        .ok()
+        .map(|style| style == EcIndentStyle::Tabs);
+
+    let tab_width = props
+        .get::<TabWidth>()
+        .ok()
+        .map(|TabWidth::Value(n)| n)
+        .filter(|&n| n > 0);
+
+    let indent_size = props
+        .get::<IndentSize>()
+        .ok()
+        .and_then(|size| match size {
+            IndentSize::Value(n) => Some(n),
+            IndentSize::UseTabWidth => tab_width,
+        })
+        .filter(|&n| n > 0);
+
+    // encoding_rs reads `latin1` as windows-1252, its superset.
+    let (encoding, bom) = match props.get::<Charset>().ok() {
+        Some(Charset::Utf8) => (Some(UTF_8), Some(false)),
+        Some(Charset::Utf8Bom) => (Some(UTF_8), Some(true)),
+        Some(Charset::Latin1) => (Some(WINDOWS_1252), Some(false)),
+        Some(Charset::Utf16Le) => (Some(UTF_16LE), None),
+        Some(Charset::Utf16Be) => (Some(UTF_16BE), None),
+        None => (None, None),
+    };
+
+    let max_line_length = props.get::<MaxLineLen>().ok().and_then(|len| match len {
+        MaxLineLen::Value(n) => Some(n),
+        MaxLineLen::Off => None,
+    });
+
     EditorConfigRules {
         line_ending,
         final_newline,
         trim_trailing_whitespace,
+        indent_tabs,
+        indent_size,
+        tab_width,
+        encoding,
+        bom,
+        max_line_length,
     }
 }
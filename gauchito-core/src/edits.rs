@@ -9,6 +9,8 @@
 use ropey::RopeSlice;
 
 use crate::changeset::{ChangeBuilder, ChangeSet};
+use crate::indent;
+use crate::syntax::Language;
 
 /// Insert a single character at every head without replacing the selection.
 pub fn insert_char(text: &RopeSlice, heads: &[usize], ch: char) -> ChangeSet {
@@ -28,6 +30,155 @@ pub fn insert_char(text: &RopeSlice, heads: &[usize], ch: char) -> ChangeSet {
     b.finish()
 }
 
+/// Insert a newline at every head, indenting the new line per
+/// [`indent::newline_indent`]. `unit` is one indent level.
+pub fn insert_newline_indent(
+    text: &RopeSlice,
+    heads: &[usize],
+    language: Option<Language>,
+    unit: &str,
+) -> ChangeSet {
+    let doc_len = text.len_chars();
+
+    let mut positions: Vec<usize> = heads.to_vec();
+    positions.sort();
+    positions.dedup();
+
+    let mut b = ChangeBuilder::new(doc_len);
+    for &pos in &positions {
+        let indent = indent::newline_indent(text, pos, language, unit);
+        b.advance_to(pos);
+        b.insert(&format!("\n{indent}"));
+    }
+
+    b.finish()
+}
+
+/// Open a line above every head's line, indented like that line. Heads on
+/// it stay past the new line, so one step left is the end of its indentation.
+pub fn insert_line_above_indent(text: &RopeSlice, heads: &[usize]) -> ChangeSet {
+    let doc_len = text.len_chars();
+
+    let mut lines: Vec<usize> = heads.iter().map(|&h| text.char_to_line(h)).collect();
+    lines.sort();
+    lines.dedup();
+
+    let mut b = ChangeBuilder::new(doc_len);
+    for &line in &lines {
+        b.advance_to(text.line_to_char(line));
+        b.insert(&format!("{}\n", indent::line_indent(text, line)));
+    }
+
+    b.finish()
+}
+
+/// Insert `ch` at every head. A closing bracket typed where only
+/// indentation precedes the head also drops that indentation by one level
+/// (see [`indent::dedent`]).
+pub fn insert_char_indent(
+    text: &RopeSlice,
+    heads: &[usize],
+    ch: char,
+    unit: &str,
+    tab_width: usize,
+) -> ChangeSet {
+    if !indent::is_closing_bracket(ch) {
+        return insert_char(text, heads, ch);
+    }
+
+    let doc_len = text.len_chars();
+
+    let mut positions: Vec<usize> = heads.to_vec();
+    positions.sort();
+    positions.dedup();
+
+    let mut b = ChangeBuilder::new(doc_len);
+    let mut done = 0;
+    for &pos in &positions {
+        let line_start = text.line_to_char(text.char_to_line(pos));
+        let before: String = text.slice(line_start..pos).chars().collect();
+        let after = indent::dedent(&before, unit, tab_width);
+        let same = common_prefix(&before, &after);
+        let from = line_start + same;
+
+        // Another cursor earlier on the same line already claimed the prefix.
+        if after != before && from >= done && before.chars().all(|c| c == ' ' || c == '\t') {
+            b.advance_to(from);
+            b.delete(pos - from);
+            b.insert(&format!("{}{ch}", &after[same..]));
+        } else {
+            b.advance_to(pos);
+            b.insert(&ch.to_string());
+        }
+        done = pos;
+    }
+
+    b.finish()
+}
+
+/// Length of the prefix `a` and `b` share, in chars. Indentation is ASCII,
+/// so that's bytes too.
+fn common_prefix(a: &str, b: &str) -> usize {
+    a.chars().zip(b.chars()).take_while(|(x, y)| x == y).count()
+}
+
+/// Lines touched by any of `ranges`, sorted and deduplicated. A range that
+/// ends at a line start doesn't touch that line; a collapsed one touches its own.
+fn touched_lines(text: &RopeSlice, ranges: &[(usize, usize)]) -> Vec<usize> {
+    let mut lines: Vec<usize> = ranges
+        .iter()
+        .flat_map(|&(from, to)| {
+            let last = if to > from { to - 1 } else { to };
+            text.char_to_line(from)..=text.char_to_line(last)
+        })
+        .collect();
+    lines.sort();
+    lines.dedup();
+    lines
+}
+
+/// Add one indent level to every line touched by `ranges`. Blank lines are
+/// left alone.
+pub fn indent_lines(text: &RopeSlice, ranges: &[(usize, usize)], unit: &str) -> ChangeSet {
+    let mut b = ChangeBuilder::new(text.len_chars());
+    for line in touched_lines(text, ranges) {
+        let blank = text.line(line).chars().all(|c| c == '\n' || c == '\r');
+        if blank {
+            continue;
+        }
+        b.advance_to(text.line_to_char(line));
+        b.insert(unit);
+    }
+
+    b.finish()
+}
+
+/// Remove one indent level (see [`indent::dedent`]) from every line touched
+/// by `ranges`.
+pub fn dedent_lines(
+    text: &RopeSlice,
+    ranges: &[(usize, usize)],
+    unit: &str,
+    tab_width: usize,
+) -> ChangeSet {
+    let mut b = ChangeBuilder::new(text.len_chars());
+    for line in touched_lines(text, ranges) {
+        let current = indent::line_indent(text, line);
+        let after = indent::dedent(&current, unit, tab_width);
+        if after == current {
+            continue;
+        }
+        let same = common_prefix(&current, &after);
+        b.advance_to(text.line_to_char(line) + same);
+        b.delete(current.len() - same);
+        if same < after.len() {
+            b.insert(&after[same..]);
+        }
+    }
+
+    b.finish()
+}
+
 /// Delete one character backward at each cursor, or the range if non-empty.
 /// `ranges` are `(from, to)` half-open intervals; collapsed ranges (`from == to`)
 /// trigger a one-char backspace at `from`.
@@ -110,3 +261,98 @@ pub fn delete_selection(text: &RopeSlice, ranges: &[(usize, usize)]) -> ChangeSe
 
     b.finish()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::Document;
+    use ropey::Rope;
+
+    /// Run `kernel` over `text` and apply what it returns.
+    fn run(text: &str, kernel: impl FnOnce(&RopeSlice) -> ChangeSet) -> String {
+        let mut doc = Document::from_rope(Rope::from_str(text), None);
+        let cs = kernel(&doc.text.slice(..));
+        doc.apply_mutations(&cs);
+        doc.text.to_string()
+    }
+
+    fn typed(text: &str, heads: &[usize], ch: char) -> String {
+        run(text, |t| insert_char_indent(t, heads, ch, "    ", 4))
+    }
+
+    #[test]
+    fn newline_indent_at_every_head() {
+        let out = run("fn f() {\n    x\n}", |t| {
+            insert_newline_indent(t, &[14, 8, 14], None, "    ")
+        });
+        assert_eq!(out, "fn f() {\n    \n    x\n    \n}");
+    }
+
+    #[test]
+    fn line_above_each_head() {
+        let out = run("a\n    b\n\tc", |t| {
+            insert_line_above_indent(t, &[1, 6, 8, 10])
+        });
+        assert_eq!(out, "\na\n    \n    b\n\t\n\tc");
+    }
+
+    #[test]
+    fn closing_bracket_drops_a_level() {
+        assert_eq!(typed("    ", &[4], '}'), "}");
+        assert_eq!(typed("    \n\t\t", &[4, 7], ']'), "]\n\t]");
+        // Only after nothing but indentation.
+        assert_eq!(typed("  x", &[3], ')'), "  x)");
+        // Other characters are plain inserts.
+        assert_eq!(typed("    ", &[4], 'x'), "    x");
+    }
+
+    #[test]
+    fn closing_bracket_cursors_share_a_line() {
+        // The second cursor's level overlaps what the first removed, so it
+        // only inserts.
+        assert_eq!(typed("        ", &[6, 8], '}'), "  }  }");
+        // Levels that don't overlap are each removed.
+        assert_eq!(typed("        ", &[4, 8], '}'), "}}");
+    }
+
+    #[test]
+    fn closing_bracket_measures_tabs() {
+        let out = run("\t", |t| insert_char_indent(t, &[1], '}', "  ", 4));
+        assert_eq!(out, "  }");
+    }
+
+    #[test]
+    fn indent_touched_lines() {
+        let text = "a\n\nb\nc\n";
+        // Blank lines stay blank.
+        assert_eq!(
+            run(text, |t| indent_lines(t, &[(0, 4)], "  ")),
+            "  a\n\n  b\nc\n"
+        );
+        // Ending at a line start leaves that line alone; cursors on one line
+        // shift it once.
+        assert_eq!(
+            run(text, |t| indent_lines(t, &[(0, 2), (5, 5), (6, 6)], "  ")),
+            "  a\n\nb\n  c\n"
+        );
+    }
+
+    #[test]
+    fn dedent_touched_lines() {
+        let text = "        a\n\tb\n  c\nd\n";
+        let all = [(0, text.len())];
+        assert_eq!(
+            run(text, |t| dedent_lines(t, &all, "    ", 4)),
+            "    a\nb\nc\nd\n"
+        );
+        assert_eq!(
+            run(text, |t| dedent_lines(t, &[(1, 1), (3, 3)], "    ", 4)),
+            "    a\n\tb\n  c\nd\n"
+        );
+        // A tab wider than a level keeps the columns left over.
+        assert_eq!(
+            run("\tx\n", |t| dedent_lines(t, &[(0, 0)], "  ", 4)),
+            "  x\n"
+        );
+    }
+}
@@ -0,0 +1,238 @@
+//! Highlight spans from tree-sitter highlight queries.
+//!
+//! Each bundled grammar ships a `highlights.scm`; its captures (`@keyword`,
+//! `@function.method`, …) name what a span *is*, and a [`Theme`] decides how
+//! it looks. [`highlights`] only runs the query over a byte range derived from
+//! the requested lines, so cost tracks the viewport, not the file.
+//!
+//! Captures overlap (`@variable` on an identifier that's also `@function`).
+//! Spans are flattened so the innermost node wins, and for the same node the
+//! earliest pattern in the query wins — the convention the upstream queries
+//! are written for.
+//!
+//! [`Theme`]: crate::theme::Theme
+
+use std::collections::HashSet;
+use std::ops::Range;
+use std::sync::OnceLock;
+
+use ropey::Rope;
+use streaming_iterator::StreamingIterator;
+use tree_sitter::{Node, Query, QueryCursor};
+
+use crate::syntax::{Language, SyntaxTree};
+use crate::theme::{Style, Theme};
+
+/// A highlighted half-open char range and the capture name that produced it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Highlight {
+    pub from: usize,
+    pub to: usize,
+    pub name: &'static str,
+}
+
+impl Language {
+    fn highlights_source(self) -> &'static str {
+        match self {
+            Language::Rust => tree_sitter_rust::HIGHLIGHTS_QUERY,
+            Language::Lua => tree_sitter_lua::HIGHLIGHTS_QUERY,
+            Language::Toml => tree_sitter_toml_ng::HIGHLIGHTS_QUERY,
+            Language::Markdown => tree_sitter_md::HIGHLIGHT_QUERY_BLOCK,
+        }
+    }
+
+    /// The compiled highlight query, built once per language.
+    fn highlights_query(self) -> Option<&'static Query> {
+        static RUST: OnceLock<Option<Query>> = OnceLock::new();
+        static LUA: OnceLock<Option<Query>> = OnceLock::new();
+        static TOML: OnceLock<Option<Query>> = OnceLock::new();
+        static MARKDOWN: OnceLock<Option<Query>> = OnceLock::new();
+
+        let cell = match self {
+            Language::Rust => &RUST,
+            Language::Lua => &LUA,
+            Language::Toml => &TOML,
+            Language::Markdown => &MARKDOWN,
+        };
+
+        cell.get_or_init(|| Query::new(&self.grammar(), self.highlights_source()).ok())
+            .as_ref()
+    }
+}
+
+/// Highlight spans for `lines` (a half-open line range), sorted and
+/// non-overlapping. Unhighlighted text has no span.
+pub fn highlights(tree: &SyntaxTree, text: &Rope, lines: Range<usize>) -> Vec<Highlight> {
+    let Some(query) = tree.language.highlights_query() else {
+        return Vec::new();
+    };
+
+    let first = lines.start.min(text.len_lines());
+    let last = lines.end.clamp(first, text.len_lines());
+    let start_byte = text.line_to_byte(first);
+    let end_byte = text.line_to_byte(last);
+    if start_byte >= end_byte {
+        return Vec::new();
+    }
+
+    // (start, end, node id, pattern, capture) for every capture touching the range.
+    let mut captures = Vec::new();
+    let mut cursor = QueryCursor::new();
+    cursor.set_byte_range(start_byte..end_byte);
+
+    let provider = |node: Node| {
+        text.byte_slice(node.byte_range())
+            .chunks()
+            .map(str::as_bytes)
+    };
+    let mut matches = cursor.captures(query, tree.root(), provider);
+    while let Some((m, index)) = matches.next() {
+        let capture = m.captures[*index];
+        let node = capture.node;
+        captures.push((
+            node.start_byte(),
+            node.end_byte(),
+            node.id(),
+            m.pattern_index,
+            capture.index as usize,
+        ));
+    }
+
+    // Outer nodes first so inner ones paint over them; for one node the
+    // first pattern claims it.
+    captures.sort_by(|a, b| a.0.cmp(&b.0).then(b.1.cmp(&a.1)).then(a.3.cmp(&b.3)));
+
+    let names = query.capture_names();
+    let mut paint: Vec<Option<&'static str>> = vec![None; end_byte - start_byte];
+    let mut claimed = HashSet::new();
+
+    for (start, end, id, _, capture) in captures {
+        if !claimed.insert(id) {
+            continue;
+        }
+
+        let from = start.max(start_byte) - start_byte;
+        let to = end.min(end_byte) - start_byte;
+        for slot in paint.iter_mut().take(to).skip(from) {
+            *slot = Some(names[capture]);
+        }
+    }
+
+    let mut spans: Vec<Highlight> = Vec::new();
+    let mut i = 0;
+    while i < paint.len() {
+        let Some(name) = paint[i] else {
+            i += 1;
+            continue;
+        };
+        let mut j = i + 1;
+        while j < paint.len() && paint[j] == Some(name) {
+            j += 1;
+        }
+        spans.push(Highlight {
+            from: text.byte_to_char(start_byte + i),
+            to: text.byte_to_char(start_byte + j),
+            name,
+        });
+        i = j;
+    }
+
+    spans
+}
+
+/// [`highlights`] for `lines` with each span's style looked up in `theme`:
+/// what a renderer paints over the plain text. Spans the theme has no style
+/// for are left out.
+pub fn styled(
+    tree: &SyntaxTree,
+    text: &Rope,
+    lines: Range<usize>,
+    theme: &Theme,
+) -> Vec<(Range<usize>, Style)> {
+    highlights(tree, text, lines)
+        .into_iter()
+        .filter_map(|h| Some((h.from..h.to, *theme.get(h.name)?)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::syntax::Syntax;
+
+    fn spans(lang: Language, src: &str, lines: Range<usize>) -> Vec<(String, &'static str)> {
+        let text = Rope::from_str(src);
+        let syntax = Syntax::new(lang, &text).unwrap();
+        highlights(syntax.tree(), &text, lines)
+            .into_iter()
+            .map(|h| (text.slice(h.from..h.to).to_string(), h.name))
+            .collect()
+    }
+
+    #[test]
+    fn rust_keywords_and_functions() {
+        let s = spans(Language::Rust, "fn main() {}\n", 0..1);
+        assert!(s.contains(&("fn".into(), "keyword")));
+        assert!(s.contains(&("main".into(), "function")));
+    }
+
+    #[test]
+    fn innermost_capture_wins() {
+        // The escape sequence paints over the string literal around it.
+        let s = spans(Language::Rust, "const S: &str = \"a\\nb\";\n", 0..1);
+        assert!(s.contains(&("\\n".into(), "escape")));
+        assert!(s.contains(&("\"a".into(), "string")));
+    }
+
+    #[test]
+    fn first_pattern_wins_for_one_node() {
+        // `Foo` matches both the uppercase-constructor and call patterns.
+        let s = spans(Language::Rust, "fn f() { Foo(1); }\n", 0..1);
+        assert!(s.contains(&("Foo".into(), "constructor")));
+    }
+
+    #[test]
+    fn only_requested_lines() {
+        let s = spans(Language::Rust, "fn a() {}\nfn b() {}\n", 1..2);
+        assert!(s.contains(&("b".into(), "function")));
+        assert!(!s.iter().any(|(t, _)| t == "a"));
+    }
+
+    #[test]
+    fn spans_are_sorted_and_disjoint() {
+        let text = Rope::from_str("let x = \"héllo\"; // done\n");
+        let syntax = Syntax::new(Language::Rust, &text).unwrap();
+        let spans = highlights(syntax.tree(), &text, 0..1);
+        assert!(!spans.is_empty());
+        for pair in spans.windows(2) {
+            assert!(pair[0].to <= pair[1].from);
+        }
+        assert!(spans.iter().any(|h| h.name == "string"));
+    }
+
+    #[test]
+    fn toml_and_markdown_queries_compile() {
+        assert!(!spans(Language::Toml, "[a]\nk = 1\n", 0..2).is_empty());
+        assert!(!spans(Language::Markdown, "# Title\n", 0..1).is_empty());
+    }
+
+    #[test]
+    fn styled_spans_use_the_theme() {
+        let text = Rope::from_str("fn main() {}\n");
+        let syntax = Syntax::new(Language::Rust, &text).unwrap();
+        let bold = Style {
+            bold: true,
+            ..Style::default()
+        };
+        let mut theme = Theme::new();
+        theme.set("keyword", bold);
+
+        let spans = styled(syntax.tree(), &text, 0..1, &theme);
+        assert_eq!(spans, vec![(0..2, bold)]);
+    }
+
+    #[test]
+    fn empty_range() {
+        assert!(spans(Language::Rust, "fn a() {}\n", 5..9).is_empty());
+    }
+}
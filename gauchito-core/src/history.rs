@@ -1,11 +1,16 @@
+use std::time::{Duration, SystemTime};
+
+use serde::{Deserialize, Serialize};
+
 use crate::mutation::Mutation;
 
-#[derive(Clone)]
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct SelectionSnapshot {
     pub ranges: Vec<(usize, usize)>,
     pub primary: usize,
 }
 
+#[derive(Serialize, Deserialize)]
 pub struct Transaction {
     pub mutations: Vec<Mutation>,
     pub inverses: Vec<Mutation>,
@@ -13,15 +18,104 @@ pub struct Transaction {
     pub selection_after: Option<SelectionSnapshot>,
 }
 
+#[derive(Serialize, Deserialize)]
 struct Revision {
     parent: usize,
     last: Option<usize>,
     txn: Transaction,
+    timestamp: SystemTime,
+    saved: bool,
+}
+
+/// One revision as seen from outside the history.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RevisionInfo {
+    pub id: usize,
+    /// `None` for the root.
+    pub parent: Option<usize>,
+    pub timestamp: SystemTime,
+    /// The document was saved at this revision.
+    pub saved: bool,
+}
+
+/// A row of [`History::tree`]: the revision and the graph drawn left of it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TreeRow {
+    pub id: usize,
+    pub graph: String,
+}
+
+/// When [`History::commit_coalescing`] folds an edit into the previous undo
+/// step instead of starting a new one. Either way the edit must come within
+/// `idle` of the previous one; typing and deleting runs also need the
+/// selection left where the previous edit put it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Coalesce {
+    /// Runs of pure insertions merge.
+    pub typing: bool,
+    /// Runs of pure deletions (backspace, delete) merge.
+    pub deleting: bool,
+    /// Any edits merge, whatever they are, until a pause.
+    pub bursts: bool,
+    /// A pause this long always starts a new step.
+    pub idle: Duration,
+}
+
+impl Coalesce {
+    /// Every edit is its own step.
+    pub const NEVER: Coalesce = Coalesce {
+        typing: false,
+        deleting: false,
+        bursts: false,
+        idle: Duration::ZERO,
+    };
 }
 
+impl Default for Coalesce {
+    fn default() -> Self {
+        Coalesce {
+            typing: true,
+            deleting: true,
+            bursts: false,
+            idle: Duration::from_secs(1),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum EditKind {
+    Insert,
+    Delete,
+    Other,
+}
+
+impl EditKind {
+    fn of(txn: &Transaction) -> EditKind {
+        let ms = &txn.mutations;
+        if ms.is_empty() {
+            EditKind::Other
+        } else if ms.iter().all(|m| m.p() == m.q() && !m.t().is_empty()) {
+            EditKind::Insert
+        } else if ms.iter().all(|m| m.p() < m.q() && m.t().is_empty()) {
+            EditKind::Delete
+        } else {
+            EditKind::Other
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
 pub struct History {
     revisions: Vec<Revision>,
     current: usize,
+    /// The current revision came from `commit_coalescing` and may still
+    /// absorb the next edit. Anything else that touches the history closes it.
+    #[serde(skip)]
+    open: bool,
+    /// The revision last written to disk; `None` when none of them was.
+    /// Not persisted: loading marks whatever revision matches the file.
+    #[serde(skip)]
+    saved_at: Option<usize>,
 }
 
 impl History {
@@ -36,11 +130,16 @@ impl History {
                     selection_before: None,
                     selection_after: None,
                 },
+                timestamp: SystemTime::now(),
+                saved: false,
             }],
             current: 0,
+            open: false,
+            saved_at: Some(0),
         }
     }
 
+    /// Commit `txn` as its own undo step.
     pub fn commit(&mut self, txn: Transaction) {
         let id = self.revisions.len();
 
@@ -50,9 +149,53 @@ impl History {
             parent: self.current,
             last: None,
             txn,
+            timestamp: SystemTime::now(),
+            saved: false,
         });
 
         self.current = id;
+        self.open = false;
+    }
+
+    /// Commit `txn`, folding it into the current revision when `rules` allow
+    /// (see [`Coalesce`]).
+    pub fn commit_coalescing(&mut self, txn: Transaction, rules: &Coalesce) {
+        if !self.open || !self.absorbs(&txn, rules) {
+            self.commit(txn);
+            self.open = true;
+            return;
+        }
+
+        let revision = &mut self.revisions[self.current];
+        revision.txn.mutations.extend(txn.mutations);
+        revision.txn.inverses.extend(txn.inverses);
+        revision.txn.selection_after = txn.selection_after;
+        revision.timestamp = SystemTime::now();
+    }
+
+    /// Start a new undo step with the next edit, whatever the rules say.
+    pub fn seal(&mut self) {
+        self.open = false;
+    }
+
+    fn absorbs(&self, txn: &Transaction, rules: &Coalesce) -> bool {
+        let prev = &self.revisions[self.current];
+        let recent = prev.timestamp.elapsed().is_ok_and(|e| e < rules.idle);
+        if !recent {
+            return false;
+        }
+        if rules.bursts {
+            return true;
+        }
+
+        let continues =
+            txn.selection_before.is_some() && txn.selection_before == prev.txn.selection_after;
+        continues
+            && match (EditKind::of(&prev.txn), EditKind::of(txn)) {
+                (EditKind::Insert, EditKind::Insert) => rules.typing,
+                (EditKind::Delete, EditKind::Delete) => rules.deleting,
+                _ => false,
+            }
     }
 
     pub fn undo(&mut self) -> Option<(Vec<Mutation>, Option<SelectionSnapshot>)> {
@@ -60,6 +203,7 @@ impl History {
             return None;
         }
 
+        self.open = false;
         let revision = &self.revisions[self.current];
         let inverses = revision.txn.inverses.clone();
         let selection = revision.txn.selection_before.clone();
@@ -73,6 +217,7 @@ impl History {
         let child = self.revisions[self.current].last?;
 
         self.current = child;
+        self.open = false;
 
         let revision = &self.revisions[self.current];
 
@@ -82,9 +227,214 @@ impl History {
         ))
     }
 
+    /// The transaction that produced the current revision; `None` at the
+    /// root.
+    pub fn last_transaction(&self) -> Option<&Transaction> {
+        (!self.at_root()).then(|| &self.revisions[self.current].txn)
+    }
+
     pub fn at_root(&self) -> bool {
         self.current == 0
     }
+
+    // ── Tree navigation ─────────────────────────────────────────────────
+    // Revision ids are assigned in commit order, so they double as a
+    // chronological index across every branch.
+
+    pub fn current(&self) -> usize {
+        self.current
+    }
+
+    pub fn revision_count(&self) -> usize {
+        self.revisions.len()
+    }
+
+    pub fn revision(&self, id: usize) -> Option<RevisionInfo> {
+        let r = self.revisions.get(id)?;
+        Some(RevisionInfo {
+            id,
+            parent: (id != 0).then_some(r.parent),
+            timestamp: r.timestamp,
+            saved: r.saved,
+        })
+    }
+
+    /// Children of `id`, oldest first.
+    pub fn children(&self, id: usize) -> Vec<usize> {
+        (1..self.revisions.len())
+            .filter(|&c| self.revisions[c].parent == id)
+            .collect()
+    }
+
+    /// The tip of every branch: revisions without children, oldest first.
+    pub fn branches(&self) -> Vec<usize> {
+        let mut has_child = vec![false; self.revisions.len()];
+        for r in &self.revisions[1..] {
+            has_child[r.parent] = true;
+        }
+        (0..self.revisions.len())
+            .filter(|&id| !has_child[id])
+            .collect()
+    }
+
+    /// Record that the document was saved at the current revision.
+    pub fn mark_saved(&mut self) {
+        self.revisions[self.current].saved = true;
+        self.saved_at = Some(self.current);
+        self.open = false;
+    }
+
+    /// Forget the save point: the text matches no file, as for a buffer
+    /// holding recovered work.
+    pub fn mark_unsaved(&mut self) {
+        self.saved_at = None;
+    }
+
+    /// The current revision isn't the one last saved. Undoing or redoing
+    /// back to it makes the document clean again.
+    pub fn is_modified(&self) -> bool {
+        self.saved_at != Some(self.current)
+    }
+
+    /// Move to revision `target`, on any branch: undo up to the common
+    /// ancestor, then redo down to it. Returns the mutations to apply, in
+    /// order, and the selection to restore. Redo then follows this branch.
+    pub fn goto(&mut self, target: usize) -> Option<(Vec<Mutation>, Option<SelectionSnapshot>)> {
+        if target >= self.revisions.len() || target == self.current {
+            return None;
+        }
+
+        let up = self.ancestors(self.current);
+        let mut down = self.ancestors(target);
+        let common = *down.iter().find(|id| up.contains(id))?;
+        down.truncate(down.iter().position(|&id| id == common)?);
+        down.reverse();
+
+        let mut mutations = Vec::new();
+        let mut selection = None;
+        for &id in up.iter().take_while(|&&id| id != common) {
+            let txn = &self.revisions[id].txn;
+            mutations.extend(txn.inverses.iter().rev().cloned());
+            selection = txn.selection_before.clone();
+        }
+        for &id in &down {
+            let parent = self.revisions[id].parent;
+            self.revisions[parent].last = Some(id);
+            let txn = &self.revisions[id].txn;
+            mutations.extend(txn.mutations.iter().cloned());
+            selection = txn.selection_after.clone();
+        }
+
+        self.current = target;
+        self.open = false;
+        Some((mutations, selection))
+    }
+
+    /// `id` and its ancestors up to the root, nearest first.
+    fn ancestors(&self, mut id: usize) -> Vec<usize> {
+        let mut chain = vec![id];
+        while id != 0 {
+            id = self.revisions[id].parent;
+            chain.push(id);
+        }
+        chain
+    }
+
+    /// The revision `n` steps away in commit order (negative is earlier).
+    pub fn step(&self, n: isize) -> usize {
+        let last = self.revisions.len() - 1;
+        self.current.saturating_add_signed(n).min(last)
+    }
+
+    /// The newest revision made at least `d` before the current one.
+    pub fn earlier(&self, d: Duration) -> usize {
+        let Some(at) = self.revisions[self.current].timestamp.checked_sub(d) else {
+            return 0;
+        };
+        self.newest_until(at, self.current).unwrap_or(0)
+    }
+
+    /// The newest revision made at most `d` after the current one.
+    pub fn later(&self, d: Duration) -> usize {
+        let last = self.revisions.len() - 1;
+        let Some(at) = self.revisions[self.current].timestamp.checked_add(d) else {
+            return last;
+        };
+        self.newest_until(at, last)
+            .map_or(self.current, |id| id.max(self.current))
+    }
+
+    fn newest_until(&self, at: SystemTime, limit: usize) -> Option<usize> {
+        (0..=limit)
+            .rev()
+            .find(|&id| self.revisions[id].timestamp <= at)
+    }
+
+    /// The `n`th save point before the current revision, or the root.
+    pub fn earlier_saves(&self, n: usize) -> usize {
+        (0..self.current)
+            .rev()
+            .filter(|&id| self.revisions[id].saved)
+            .nth(n.saturating_sub(1))
+            .unwrap_or(0)
+    }
+
+    /// The `n`th save point after the current revision, or the newest
+    /// revision when there are fewer.
+    pub fn later_saves(&self, n: usize) -> usize {
+        (self.current + 1..self.revisions.len())
+            .filter(|&id| self.revisions[id].saved)
+            .nth(n.saturating_sub(1))
+            .unwrap_or(self.revisions.len() - 1)
+    }
+
+    /// Every revision with a graph prefix, depth first from the root. The
+    /// newest child continues its parent's column; older children hang off
+    /// it as indented branches:
+    ///
+    /// ```text
+    /// 0
+    /// ├─1
+    /// │ 2
+    /// 3
+    /// ```
+    pub fn tree(&self) -> Vec<TreeRow> {
+        let mut children = vec![Vec::new(); self.revisions.len()];
+        for (id, r) in self.revisions.iter().enumerate().skip(1) {
+            children[r.parent].push(id);
+        }
+
+        let mut rows = Vec::with_capacity(self.revisions.len());
+        tree_rows(&children, 0, String::new(), String::new(), &mut rows);
+        rows
+    }
+}
+
+fn tree_rows(
+    children: &[Vec<usize>],
+    mut id: usize,
+    first: String,
+    rest: String,
+    rows: &mut Vec<TreeRow>,
+) {
+    let mut graph = first;
+    loop {
+        rows.push(TreeRow { id, graph });
+        let Some((&main, branches)) = children[id].split_last() else {
+            return;
+        };
+        for &branch in branches {
+            tree_rows(
+                children,
+                branch,
+                format!("{rest}├─"),
+                format!("{rest}│ "),
+                rows,
+            );
+        }
+        id = main;
+        graph = rest.clone();
+    }
 }
 
 impl Default for History {
@@ -201,4 +551,224 @@ mod tests {
         }
         assert_eq!(rope.to_string(), "");
     }
+
+    /// Commit inserting `t` at `at` onto `rope`, with real inverses.
+    fn edit(h: &mut History, rope: &mut Rope, at: usize, t: &str) {
+        let mutations = vec![Mutation::new(at, at, t.to_string())];
+        let inverses = mutations.iter().map(|m| m.apply(rope)).collect();
+        h.commit(Transaction {
+            mutations,
+            inverses,
+            selection_before: Some(snap(at)),
+            selection_after: Some(snap(at + t.len())),
+        });
+    }
+
+    /// a → ab (1) → abc (2); undo twice; a → ad (3).
+    fn branched() -> (History, Rope) {
+        let mut h = History::new();
+        let mut rope = Rope::from_str("a");
+        edit(&mut h, &mut rope, 1, "b");
+        edit(&mut h, &mut rope, 2, "c");
+        for _ in 0..2 {
+            let (inverses, _) = h.undo().unwrap();
+            for m in inverses.iter().rev() {
+                m.apply(&mut rope);
+            }
+        }
+        edit(&mut h, &mut rope, 1, "d");
+        (h, rope)
+    }
+
+    #[test]
+    fn goto_any_branch() {
+        let (mut h, mut rope) = branched();
+        assert_eq!(rope.to_string(), "ad");
+        assert_eq!(h.branches(), vec![2, 3]);
+        assert_eq!(h.children(0), vec![1, 3]);
+
+        let (mutations, sel) = h.goto(2).unwrap();
+        apply_forward(&mut rope, &mutations);
+        assert_eq!(rope.to_string(), "abc");
+        assert_eq!(sel.unwrap().ranges, vec![(3, 3)]);
+        assert_eq!(h.current(), 2);
+
+        // Redo now follows the branch just visited.
+        let (mutations, _) = h.goto(0).unwrap();
+        apply_forward(&mut rope, &mutations);
+        assert_eq!(rope.to_string(), "a");
+        let (mutations, _) = h.redo().unwrap();
+        apply_forward(&mut rope, &mutations);
+        assert_eq!(rope.to_string(), "ab");
+
+        assert!(h.goto(1).is_none());
+        assert!(h.goto(9).is_none());
+    }
+
+    #[test]
+    fn tree_graph() {
+        let (h, _) = branched();
+        let tree = h.tree();
+        let rows: Vec<(usize, &str)> = tree.iter().map(|r| (r.id, r.graph.as_str())).collect();
+        assert_eq!(rows, vec![(0, ""), (1, "├─"), (2, "│ "), (3, "")]);
+    }
+
+    #[test]
+    fn time_travel() {
+        let (mut h, _) = branched();
+        let t0 = SystemTime::UNIX_EPOCH + Duration::from_secs(1000);
+        for (id, secs) in [(0, 0), (1, 10), (2, 20), (3, 60)] {
+            h.revisions[id].timestamp = t0 + Duration::from_secs(secs);
+        }
+
+        // At 3 (t = 60).
+        assert_eq!(h.earlier(Duration::from_secs(30)), 2);
+        assert_eq!(h.earlier(Duration::from_secs(45)), 1);
+        assert_eq!(h.earlier(Duration::from_secs(600)), 0);
+        assert_eq!(h.later(Duration::from_secs(5)), 3);
+
+        h.goto(1).unwrap();
+        assert_eq!(h.later(Duration::from_secs(10)), 2);
+        assert_eq!(h.step(-1), 0);
+        assert_eq!(h.step(5), 3);
+        // Far past what SystemTime can hold.
+        assert_eq!(h.later(Duration::MAX), 3);
+        assert_eq!(h.earlier(Duration::MAX), 0);
+    }
+
+    #[test]
+    fn save_points() {
+        let (mut h, _) = branched();
+        h.goto(1).unwrap();
+        h.mark_saved();
+        h.goto(3).unwrap();
+        h.mark_saved();
+
+        assert_eq!(h.earlier_saves(1), 1);
+        assert_eq!(h.earlier_saves(2), 0);
+        h.goto(0).unwrap();
+        assert_eq!(h.later_saves(1), 1);
+        assert_eq!(h.later_saves(2), 3);
+        assert!(h.revision(3).unwrap().saved);
+        assert_eq!(h.revision(3).unwrap().parent, Some(0));
+        assert_eq!(h.revision(0).unwrap().parent, None);
+    }
+
+    /// Splice `[p, q) → t` on `rope` as a coalescing commit, with the cursor
+    /// moving from `p` (or `q` for a backspace) to the end of `t`.
+    fn splice(h: &mut History, rope: &mut Rope, p: usize, q: usize, t: &str, rules: &Coalesce) {
+        let mutations = vec![Mutation::new(p, q, t.to_string())];
+        let inverses = mutations.iter().map(|m| m.apply(rope)).collect();
+        let before = if t.is_empty() { q } else { p };
+        h.commit_coalescing(
+            Transaction {
+                mutations,
+                inverses,
+                selection_before: Some(snap(before)),
+                selection_after: Some(snap(p + t.chars().count())),
+            },
+            rules,
+        );
+    }
+
+    fn undo_into(h: &mut History, rope: &mut Rope) {
+        let (inverses, _) = h.undo().unwrap();
+        for m in inverses.iter().rev() {
+            m.apply(rope);
+        }
+    }
+
+    #[test]
+    fn typing_and_deleting_runs_coalesce() {
+        let rules = Coalesce::default();
+        let mut h = History::new();
+        let mut rope = Rope::new();
+        for (i, c) in ["a", "b", "c"].into_iter().enumerate() {
+            splice(&mut h, &mut rope, i, i, c, &rules);
+        }
+        // Backspace twice: a new kind of edit, so a new step.
+        splice(&mut h, &mut rope, 2, 3, "", &rules);
+        splice(&mut h, &mut rope, 1, 2, "", &rules);
+        assert_eq!(rope.to_string(), "a");
+        assert_eq!(h.revision_count(), 3);
+
+        undo_into(&mut h, &mut rope);
+        assert_eq!(rope.to_string(), "abc");
+        undo_into(&mut h, &mut rope);
+        assert_eq!(rope.to_string(), "");
+    }
+
+    #[test]
+    fn moving_the_cursor_breaks_a_run() {
+        let rules = Coalesce::default();
+        let mut h = History::new();
+        let mut rope = Rope::from_str("xy");
+        splice(&mut h, &mut rope, 0, 0, "a", &rules);
+        splice(&mut h, &mut rope, 3, 3, "b", &rules);
+        assert_eq!(h.revision_count(), 3);
+    }
+
+    #[test]
+    fn explicit_commits_and_undo_close_the_step() {
+        let rules = Coalesce::default();
+        let mut h = History::new();
+        let mut rope = Rope::new();
+        splice(&mut h, &mut rope, 0, 0, "a", &rules);
+        h.seal();
+        splice(&mut h, &mut rope, 1, 1, "b", &rules);
+        edit(&mut h, &mut rope, 2, "c");
+        splice(&mut h, &mut rope, 3, 3, "d", &rules);
+        assert_eq!(h.revision_count(), 5);
+
+        undo_into(&mut h, &mut rope);
+        let (mutations, _) = h.redo().unwrap();
+        apply_forward(&mut rope, &mutations);
+        splice(&mut h, &mut rope, 4, 4, "e", &rules);
+        assert_eq!(h.revision_count(), 6);
+    }
+
+    #[test]
+    fn bursts_and_idle() {
+        let bursts = Coalesce {
+            bursts: true,
+            ..Coalesce::default()
+        };
+        let mut h = History::new();
+        let mut rope = Rope::from_str("xy");
+        splice(&mut h, &mut rope, 0, 0, "a", &bursts);
+        splice(&mut h, &mut rope, 2, 3, "", &bursts);
+        assert_eq!(h.revision_count(), 2);
+
+        let mut h = History::new();
+        splice(&mut h, &mut rope, 0, 0, "a", &Coalesce::NEVER);
+        splice(&mut h, &mut rope, 1, 1, "b", &Coalesce::NEVER);
+        assert_eq!(h.revision_count(), 3);
+    }
+
+    #[test]
+    fn modified_until_back_at_the_save_point() {
+        let mut h = History::new();
+        let mut rope = Rope::from_str("a");
+        assert!(!h.is_modified());
+
+        edit(&mut h, &mut rope, 1, "b");
+        assert!(h.is_modified());
+        h.mark_saved();
+        assert!(!h.is_modified());
+
+        edit(&mut h, &mut rope, 2, "c");
+        assert!(h.is_modified());
+        h.undo().unwrap();
+        assert!(!h.is_modified());
+        h.undo().unwrap();
+        assert!(h.is_modified());
+        h.goto(2).unwrap();
+        h.goto(1).unwrap();
+        assert!(!h.is_modified());
+
+        h.mark_unsaved();
+        assert!(h.is_modified());
+        h.undo().unwrap();
+        assert!(h.is_modified());
+    }
 }
@@ -0,0 +1,236 @@
+//! Indentation rules for new and edited lines.
+//!
+//! Text-only heuristics: the new line copies the current line's indentation,
+//! gains a level after an opening bracket (or a block keyword, for languages
+//! that have them), and a closing bracket typed on a blank prefix loses one.
+//! The indent unit (`"\t"`, `"    "`, …) comes from the document options.
+
+use std::borrow::Cow;
+
+use ropey::RopeSlice;
+
+use crate::options::IndentStyle;
+use crate::syntax::Language;
+
+/// Lines sampled by [`infer`]; enough to settle, cheap on huge files.
+const INFER_SAMPLE_LINES: usize = 1000;
+
+/// Leading whitespace of `line`.
+pub fn line_indent(text: &RopeSlice, line: usize) -> String {
+    text.line(line)
+        .chars()
+        .take_while(|&c| c == ' ' || c == '\t')
+        .collect()
+}
+
+/// Indentation for a line opened by a newline inserted at `head`.
+pub fn newline_indent(
+    text: &RopeSlice,
+    head: usize,
+    language: Option<Language>,
+    unit: &str,
+) -> String {
+    let line = text.char_to_line(head);
+    let start = text.line_to_char(line);
+    let before: String = text.slice(start..head).chars().collect();
+
+    let mut indent = line_indent(text, line);
+    // Breaking inside the indentation keeps only what's left of the cursor.
+    indent.truncate(before.len().min(indent.len()));
+
+    if opens_block(before.trim_end(), language) {
+        indent.push_str(unit);
+    }
+
+    indent
+}
+
+/// `indent` with one level removed from its end. A trailing tab is
+/// `tab_width` columns; what's left of it after a narrower level of spaces
+/// comes back as spaces.
+pub fn dedent<'a>(indent: &'a str, unit: &str, tab_width: usize) -> Cow<'a, str> {
+    if let Some(rest) = indent.strip_suffix(unit) {
+        return rest.into();
+    }
+    if let Some(rest) = indent.strip_suffix('\t') {
+        let left = tab_width.saturating_sub(unit.len());
+        if left == 0 {
+            return rest.into();
+        }
+        return format!("{rest}{}", " ".repeat(left)).into();
+    }
+    // Partial level: drop the trailing spaces, at most one unit's worth.
+    let spaces = indent.len() - indent.trim_end_matches(' ').len();
+    indent[..indent.len() - spaces.min(unit.len().max(1))].into()
+}
+
+/// Guess the indent style a file already uses: tabs if more lines start with
+/// a tab than with spaces, otherwise the most common step between the widths
+/// of successive space-indented lines. `None` when nothing is indented.
+pub fn infer(text: &RopeSlice) -> Option<IndentStyle> {
+    let mut tabs = 0;
+    let mut spaces = 0;
+    // Votes for step widths 1..=8.
+    let mut steps = [0usize; 9];
+    let mut prev_width = 0;
+
+    for line in text.lines().take(INFER_SAMPLE_LINES) {
+        let mut chars = line.chars().peekable();
+        match chars.peek() {
+            Some('\t') => {
+                tabs += 1;
+                continue;
+            }
+            Some('\n') | Some('\r') | None => continue,
+            _ => {}
+        }
+
+        let width = chars.by_ref().take_while(|&c| c == ' ').count();
+        if width > 0 {
+            spaces += 1;
+        }
+        let step = width.abs_diff(prev_width);
+        if (1..steps.len()).contains(&step) {
+            steps[step] += 1;
+        }
+        prev_width = width;
+    }
+
+    if tabs == 0 && spaces == 0 {
+        return None;
+    }
+    if tabs > spaces {
+        return Some(IndentStyle::Tabs);
+    }
+
+    // Ties go to the larger step, so a 4-space file with a few 2-space
+    // continuation lines still reads as 4.
+    let (step, _) = steps
+        .iter()
+        .enumerate()
+        .skip(1)
+        .max_by_key(|&(step, &votes)| (votes, step))?;
+    Some(IndentStyle::Spaces(step))
+}
+
+pub fn is_closing_bracket(c: char) -> bool {
+    matches!(c, ')' | ']' | '}')
+}
+
+/// True if the code before the cursor ends by opening a block.
+fn opens_block(before: &str, language: Option<Language>) -> bool {
+    if before.ends_with(['(', '[', '{']) {
+        return true;
+    }
+
+    match language {
+        Some(Language::Lua) => {
+            let last = before
+                .rsplit(|c: char| !(c.is_alphanumeric() || c == '_'))
+                .next()
+                .unwrap_or("");
+            matches!(last, "then" | "do" | "else" | "repeat")
+                || (before.ends_with(')') && is_lua_function_header(before))
+        }
+        _ => false,
+    }
+}
+
+/// `function name(args)` / `local function f(x)` / `= function(x)`, but not
+/// a one-line `f(function() … end)`.
+fn is_lua_function_header(line: &str) -> bool {
+    let words: Vec<&str> = line
+        .split(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .collect();
+    words.contains(&"function") && !words.contains(&"end")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ropey::Rope;
+
+    fn indent_at(s: &str, head: usize, lang: Option<Language>) -> String {
+        newline_indent(&Rope::from_str(s).slice(..), head, lang, "    ")
+    }
+
+    #[test]
+    fn copies_current_indent() {
+        assert_eq!(indent_at("    let x = 1;\n", 14, None), "    ");
+        assert_eq!(indent_at("\tfoo\n", 4, None), "\t");
+    }
+
+    #[test]
+    fn adds_level_after_open_bracket() {
+        assert_eq!(indent_at("fn f() {\n", 8, None), "    ");
+        assert_eq!(indent_at("    call(\n", 9, None), "        ");
+        // Trailing blanks after the bracket don't matter.
+        assert_eq!(indent_at("x = [  \n", 7, None), "    ");
+    }
+
+    #[test]
+    fn breaking_inside_indent() {
+        assert_eq!(indent_at("        x\n", 4, None), "    ");
+    }
+
+    #[test]
+    fn lua_block_keywords() {
+        let lua = Some(Language::Lua);
+        assert_eq!(indent_at("if x then\n", 9, lua), "    ");
+        assert_eq!(indent_at("  for i = 1, 3 do\n", 17, lua), "      ");
+        assert_eq!(indent_at("local function f(a)\n", 19, lua), "    ");
+        assert_eq!(indent_at("print(x)\n", 8, lua), "");
+        assert_eq!(indent_at("f(function() return 1 end)\n", 26, lua), "");
+        // Keywords only count for languages that have them.
+        assert_eq!(indent_at("if x then\n", 9, None), "");
+    }
+
+    fn infer_of(s: &str) -> Option<IndentStyle> {
+        infer(&Rope::from_str(s).slice(..))
+    }
+
+    #[test]
+    fn infer_spaces() {
+        let src = "fn a() {\n  if x {\n    y();\n  }\n}\n";
+        assert_eq!(infer_of(src), Some(IndentStyle::Spaces(2)));
+
+        let src = "a:\n    b:\n        c\n    d\n";
+        assert_eq!(infer_of(src), Some(IndentStyle::Spaces(4)));
+    }
+
+    #[test]
+    fn infer_tabs() {
+        assert_eq!(infer_of("a {\n\tb\n\tc\n}\n"), Some(IndentStyle::Tabs));
+    }
+
+    #[test]
+    fn infer_ignores_blank_lines() {
+        assert_eq!(
+            infer_of("a\n    b\n\n    c\n"),
+            Some(IndentStyle::Spaces(4))
+        );
+    }
+
+    #[test]
+    fn infer_nothing_indented() {
+        assert_eq!(infer_of("a\nb\n"), None);
+        assert_eq!(infer_of(""), None);
+    }
+
+    #[test]
+    fn dedent_levels() {
+        assert_eq!(dedent("        ", "    ", 4), "    ");
+        assert_eq!(dedent("\t\t", "\t", 4), "\t");
+        assert_eq!(dedent("\t", "    ", 4), "");
+        assert_eq!(dedent("      ", "    ", 4), "  ");
+        assert_eq!(dedent("  ", "    ", 4), "");
+        assert_eq!(dedent("", "    ", 4), "");
+    }
+
+    #[test]
+    fn dedent_measures_tabs() {
+        assert_eq!(dedent("\t", "  ", 4), "  ");
+        assert_eq!(dedent("  \t", "    ", 8), "      ");
+        assert_eq!(dedent("\t", "        ", 4), "");
+    }
+}
@@ -1,88 +1,374 @@
-use std::io::{self, BufRead, BufReader, Write};
-use std::path::{Path, PathBuf};
+use std::fs::{File, Metadata, OpenOptions};
+use std::io::{self, Write};
+#[cfg(unix)]
+use std::os::unix::fs::MetadataExt;
+use std::path::{Component, Path, PathBuf};
+use std::time::SystemTime;
 
-use ropey::{Rope, RopeBuilder};
+use chardetng::EncodingDetector;
+use encoding_rs::{Encoding, UTF_8, UTF_16BE, UTF_16LE};
+use ropey::Rope;
 
+use crate::diff;
 use crate::document::Document;
 use crate::editorconfig;
-use crate::options::{DocumentOptions, LineEnding};
+use crate::history::Transaction;
+use crate::indent;
+use crate::options::{DocumentOptions, LineEnding, Sniffed};
+use crate::syntax::Language;
+use crate::undofile::{self, Limits};
+
+/// Bytes [`utf16_without_bom`] and [`is_binary`] look at.
+const SAMPLE_BYTES: usize = 8000;
+
+/// Bytes that aren't valid in the file's encoding are kept in the rope as
+/// `ESCAPE_BASE + byte`, in the last private-use plane, and written back as
+/// the raw byte.
+const ESCAPE_BASE: u32 = 0x10FF00;
+
+/// Longest path [`keyed_path`] flattens into a file name; file names are
+/// usually capped at 255 bytes.
+const MAX_KEY_BYTES: usize = 200;
+
+/// Where [`fnv1a`] starts.
+pub(crate) const FNV_OFFSET: u64 = 0xcbf29ce484222325;
 
 pub fn load(path: PathBuf) -> io::Result<Document> {
-    let path = std::fs::canonicalize(&path).unwrap_or(path);
+    let path = absolute(&path);
     let rules = editorconfig::rules_for(&path);
 
-    let (text, options) = if path.exists() {
-        let (rope, line_ending, final_newline, bom) = read_and_sniff(&path)?;
-        let options = DocumentOptions::resolve(
-            line_ending,
-            final_newline,
-            bom,
-            rules.line_ending,
-            rules.final_newline,
-            rules.trim_trailing_whitespace,
-        );
-        (rope, options)
+    let (text, sniffed) = if path.exists() {
+        read_and_sniff(&path, rules.encoding)?
     } else {
-        let options = DocumentOptions::resolve(
-            LineEnding::Lf,
-            true,
-            false,
-            rules.line_ending,
-            rules.final_newline,
-            rules.trim_trailing_whitespace,
-        );
-        (Rope::new(), options)
+        (Rope::new(), Sniffed::default())
     };
+    let sniffed_lossless = sniffed.lossless;
+    let options = DocumentOptions::resolve(sniffed, rules);
+
+    let mut doc = Document::from_rope(text, Some(options));
+    doc.read_only = !sniffed_lossless;
+    doc.set_language(Language::from_path(&path));
 
-    let mut doc = Document::from_rope(text);
+    // An unreadable or stale undo file just means starting without history.
+    if let Ok(Some(history)) = undofile::read(&gauchito_paths::undo_dir(), &path, &doc.text) {
+        doc.set_history(history);
+        doc.mark_saved();
+    }
+    doc.disk = DiskStamp::of(&path);
     doc.path = Some(path);
-    doc.options = options;
     Ok(doc)
 }
 
-/// Read a file line-by-line, sniffing BOM, line-ending style, and final newline.
-/// Returns the rope (with `\r\n` normalized to `\n`) and the sniffed properties.
-fn read_and_sniff(path: &Path) -> io::Result<(Rope, LineEnding, bool, bool)> {
-    let file = std::fs::File::open(path)?;
-    let mut reader = BufReader::new(file);
-    let mut builder = RopeBuilder::new();
-    let mut buf = String::new();
+/// `path` made absolute with `.` and `..` resolved, so one file has one
+/// path (swap and undo files are keyed by it). `..` goes up from where the
+/// directory before it really is, following symlinks as the system would;
+/// directories that don't exist yet are resolved by name. Otherwise not
+/// canonical: a symlinked file stays a symlink on save.
+pub fn absolute(path: &Path) -> PathBuf {
+    let path = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    let mut out = PathBuf::new();
+    for part in path.components() {
+        match part {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if let Ok(real) = std::fs::canonicalize(&out) {
+                    out = real;
+                }
+                out.pop();
+            }
+            part => out.push(part),
+        }
+    }
+    out
+}
 
-    let mut first = true;
-    let mut bom = false;
-    let mut line_ending = LineEnding::Lf;
-    let mut final_newline = false;
+/// The file in `dir` keeping per-file state (swap, undo history) for `file`,
+/// with extension `ext`. It's the path with `/` replaced by `%`, as vim does.
+/// A path with a `%` of its own, one that isn't UTF-8, or one too long to
+/// flatten is named after its last component and a hash of the whole path
+/// instead. Those names have no `%`, so they can't meet a flattened one.
+pub(crate) fn keyed_path(dir: &Path, file: &Path, ext: &str) -> PathBuf {
+    if let Some(path) = file.to_str()
+        && !path.contains('%')
+        && path.len() <= MAX_KEY_BYTES
+    {
+        let name: String = path
+            .chars()
+            .map(|c| if c == '/' || c == '\\' { '%' } else { c })
+            .collect();
+        return dir.join(format!("{name}.{ext}"));
+    }
+    let mut tail: String = file
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .replace('%', "_");
+    while tail.len() > 64 {
+        tail.pop();
+    }
+    let hash = fnv1a(FNV_OFFSET, file.as_os_str().as_encoded_bytes());
+    dir.join(format!("{tail}-{hash:016x}.{ext}"))
+}
 
-    loop {
-        buf.clear();
-        if reader.read_line(&mut buf)? == 0 {
-            break;
-        }
+/// FNV-1a over `bytes`, going on from `hash`.
+pub(crate) fn fnv1a(mut hash: u64, bytes: &[u8]) -> u64 {
+    for &b in bytes {
+        hash ^= u64::from(b);
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
 
-        if first {
-            first = false;
+/// What's cheap to check about a file to tell whether it changed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiskStamp {
+    pub modified: SystemTime,
+    pub len: u64,
+}
 
-            if buf.starts_with('\u{feff}') {
-                bom = true;
-                buf.drain(..'\u{feff}'.len_utf8());
-            }
+impl DiskStamp {
+    /// `None` if the file doesn't exist (or can't be stat'ed).
+    pub fn of(path: &Path) -> Option<Self> {
+        let meta = std::fs::metadata(path).ok()?;
+        Some(DiskStamp {
+            modified: meta.modified().ok()?,
+            len: meta.len(),
+        })
+    }
+}
 
-            if buf.ends_with("\r\n") {
-                line_ending = LineEnding::Crlf;
-            }
-        }
+/// The file on disk no longer matches `doc.disk`: it was written, created or
+/// deleted by someone else since we last read or wrote it.
+pub fn changed_on_disk(doc: &Document) -> bool {
+    doc.path
+        .as_deref()
+        .is_some_and(|path| DiskStamp::of(path) != doc.disk)
+}
 
-        final_newline = buf.ends_with('\n');
+/// Replace the text with the file's current contents as an undoable edit.
+/// Only the text that differs is spliced, so anchors (selections, marks)
+/// elsewhere stay put. Line endings, BOM and final newline are sniffed
+/// again, so a save writes the file back as it now is. The result counts
+/// as saved. Returns whether the text
+/// changed; a file that no longer decodes losslessly is an error.
+pub fn reload(doc: &mut Document) -> io::Result<bool> {
+    let encoding = doc.options.encoding;
+    reload_as(doc, encoding)
+}
+
+/// [`reload`], decoding the file as `encoding` this time (a BOM still
+/// wins). Saves then write `encoding` too.
+pub fn reload_as(doc: &mut Document, encoding: &'static Encoding) -> io::Result<bool> {
+    let path = doc
+        .path
+        .clone()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::Other, "document has no file path"))?;
+    let stamp = DiskStamp::of(&path);
+    let (text, sniffed) = read_and_sniff(&path, Some(encoding))?;
+    if !sniffed.lossless {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "file is now binary or undecodable",
+        ));
+    }
+
+    let mutations = diff::mutations(&doc.text, &text);
+    let changed = !mutations.is_empty();
+    if changed {
+        // Its own undo step, never merged with the typing around it.
+        doc.seal_history();
+        let inverses = doc.apply_mutations(&mutations);
+        doc.commit(Transaction {
+            mutations,
+            inverses,
+            selection_before: None,
+            selection_after: None,
+        });
+        doc.seal_history();
+    }
+    // The file's format may have changed too. `.editorconfig` still wins,
+    // as on load; the indent style is the user's to keep.
+    let encoding = sniffed.encoding;
+    let format = DocumentOptions::resolve(sniffed, editorconfig::rules_for(&path));
+    doc.options.line_ending = format.line_ending;
+    doc.options.final_newline = format.final_newline;
+    doc.options.bom = format.bom;
+    doc.options.encoding = encoding;
+    doc.mark_saved();
+    doc.disk = stamp;
+    Ok(changed)
+}
+
+/// Read and decode a file, sniffing BOM, encoding, line-ending style, final
+/// newline and indentation. Returns the rope (with `\r\n` normalized to
+/// `\n`) and the sniffed properties.
+///
+/// `encoding` (from `.editorconfig` or the caller) is used unless the file
+/// starts with a BOM; see [`detect`]. Binary and undecodable files still
+/// load, with `lossless` cleared so the document opens read-only.
+fn read_and_sniff(path: &Path, encoding: Option<&'static Encoding>) -> io::Result<(Rope, Sniffed)> {
+    let bytes = std::fs::read(path)?;
+
+    let (encoding, bom_len) = detect(&bytes, encoding);
+    let (mut decoded, lossless) = decode(&bytes[bom_len..], encoding);
+    let lossless = lossless && !is_binary(&bytes, encoding);
+
+    let line_ending = match decoded.find('\n') {
+        Some(i) if decoded[..i].ends_with('\r') => LineEnding::Crlf,
+        _ => LineEnding::Lf,
+    };
+    let final_newline = decoded.ends_with('\n');
+
+    if decoded.contains("\r\n") {
+        decoded = decoded.replace("\r\n", "\n");
+    }
+
+    let rope = Rope::from_str(&decoded);
+    let indent = indent::infer(&rope.slice(..));
+
+    Ok((
+        rope,
+        Sniffed {
+            line_ending,
+            final_newline,
+            bom: bom_len > 0,
+            encoding,
+            indent,
+            lossless,
+        },
+    ))
+}
+
+/// Pick the encoding of `bytes` and the length of its BOM. In order: a BOM,
+/// the `preferred` encoding, UTF-16 without a BOM (NULs in every other byte),
+/// valid UTF-8, and finally chardetng's guess.
+fn detect(bytes: &[u8], preferred: Option<&'static Encoding>) -> (&'static Encoding, usize) {
+    if let Some(found) = Encoding::for_bom(bytes) {
+        return found;
+    }
+    if let Some(encoding) = preferred {
+        return (encoding, 0);
+    }
+    if let Some(encoding) = utf16_without_bom(bytes) {
+        return (encoding, 0);
+    }
+    if mostly_utf8(bytes) {
+        return (UTF_8, 0);
+    }
 
-        if buf.ends_with("\r\n") {
-            buf.truncate(buf.len() - 2);
-            buf.push('\n');
+    let mut detector = EncodingDetector::new();
+    detector.feed(bytes, true);
+    (detector.guess(None, true), 0)
+}
+
+/// Mostly-ASCII UTF-16 has a NUL in every high byte. Looks at the first
+/// [`SAMPLE_BYTES`] bytes only.
+fn utf16_without_bom(bytes: &[u8]) -> Option<&'static Encoding> {
+    let sample = &bytes[..bytes.len().min(SAMPLE_BYTES) & !1];
+    if sample.is_empty() {
+        return None;
+    }
+
+    let pairs = sample.len() / 2;
+    let (even, odd) = sample.chunks(2).fold((0, 0), |(even, odd), pair| {
+        (
+            even + usize::from(pair[0] == 0),
+            odd + usize::from(pair[1] == 0),
+        )
+    });
+
+    // Nearly every high byte NUL, nearly no low byte.
+    if odd * 10 >= pairs * 9 && even * 10 <= pairs {
+        Some(UTF_16LE)
+    } else if even * 10 >= pairs * 9 && odd * 10 <= pairs {
+        Some(UTF_16BE)
+    } else {
+        None
+    }
+}
+
+/// True if `bytes` are valid UTF-8 but for a few stray bytes: more valid
+/// multi-byte characters than invalid sequences.
+fn mostly_utf8(bytes: &[u8]) -> bool {
+    let (mut multibyte, mut invalid) = (0, 0);
+    for chunk in bytes.utf8_chunks() {
+        multibyte += chunk.valid().chars().filter(|c| !c.is_ascii()).count();
+        invalid += usize::from(!chunk.invalid().is_empty());
+    }
+    invalid == 0 || multibyte > invalid
+}
+
+/// NULs in the first [`SAMPLE_BYTES`] bytes mean binary, except in UTF-16.
+fn is_binary(bytes: &[u8], encoding: &'static Encoding) -> bool {
+    encoding != UTF_16LE
+        && encoding != UTF_16BE
+        && bytes[..bytes.len().min(SAMPLE_BYTES)].contains(&0)
+}
+
+fn escape(byte: u8) -> char {
+    char::from_u32(ESCAPE_BASE + u32::from(byte)).unwrap()
+}
+
+fn unescape(c: char) -> Option<u8> {
+    u32::from(c)
+        .checked_sub(ESCAPE_BASE)
+        .and_then(|b| u8::try_from(b).ok())
+}
+
+/// Decode `bytes`, and whether [`encode`] gives them back unchanged.
+///
+/// Invalid UTF-8 is escaped byte by byte, which round-trips unless the file
+/// already holds characters from the escape range. Other encodings decode
+/// invalid sequences to U+FFFD, which doesn't.
+fn decode(bytes: &[u8], encoding: &'static Encoding) -> (String, bool) {
+    if encoding != UTF_8 {
+        let (text, had_errors) = encoding.decode_without_bom_handling(bytes);
+        return (text.into_owned(), !had_errors);
+    }
+
+    let mut text = String::with_capacity(bytes.len());
+    let mut lossless = true;
+    for chunk in bytes.utf8_chunks() {
+        lossless &= !chunk.valid().chars().any(|c| unescape(c).is_some());
+        text.push_str(chunk.valid());
+        text.extend(chunk.invalid().iter().map(|&b| escape(b)));
+    }
+    (text, lossless)
+}
+
+/// Encode `s` for `encoding`, writing escaped bytes back raw. Fails if `s`
+/// has characters the encoding can't represent.
+fn encode(s: &str, encoding: &'static Encoding) -> io::Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(s.len());
+    let mut run = 0;
+    for (i, c) in s.char_indices() {
+        if let Some(byte) = unescape(c) {
+            out.extend(encode_run(&s[run..i], encoding)?);
+            out.push(byte);
+            run = i + c.len_utf8();
         }
+    }
+    out.extend(encode_run(&s[run..], encoding)?);
+    Ok(out)
+}
 
-        builder.append(&buf);
+fn encode_run(s: &str, encoding: &'static Encoding) -> io::Result<Vec<u8>> {
+    // encoding_rs only decodes UTF-16; its encoder for them writes UTF-8.
+    if encoding == UTF_16LE {
+        return Ok(s.encode_utf16().flat_map(u16::to_le_bytes).collect());
+    }
+    if encoding == UTF_16BE {
+        return Ok(s.encode_utf16().flat_map(u16::to_be_bytes).collect());
     }
 
-    Ok((builder.finish(), line_ending, final_newline, bom))
+    let (bytes, _, unmappable) = encoding.encode(s);
+    if unmappable {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("text has characters {} can't represent", encoding.name()),
+        ));
+    }
+    Ok(bytes.into_owned())
 }
 
 pub fn write(doc: &Document) -> io::Result<()> {
@@ -91,14 +377,83 @@ pub fn write(doc: &Document) -> io::Result<()> {
         .as_ref()
         .ok_or_else(|| io::Error::new(io::ErrorKind::Other, "document has no file path"))?;
 
-    let file = std::fs::File::create(path)?;
-    let mut w = io::BufWriter::new(file);
+    if doc.read_only {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "document is read-only",
+        ));
+    }
+
+    let bytes = serialize(doc)?;
+    save(path, &bytes)?;
+
+    // The history is kept against the text load will read back. A final
+    // newline added at the end leaves every offset in it valid; trimmed
+    // whitespace doesn't, so then it's dropped.
+    let text = read_back(&bytes, doc.options.encoding);
+    let len = doc.text.len_chars();
+    let fits = text.len_chars() <= len + 1
+        && text.len_chars() >= len
+        && text.slice(..len) == doc.text.slice(..);
+
+    // The file is saved either way; losing its undo history isn't worth an error.
+    let dir = gauchito_paths::undo_dir();
+    let limits = Limits::default();
+    let _ = if fits {
+        undofile::write(&dir, path, &text, doc.history(), limits)
+    } else {
+        undofile::remove(&dir, path)
+    }
+    .and_then(|()| undofile::prune(&dir, limits));
+    Ok(())
+}
+
+/// Write `doc` to `path` and make that its file from now on: `doc.path`, the
+/// `.editorconfig` options and the grammar follow the new name. With
+/// `create_dirs`, missing parent directories are created first. On error the
+/// document is left as it was.
+pub fn save_as(doc: &mut Document, path: PathBuf, create_dirs: bool) -> io::Result<()> {
+    let path = absolute(&path);
+    if create_dirs && let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    // The buffer's current format stands in for what load would sniff.
+    let rules = editorconfig::rules_for(&path);
+    let sniffed = Sniffed {
+        line_ending: doc.options.line_ending,
+        final_newline: doc.options.final_newline,
+        bom: doc.options.bom,
+        encoding: rules.encoding.unwrap_or(doc.options.encoding),
+        indent: indent::infer(&doc.text.slice(..)),
+        lossless: true,
+    };
+    let options = DocumentOptions::resolve(sniffed, rules);
+
+    let old_options = std::mem::replace(&mut doc.options, options);
+    let old_path = doc.path.replace(path.clone());
+    if let Err(e) = write(doc) {
+        doc.options = old_options;
+        doc.path = old_path;
+        return Err(e);
+    }
+
+    doc.disk = DiskStamp::of(&path);
+    doc.set_language(Language::from_path(&path));
+    Ok(())
+}
 
-    if doc.options.bom {
-        w.write_all("\u{feff}".as_bytes())?;
+/// The document's bytes on disk, per its options.
+fn serialize(doc: &Document) -> io::Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(doc.text.len_bytes());
+    let encoding = doc.options.encoding;
+
+    // Only the Unicode encodings have a BOM.
+    if doc.options.bom && (encoding == UTF_8 || encoding == UTF_16LE || encoding == UTF_16BE) {
+        out.extend(encode("\u{feff}", encoding)?);
     }
 
-    let sep = doc.options.line_ending.as_str();
+    let sep = &encode(doc.options.line_ending.as_str(), encoding)?;
 
     for line in doc.text.lines() {
         let mut content: String = line.chars().collect();
@@ -114,10 +469,10 @@ pub fn write(doc: &Document) -> io::Result<()> {
             content.truncate(trimmed.len());
         }
 
-        w.write_all(content.as_bytes())?;
+        out.extend(encode(&content, encoding)?);
 
         if had_newline {
-            w.write_all(sep.as_bytes())?;
+            out.extend_from_slice(sep);
         }
     }
 
@@ -126,9 +481,424 @@ pub fn write(doc: &Document) -> io::Result<()> {
         let len = doc.text.len_chars();
         let ends_with_nl = len > 0 && doc.text.char(len - 1) == '\n';
         if !ends_with_nl {
-            w.write_all(sep.as_bytes())?;
+            out.extend_from_slice(sep);
         }
     }
 
-    w.flush()
+    Ok(out)
+}
+
+/// The text [`load`] decodes from `bytes` written by [`serialize`].
+fn read_back(bytes: &[u8], encoding: &'static Encoding) -> Rope {
+    let (encoding, bom_len) = detect(bytes, Some(encoding));
+    let (decoded, _) = decode(&bytes[bom_len..], encoding);
+    Rope::from_str(&decoded.replace("\r\n", "\n"))
+}
+
+/// Replace the contents of `path` with `bytes`.
+///
+/// Symlinks are followed, so the link stays and its target is replaced. The
+/// new contents go to a temp file next to the target, are synced, and renamed
+/// over it, keeping mode and ownership; a crash leaves either the old file or
+/// the new one. When that isn't possible (read-only directory, ownership we
+/// can't give away, hard links the rename would split) the file is rewritten
+/// in place instead.
+fn save(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    let target = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let meta = std::fs::metadata(&target).ok();
+
+    #[cfg(unix)]
+    if meta.as_ref().is_some_and(|m| m.nlink() > 1) {
+        return write_in_place(&target, bytes);
+    }
+
+    let temp = temp_path(&target);
+    let file = match create_temp(&temp, meta.is_some()) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::PermissionDenied => {
+            return write_in_place(&target, bytes);
+        }
+        Err(e) => return Err(e),
+    };
+
+    match write_temp(file, bytes, meta.as_ref()) {
+        Ok(true) => {}
+        Ok(false) => {
+            let _ = std::fs::remove_file(&temp);
+            return write_in_place(&target, bytes);
+        }
+        Err(e) => {
+            let _ = std::fs::remove_file(&temp);
+            return Err(e);
+        }
+    }
+
+    if let Err(e) = std::fs::rename(&temp, &target) {
+        let _ = std::fs::remove_file(&temp);
+        return Err(e);
+    }
+
+    // Make the rename itself durable.
+    #[cfg(unix)]
+    if let Some(dir) = target.parent().and_then(|d| File::open(d).ok()) {
+        let _ = dir.sync_all();
+    }
+
+    Ok(())
+}
+
+/// `.name.gauchito-<pid>` in the same directory as `target`, so the rename
+/// stays on one filesystem.
+fn temp_path(target: &Path) -> PathBuf {
+    let name = target
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    target.with_file_name(format!(".{name}.gauchito-{}", std::process::id()))
+}
+
+/// Create the temp file. One already there carries our pid but isn't ours
+/// (we remove ours after every save): it was left by a process that died
+/// with the same pid, so it goes. A `private` one (replacing a file whose
+/// mode [`write_temp`] copies) starts readable by its owner only.
+fn create_temp(temp: &Path, private: bool) -> io::Result<File> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    if private {
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    }
+    let create = || options.open(temp);
+    match create() {
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+            std::fs::remove_file(temp)?;
+            create()
+        }
+        result => result,
+    }
+}
+
+/// Copy ownership and mode from `meta` to the temp file, then fill and sync
+/// it. The bytes go in last, so nobody the target keeps out can read them
+/// meanwhile. Returns false if the ownership can't be kept.
+fn write_temp(mut file: File, bytes: &[u8], meta: Option<&Metadata>) -> io::Result<bool> {
+    if let Some(meta) = meta {
+        // Ownership first: chown clears setuid/setgid bits.
+        #[cfg(unix)]
+        if std::os::unix::fs::fchown(&file, Some(meta.uid()), Some(meta.gid())).is_err() {
+            return Ok(false);
+        }
+        file.set_permissions(meta.permissions())?;
+    }
+
+    file.write_all(bytes)?;
+
+    // Writing clears setuid/setgid too: put them back.
+    #[cfg(unix)]
+    if let Some(meta) = meta
+        && meta.mode() & 0o6000 != 0
+    {
+        file.set_permissions(meta.permissions())?;
+    }
+
+    file.sync_all()?;
+    Ok(true)
+}
+
+/// Replace `path` with what `fill` writes, through a temp file only its
+/// owner can read. For the editor's own files (swap, undo), which can hold
+/// text from files other users can't read. A crash mid-write keeps the old
+/// file.
+pub(crate) fn write_private(
+    path: &Path,
+    fill: impl FnOnce(&mut File) -> io::Result<()>,
+) -> io::Result<()> {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".{}.tmp", std::process::id()));
+    let temp = path.with_file_name(name);
+
+    // A leftover from a crashed process that had our pid; its mode may be wider.
+    let _ = std::fs::remove_file(&temp);
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    let mut file = options.open(&temp)?;
+    let result = fill(&mut file)
+        .and_then(|()| file.sync_all())
+        .and_then(|()| std::fs::rename(&temp, path));
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temp);
+    }
+    result
+}
+
+fn write_in_place(target: &Path, bytes: &[u8]) -> io::Result<()> {
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(target)?;
+    file.write_all(bytes)?;
+    file.sync_all()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use encoding_rs::{SHIFT_JIS, WINDOWS_1252};
+
+    #[test]
+    fn detect_bom() {
+        assert_eq!(detect(b"\xEF\xBB\xBFhi", None), (UTF_8, 3));
+        assert_eq!(detect(b"\xFF\xFEh\0i\0", None), (UTF_16LE, 2));
+        // A BOM beats the preferred encoding.
+        assert_eq!(detect(b"\xFE\xFF\0h", Some(WINDOWS_1252)), (UTF_16BE, 2));
+    }
+
+    #[test]
+    fn detect_without_bom() {
+        assert_eq!(detect("añ\n".as_bytes(), None), (UTF_8, 0));
+        assert_eq!(detect(b"h\0i\0\n\0", None), (UTF_16LE, 0));
+        assert_eq!(detect(b"\0h\0i\0\n", None), (UTF_16BE, 0));
+        assert_eq!(detect(b"caf\xE9 cr\xE8me\n", None).0, WINDOWS_1252);
+        assert_eq!(detect(b"plain", Some(SHIFT_JIS)), (SHIFT_JIS, 0));
+    }
+
+    #[test]
+    fn round_trip() {
+        for encoding in [UTF_8, UTF_16LE, UTF_16BE, WINDOWS_1252] {
+            let bytes = encode("café\n", encoding).unwrap();
+            assert_eq!(decode(&bytes, encoding), ("café\n".to_string(), true));
+        }
+    }
+
+    #[test]
+    fn unrepresentable() {
+        assert!(encode("→", WINDOWS_1252).is_err());
+    }
+
+    #[test]
+    fn invalid_utf8_round_trips() {
+        let bytes = b"ok \xFF\xFE caf\xC3\xA9\n";
+        let (text, lossless) = decode(bytes, UTF_8);
+        assert!(lossless);
+        assert_eq!(text.chars().filter(|&c| unescape(c).is_some()).count(), 2);
+        assert_eq!(encode(&text, UTF_8).unwrap(), bytes);
+    }
+
+    #[test]
+    fn escape_range_in_source_is_lossy() {
+        let bytes = "\u{10FF41}".as_bytes();
+        assert!(!decode(bytes, UTF_8).1);
+    }
+
+    #[test]
+    fn mostly_utf8_with_stray_bytes() {
+        assert_eq!(detect(b"a\xC3\xB1 \xC3\xB1 \xFF", None).0, UTF_8);
+        assert!(mostly_utf8(b"caf\xC3\xA9 na\xC3\xAFve \xFF"));
+        assert!(!mostly_utf8(b"caf\xE9"));
+    }
+
+    #[test]
+    fn binary() {
+        assert!(is_binary(b"\x7FELF\x02\x01\x01\0\0", UTF_8));
+        assert!(!is_binary(b"h\0i\0", UTF_16LE));
+        assert!(!is_binary(b"text\n", UTF_8));
+    }
+
+    /// A fresh, empty directory for one test.
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("gauchito-io-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn save_replaces_contents() {
+        let dir = scratch("replace");
+        let file = dir.join("a.txt");
+        std::fs::write(&file, "old contents\n").unwrap();
+
+        save(&file, b"new\n").unwrap();
+        assert_eq!(std::fs::read(&file).unwrap(), b"new\n");
+        // No temp file left behind.
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+
+        save(&dir.join("b.txt"), b"created\n").unwrap();
+        assert_eq!(std::fs::read(dir.join("b.txt")).unwrap(), b"created\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn save_keeps_mode() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = scratch("mode");
+        let file = dir.join("run.sh");
+        std::fs::write(&file, "echo\n").unwrap();
+        std::fs::set_permissions(&file, std::fs::Permissions::from_mode(0o750)).unwrap();
+
+        save(&file, b"echo hi\n").unwrap();
+        let mode = std::fs::metadata(&file).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o750);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn temp_is_private_before_it_is_filled() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = scratch("private-temp");
+        let temp = dir.join("temp");
+        create_temp(&temp, true).unwrap();
+        let mode = std::fs::metadata(&temp).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        let file = dir.join("secret");
+        std::fs::write(&file, "old\n").unwrap();
+        std::fs::set_permissions(&file, std::fs::Permissions::from_mode(0o600)).unwrap();
+        save(&file, b"new\n").unwrap();
+        let mode = std::fs::metadata(&file).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn save_through_symlink() {
+        let dir = scratch("symlink");
+        let real = dir.join("real.txt");
+        let link = dir.join("link.txt");
+        std::fs::write(&real, "old\n").unwrap();
+        std::os::unix::fs::symlink(&real, &link).unwrap();
+
+        save(&link, b"new\n").unwrap();
+        assert!(std::fs::symlink_metadata(&link).unwrap().is_symlink());
+        assert_eq!(std::fs::read(&real).unwrap(), b"new\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn save_keeps_hard_links() {
+        let dir = scratch("hardlink");
+        let a = dir.join("a.txt");
+        let b = dir.join("b.txt");
+        std::fs::write(&a, "old\n").unwrap();
+        std::fs::hard_link(&a, &b).unwrap();
+
+        save(&a, b"new\n").unwrap();
+        assert_eq!(std::fs::read(&b).unwrap(), b"new\n");
+    }
+
+    #[test]
+    fn read_back_is_what_load_reads() {
+        let dir = scratch("read-back");
+        let file = dir.join("a.txt");
+        let options = DocumentOptions {
+            line_ending: LineEnding::Crlf,
+            bom: true,
+            encoding: UTF_16LE,
+            trim_trailing_whitespace: true,
+            ..DocumentOptions::default()
+        };
+        let doc = Document::from_rope(Rope::from_str("a  \nb"), Some(options));
+
+        let bytes = serialize(&doc).unwrap();
+        save(&file, &bytes).unwrap();
+        let text = read_back(&bytes, doc.options.encoding);
+        assert_eq!(text.to_string(), "a\nb\n");
+        assert_eq!(load(file).unwrap().text, text);
+    }
+
+    #[test]
+    fn save_over_stale_temp() {
+        let dir = scratch("stale");
+        let file = dir.join("a.txt");
+        std::fs::write(temp_path(&file), "left by a crash").unwrap();
+
+        save(&file, b"new\n").unwrap();
+        assert_eq!(std::fs::read(&file).unwrap(), b"new\n");
+        assert!(!temp_path(&file).exists());
+    }
+
+    #[test]
+    fn absolute_resolves_dots() {
+        let path = absolute(Path::new("/a/b/../c/./d"));
+        assert_eq!(path, Path::new("/a/c/d"));
+        assert!(absolute(Path::new("x/../y")).ends_with("y"));
+        assert!(absolute(Path::new("x/../y")).is_absolute());
+    }
+
+    #[test]
+    fn keyed_paths_stay_apart() {
+        let dir = Path::new("/state");
+        let plain = keyed_path(dir, Path::new("/a/b"), "swp");
+        assert_eq!(plain, Path::new("/state/%a%b.swp"));
+        let percent = keyed_path(dir, Path::new("/a%b"), "swp");
+        assert_ne!(percent, plain);
+        let name = percent.file_name().unwrap().to_str().unwrap();
+        assert!(name.starts_with("a_b-"));
+
+        let long = format!("/{}/notes.txt", "d".repeat(300));
+        let hashed = keyed_path(dir, Path::new(&long), "undo");
+        assert!(hashed.file_name().unwrap().len() < 255);
+        let other = format!("{long}x");
+        assert_ne!(hashed, keyed_path(dir, Path::new(&other), "undo"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn absolute_goes_up_from_a_symlink_target() {
+        let dir = scratch("dotdot");
+        std::fs::create_dir_all(dir.join("real/sub")).unwrap();
+        std::os::unix::fs::symlink(dir.join("real/sub"), dir.join("link")).unwrap();
+
+        let real = std::fs::canonicalize(dir.join("real")).unwrap();
+        assert_eq!(absolute(&dir.join("link/../f")), real.join("f"));
+        // A symlinked file itself is kept.
+        assert_eq!(absolute(&dir.join("./link")), dir.join("link"));
+    }
+
+    #[test]
+    fn reload_in_another_encoding() {
+        let dir = scratch("reload-as");
+        let file = dir.join("a.txt");
+        std::fs::write(&file, "café\n").unwrap();
+
+        let mut doc = load(file).unwrap();
+        assert_eq!(doc.text.to_string(), "café\n");
+        assert!(reload_as(&mut doc, WINDOWS_1252).unwrap());
+        assert_eq!(doc.text.to_string(), "cafÃ©\n");
+        assert_eq!(doc.options.encoding, WINDOWS_1252);
+    }
+
+    #[test]
+    fn reload_picks_up_the_new_format() {
+        let dir = scratch("reload-format");
+        let file = dir.join("a.txt");
+        std::fs::write(&file, "a\r\nb").unwrap();
+
+        let mut doc = load(file.clone()).unwrap();
+        assert_eq!(doc.options.line_ending, LineEnding::Crlf);
+        assert!(!doc.options.final_newline);
+
+        std::fs::write(&file, "\u{feff}a\nb\n").unwrap();
+        reload(&mut doc).unwrap();
+        assert_eq!(doc.text.to_string(), "a\nb\n");
+        assert_eq!(doc.options.line_ending, LineEnding::Lf);
+        assert!(doc.options.final_newline);
+        assert!(doc.options.bom);
+    }
+
+    #[test]
+    fn new_file_takes_editorconfig_charset() {
+        let dir = scratch("charset");
+        let rules = "root = true\n[*]\ncharset = latin1\n";
+        std::fs::write(dir.join(".editorconfig"), rules).unwrap();
+
+        let doc = load(dir.join("new.txt")).unwrap();
+        assert_eq!(doc.options.encoding, WINDOWS_1252);
+    }
 }
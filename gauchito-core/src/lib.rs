@@ -1,12 +1,25 @@
 pub mod anchor;
 pub mod changeset;
+pub mod clipboard;
+pub mod diff;
 pub mod document;
 pub mod editorconfig;
 pub mod edits;
 pub mod fileio;
 pub mod grapheme;
+pub mod highlight;
 pub mod history;
 pub mod ids;
+pub mod indent;
+pub mod macros;
+pub mod marks;
 pub mod movement;
 pub mod options;
+pub mod registers;
+pub mod search;
 pub mod selection;
+pub mod structure;
+pub mod swap;
+pub mod syntax;
+pub mod theme;
+pub mod undofile;
@@ -0,0 +1,376 @@
+//! Keyboard macros and dot-repeat: key streams recorded as they're typed
+//! and fed back through the dispatcher.
+//!
+//! A macro is stored as register text so it can be put, edited and yanked
+//! back like any other. Printable keys are themselves (`space` is a blank),
+//! other keys are their name in angle brackets (`<esc>`, `<ctrl-w>`), and a
+//! literal `<` is `<lt>`. Newlines are skipped, so long macros can be split
+//! over lines.
+//!
+//! [`Macros`] is the recorder's state: the keys seen while recording, the
+//! keys of the last repeatable change, and a replay queued by a handler for
+//! the dispatcher to run once that handler's key is done.
+//!
+//! A change starts when a handler the preset marks repeatable runs, and
+//! takes in every key from the first of its command (counts included). It
+//! ends with that command, or with the transaction the command opened (an
+//! insert), and is kept for `.` only if it edited something. A count given
+//! to `.` replaces the one the change was typed with.
+
+/// Replays running inside each other stop nesting past this, so a macro
+/// that replays itself can't run forever.
+const MAX_DEPTH: usize = 100;
+
+/// One key as the dispatcher sees it: its name and, if printable, its char.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Key {
+    pub name: String,
+    pub ch: Option<char>,
+}
+
+impl Key {
+    pub fn new(name: &str, ch: Option<char>) -> Self {
+        Key {
+            name: name.to_string(),
+            ch,
+        }
+    }
+}
+
+/// `keys` as register text.
+pub fn encode(keys: &[Key]) -> String {
+    let mut text = String::new();
+    for key in keys {
+        match key.ch {
+            Some('<') => text.push_str("<lt>"),
+            Some(c) if c != '\n' => text.push(c),
+            _ => {
+                text.push('<');
+                text.push_str(&key.name);
+                text.push('>');
+            }
+        }
+    }
+    text
+}
+
+/// The keys written in `text`. A `<` with no closing `>` is typed as is.
+pub fn decode(text: &str) -> Vec<Key> {
+    let mut keys = Vec::new();
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        rest = &rest[c.len_utf8()..];
+        match c {
+            '\n' | '\r' => {}
+            '<' => match rest.split_once('>') {
+                Some(("lt", after)) => {
+                    keys.push(Key::new("<", Some('<')));
+                    rest = after;
+                }
+                Some((name, after)) if !name.is_empty() && !name.contains(['<', ' ', '\n']) => {
+                    keys.push(Key::new(name, None));
+                    rest = after;
+                }
+                _ => keys.push(Key::new("<", Some('<'))),
+            },
+            ' ' => keys.push(Key::new("space", Some(' '))),
+            c => keys.push(Key::new(&c.to_string(), Some(c))),
+        }
+    }
+    keys
+}
+
+#[derive(Debug)]
+struct Recording {
+    register: char,
+    keys: Vec<Key>,
+    /// Where the key sequence being dispatched began.
+    command_start: usize,
+}
+
+#[derive(Debug, Default)]
+pub struct Macros {
+    recording: Option<Recording>,
+    queued: Option<(Vec<Key>, usize)>,
+    depth: usize,
+    /// Keys since the current command, or the open change, began.
+    command: Vec<Key>,
+    /// A change is open; true once it has edited.
+    change: Option<bool>,
+    in_transaction: bool,
+    /// The last change's leading count, and its keys after that.
+    last_count: Vec<Key>,
+    last_change: Vec<Key>,
+}
+
+impl Macros {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start recording into `register`, dropping any recording in progress.
+    pub fn start(&mut self, register: char) {
+        self.recording = Some(Recording {
+            register,
+            keys: Vec::new(),
+            command_start: 0,
+        });
+    }
+
+    /// The register being recorded into.
+    pub fn recording(&self) -> Option<char> {
+        self.recording.as_ref().map(|r| r.register)
+    }
+
+    /// A key about to be dispatched. `starts_command` when no sequence was
+    /// pending, so the key begins a new command.
+    pub fn observe(&mut self, key: Key, starts_command: bool) {
+        if starts_command && self.change.is_none() {
+            self.command.clear();
+        }
+        self.command.push(key.clone());
+
+        if self.replaying() {
+            return;
+        }
+        if let Some(rec) = &mut self.recording {
+            if starts_command {
+                rec.command_start = rec.keys.len();
+            }
+            rec.keys.push(key);
+        }
+    }
+
+    /// End the recording, returning its register and text. The keys of the
+    /// command that stopped it aren't part of the macro.
+    pub fn stop(&mut self) -> Option<(char, String)> {
+        let mut rec = self.recording.take()?;
+        rec.keys.truncate(rec.command_start);
+        Some((rec.register, encode(&rec.keys)))
+    }
+
+    /// The current command is a repeatable change.
+    pub fn begin_change(&mut self) {
+        self.change.get_or_insert(false);
+    }
+
+    /// The document was edited.
+    pub fn note_edit(&mut self) {
+        if let Some(edited) = &mut self.change {
+            *edited = true;
+        }
+    }
+
+    /// An explicit transaction opened or closed; an open one keeps a change
+    /// going past the end of its command.
+    pub fn set_transaction(&mut self, open: bool) {
+        self.in_transaction = open;
+    }
+
+    /// The dispatched command finished: close the change it was part of,
+    /// unless a transaction is still open.
+    pub fn end_command(&mut self) {
+        if self.in_transaction {
+            return;
+        }
+        if self.change.take() == Some(true) {
+            let mut keys = std::mem::take(&mut self.command);
+            self.last_change = keys.split_off(count_len(&keys));
+            self.last_count = keys;
+        }
+    }
+
+    /// Queue the last change, with `count` in place of the count it was
+    /// typed with; without one it keeps that. False if there's none.
+    pub fn repeat_last_change(&mut self, count: Option<usize>) -> bool {
+        if self.last_change.is_empty() {
+            return false;
+        }
+        let mut keys = match count {
+            Some(n) => decode(&n.to_string()),
+            None => self.last_count.clone(),
+        };
+        keys.extend(self.last_change.iter().cloned());
+        self.queue_keys(keys, 1)
+    }
+
+    /// Ask for `text` to be replayed `count` times. Returns false when
+    /// replays are already nested too deep.
+    pub fn queue(&mut self, text: &str, count: usize) -> bool {
+        self.queue_keys(decode(text), count)
+    }
+
+    fn queue_keys(&mut self, keys: Vec<Key>, count: usize) -> bool {
+        if self.depth >= MAX_DEPTH {
+            return false;
+        }
+        self.queued = Some((keys, count.max(1)));
+        true
+    }
+
+    pub fn take_queued(&mut self) -> Option<(Vec<Key>, usize)> {
+        self.queued.take()
+    }
+
+    /// Keys are coming from a macro, not the user.
+    pub fn replaying(&self) -> bool {
+        self.depth > 0
+    }
+
+    pub fn enter_replay(&mut self) {
+        self.depth += 1;
+    }
+
+    pub fn leave_replay(&mut self) {
+        self.depth -= 1;
+    }
+}
+
+/// How many of `keys` are the count typed before a command.
+fn count_len(keys: &[Key]) -> usize {
+    match keys.first().and_then(|k| k.ch) {
+        // A leading `0` is a motion, not a count.
+        Some('1'..='9') => keys
+            .iter()
+            .take_while(|k| k.ch.is_some_and(|c| c.is_ascii_digit()))
+            .count(),
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn typed(names: &[&str]) -> Vec<Key> {
+        names
+            .iter()
+            .map(|&name| {
+                let mut chars = name.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => Key::new(name, Some(c)),
+                    _ if name == "space" => Key::new(name, Some(' ')),
+                    _ => Key::new(name, None),
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn text_round_trip() {
+        let keys = typed(&["d", "w", "i", "<", "space", "é", "esc", "ctrl-w", "j"]);
+        let text = encode(&keys);
+        assert_eq!(text, "dwi<lt> é<esc><ctrl-w>j");
+        assert_eq!(decode(&text), keys);
+    }
+
+    #[test]
+    fn hand_written_text() {
+        assert_eq!(decode("x\nj\n"), typed(&["x", "j"]));
+        // Not a key name: typed literally.
+        assert_eq!(decode("a<b"), typed(&["a", "<", "b"]));
+        assert_eq!(decode("<>"), typed(&["<", ">"]));
+        assert_eq!(decode("i<a b>"), typed(&["i", "<", "a", "space", "b", ">"]));
+    }
+
+    #[test]
+    fn recording_drops_the_stopping_command() {
+        let mut macros = Macros::new();
+        macros.observe(Key::new("x", Some('x')), true);
+        macros.start('a');
+        for key in typed(&["d", "w"]) {
+            macros.observe(key, true);
+        }
+        // `d` waits for a motion: `w` continues it.
+        macros.observe(Key::new("d", Some('d')), true);
+        macros.observe(Key::new("w", Some('w')), false);
+        macros.observe(Key::new("q", Some('q')), true);
+        assert_eq!(macros.recording(), Some('a'));
+        assert_eq!(macros.stop(), Some(('a', "dwdw".to_string())));
+        assert_eq!(macros.stop(), None);
+    }
+
+    /// Dispatch `names` as one command, the way the runtime does.
+    fn command(macros: &mut Macros, names: &[&str], repeatable: bool, edits: bool) {
+        for (i, key) in typed(names).into_iter().enumerate() {
+            macros.observe(key, i == 0);
+            if i == 0 && repeatable {
+                macros.begin_change();
+            }
+        }
+        if edits {
+            macros.note_edit();
+        }
+        macros.end_command();
+    }
+
+    #[test]
+    fn last_change_spans_its_command() {
+        let mut macros = Macros::new();
+        assert!(!macros.repeat_last_change(None));
+
+        command(&mut macros, &["3", "d", "w"], true, true);
+        command(&mut macros, &["j"], false, false);
+        // Repeatable, but it changed nothing.
+        command(&mut macros, &["d", "esc"], true, false);
+        assert!(macros.repeat_last_change(None));
+        assert_eq!(macros.take_queued(), Some((typed(&["3", "d", "w"]), 1)));
+    }
+
+    #[test]
+    fn count_replaces_the_changes_own() {
+        let mut macros = Macros::new();
+        command(&mut macros, &["2", "d", "w"], true, true);
+        assert!(macros.repeat_last_change(Some(3)));
+        assert_eq!(macros.take_queued(), Some((typed(&["3", "d", "w"]), 1)));
+
+        command(&mut macros, &["1", "0", "x"], true, true);
+        assert!(macros.repeat_last_change(Some(2)));
+        assert_eq!(macros.take_queued(), Some((typed(&["2", "x"]), 1)));
+
+        // `0` begins no count.
+        command(&mut macros, &["0", "x"], true, true);
+        assert!(macros.repeat_last_change(Some(2)));
+        assert_eq!(macros.take_queued(), Some((typed(&["2", "0", "x"]), 1)));
+    }
+
+    #[test]
+    fn insert_change_ends_on_commit() {
+        let mut macros = Macros::new();
+        macros.observe(Key::new("i", Some('i')), true);
+        macros.begin_change();
+        macros.set_transaction(true);
+        macros.end_command();
+        for key in typed(&["h", "i"]) {
+            macros.observe(key, true);
+            macros.note_edit();
+            macros.end_command();
+        }
+        macros.observe(Key::new("esc", None), true);
+        macros.set_transaction(false);
+        macros.end_command();
+
+        assert!(macros.repeat_last_change(None));
+        assert_eq!(
+            macros.take_queued(),
+            Some((typed(&["i", "h", "i", "esc"]), 1))
+        );
+    }
+
+    #[test]
+    fn replays_are_not_recorded_and_nest_boundedly() {
+        let mut macros = Macros::new();
+        macros.start('b');
+        macros.enter_replay();
+        macros.observe(Key::new("x", Some('x')), true);
+        assert!(macros.queue("x", 0));
+        assert_eq!(macros.take_queued(), Some((typed(&["x"]), 1)));
+        macros.leave_replay();
+        assert_eq!(macros.stop(), Some(('b', String::new())));
+
+        for _ in 0..MAX_DEPTH {
+            macros.enter_replay();
+        }
+        assert!(!macros.queue("x", 1));
+    }
+}
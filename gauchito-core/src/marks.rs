@@ -0,0 +1,229 @@
+//! Marks and the jump list: remembered positions that follow edits.
+//!
+//! Every position is an anchor in its document's [`AnchorTable`], so it
+//! moves with the text the way selections do. Local marks (`a`–`z`) belong
+//! to one document; global marks (`A`–`Z`) and the jump list span all of
+//! them and name the document too. Positions in documents that have since
+//! been closed are skipped.
+//!
+//! [`AnchorTable`]: crate::anchor::AnchorTable
+
+use std::collections::HashMap;
+
+use crate::anchor::AnchorId;
+use crate::document::{Document, DocumentId};
+
+/// Older jumps are forgotten past this many.
+const JUMP_LIST_LEN: usize = 100;
+
+pub type Documents = HashMap<DocumentId, Document>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Location {
+    doc: DocumentId,
+    anchor: AnchorId,
+}
+
+impl Location {
+    fn new(doc: DocumentId, offset: usize, docs: &mut Documents) -> Option<Location> {
+        let anchor = docs.get_mut(&doc)?.anchors.add(offset);
+        Some(Location { doc, anchor })
+    }
+
+    /// Where it is now, if its document is still open.
+    fn resolve(&self, docs: &Documents) -> Option<(DocumentId, usize)> {
+        let d = docs.get(&self.doc)?;
+        let offset = d.anchors.offset(self.anchor).min(d.text.len_chars());
+        Some((self.doc, offset))
+    }
+
+    fn release(self, docs: &mut Documents) {
+        if let Some(d) = docs.get_mut(&self.doc) {
+            d.anchors.remove(self.anchor);
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Marks {
+    local: HashMap<(DocumentId, char), Location>,
+    global: HashMap<char, Location>,
+    jumps: Vec<Location>,
+    /// The entry back / forward move from; `jumps.len()` past the newest.
+    current: usize,
+}
+
+impl Marks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Put mark `name` at `offset` in `doc`. False for names that aren't
+    /// marks and for documents that aren't open.
+    pub fn set(
+        &mut self,
+        name: char,
+        doc: DocumentId,
+        offset: usize,
+        docs: &mut Documents,
+    ) -> bool {
+        if !name.is_ascii_alphabetic() {
+            return false;
+        }
+        let Some(loc) = Location::new(doc, offset, docs) else {
+            return false;
+        };
+        let old = if name.is_ascii_lowercase() {
+            self.local.insert((doc, name), loc)
+        } else {
+            self.global.insert(name, loc)
+        };
+        if let Some(old) = old {
+            old.release(docs);
+        }
+        true
+    }
+
+    /// Where mark `name` is now. Local marks are looked up in `doc`.
+    pub fn get(
+        &self,
+        name: char,
+        doc: DocumentId,
+        docs: &Documents,
+    ) -> Option<(DocumentId, usize)> {
+        let loc = if name.is_ascii_lowercase() {
+            self.local.get(&(doc, name))
+        } else {
+            self.global.get(&name)
+        }?;
+        loc.resolve(docs)
+    }
+
+    /// Remember `offset` in `doc` before jumping away from it. Jumps gone
+    /// back over are dropped, so forward only retraces back.
+    pub fn push_jump(&mut self, doc: DocumentId, offset: usize, docs: &mut Documents) {
+        for loc in self.jumps.drain(self.current..) {
+            loc.release(docs);
+        }
+        // Jumping again from the same spot records it once.
+        let same = self.jumps.last().and_then(|loc| loc.resolve(docs)) == Some((doc, offset));
+        if !same && let Some(loc) = Location::new(doc, offset, docs) {
+            self.jumps.push(loc);
+        }
+        if self.jumps.len() > JUMP_LIST_LEN {
+            self.jumps.remove(0).release(docs);
+        }
+        self.current = self.jumps.len();
+    }
+
+    /// The jump before the current one. Leaving the newest remembers where
+    /// it's left from (`offset` in `doc`) so forward can come back.
+    pub fn back(
+        &mut self,
+        doc: DocumentId,
+        offset: usize,
+        docs: &mut Documents,
+    ) -> Option<(DocumentId, usize)> {
+        if self.current >= self.jumps.len() {
+            self.push_jump(doc, offset, docs);
+            self.current = self.jumps.len().saturating_sub(1);
+        }
+        let start = self.current;
+        while self.current > 0 {
+            self.current -= 1;
+            if let Some(to) = self.jumps[self.current].resolve(docs) {
+                return Some(to);
+            }
+        }
+        self.current = start;
+        None
+    }
+
+    /// The jump after the current one, after going back.
+    pub fn forward(&mut self, docs: &Documents) -> Option<(DocumentId, usize)> {
+        for i in self.current + 1..self.jumps.len() {
+            if let Some(to) = self.jumps[i].resolve(docs) {
+                self.current = i;
+                return Some(to);
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mutation::Mutation;
+    use ropey::Rope;
+
+    fn open(docs: &mut Documents, text: &str) -> DocumentId {
+        let doc = Document::from_rope(Rope::from_str(text), None);
+        let id = doc.id;
+        docs.insert(id, doc);
+        id
+    }
+
+    #[test]
+    fn marks_follow_edits() {
+        let mut docs = Documents::new();
+        let one = open(&mut docs, "hello world\n");
+        let two = open(&mut docs, "other\n");
+        let mut marks = Marks::new();
+
+        assert!(marks.set('a', one, 6, &mut docs));
+        assert!(marks.set('B', one, 0, &mut docs));
+        assert!(!marks.set('1', one, 0, &mut docs));
+
+        let doc = docs.get_mut(&one).unwrap();
+        doc.apply_mutations(&[Mutation::new(0, 0, "XX".into())]);
+        assert_eq!(marks.get('a', one, &docs), Some((one, 8)));
+        // Global marks are seen from every document, local ones aren't.
+        assert_eq!(marks.get('B', two, &docs), Some((one, 2)));
+        assert_eq!(marks.get('a', two, &docs), None);
+
+        // Moving a mark doesn't leave its old anchor behind.
+        marks.set('a', one, 1, &mut docs);
+        assert_eq!(docs[&one].anchors.len(), 2);
+    }
+
+    #[test]
+    fn back_and_forward() {
+        let mut docs = Documents::new();
+        let doc = open(&mut docs, &"x".repeat(100));
+        let mut marks = Marks::new();
+
+        marks.push_jump(doc, 0, &mut docs);
+        marks.push_jump(doc, 10, &mut docs);
+        marks.push_jump(doc, 10, &mut docs);
+        assert_eq!(marks.back(doc, 20, &mut docs), Some((doc, 10)));
+        assert_eq!(marks.back(doc, 10, &mut docs), Some((doc, 0)));
+        assert_eq!(marks.back(doc, 0, &mut docs), None);
+        assert_eq!(marks.forward(&docs), Some((doc, 10)));
+        assert_eq!(marks.forward(&docs), Some((doc, 20)));
+        assert_eq!(marks.forward(&docs), None);
+
+        // A new jump after going back drops what was ahead.
+        marks.back(doc, 20, &mut docs);
+        marks.push_jump(doc, 10, &mut docs);
+        assert_eq!(marks.forward(&docs), None);
+        assert_eq!(marks.back(doc, 50, &mut docs), Some((doc, 10)));
+        assert_eq!(docs[&doc].anchors.len(), 3);
+    }
+
+    #[test]
+    fn closed_documents_are_skipped() {
+        let mut docs = Documents::new();
+        let one = open(&mut docs, "one\n");
+        let two = open(&mut docs, "two\n");
+        let mut marks = Marks::new();
+
+        marks.push_jump(one, 1, &mut docs);
+        marks.push_jump(two, 2, &mut docs);
+        marks.set('C', two, 3, &mut docs);
+        docs.remove(&two);
+
+        assert_eq!(marks.get('C', one, &docs), None);
+        assert_eq!(marks.back(one, 3, &mut docs), Some((one, 1)));
+    }
+}
@@ -9,13 +9,15 @@
 //! - Position:        `fn(&RopeSlice, head)            -> head`
 //! - Selection-shape: `fn(&RopeSlice, anchor, head)    -> (anchor, head)`
 //! - Char search:     `fn(&RopeSlice, head, ch)        -> head`
+//! - Pattern search:  `fn(&RopeSlice, head, pattern)   -> head`
 //!
-//! Functions that "search" (find_char_*, match_bracket) return the original
-//! position when the target is missing — so they're never partial.
+//! Functions that "search" (find_char_*, search_*, match_bracket) return the
+//! original position when the target is missing — so they're never partial.
 
 use ropey::RopeSlice;
 
 use crate::grapheme::{next_grapheme_boundary, prev_grapheme_boundary};
+use crate::search::Pattern;
 
 // ── Character classification ─────────────────────────────────────────────────
 
@@ -498,6 +500,34 @@ pub fn find_char_backward_after(text: &RopeSlice, head: usize, ch: char) -> usiz
     }
 }
 
+// ── Pattern search ──────────────────────────────────────────────────────────
+
+/// `/`, `n` — move to the start of the next match of `pattern` after `head`,
+/// wrapping past the end of the document. Stay on miss or invalid pattern.
+pub fn search_forward(text: &RopeSlice, head: usize, pattern: &str) -> usize {
+    let Some(pat) = Pattern::cached(pattern) else {
+        return head;
+    };
+    let from = (head + 1).min(text.len_chars());
+
+    pat.find_forward(text, from)
+        .or_else(|| pat.find_forward(text, 0))
+        .map_or(head, |(start, _)| start)
+}
+
+/// `?`, `N` — move to the start of the previous match of `pattern` before
+/// `head`, wrapping past the start of the document. Stay on miss or invalid
+/// pattern.
+pub fn search_backward(text: &RopeSlice, head: usize, pattern: &str) -> usize {
+    let Some(pat) = Pattern::cached(pattern) else {
+        return head;
+    };
+
+    pat.find_backward(text, head)
+        .or_else(|| pat.find_backward(text, text.len_chars()))
+        .map_or(head, |(start, _)| start)
+}
+
 // ── Bracket matching ────────────────────────────────────────────────────────
 
 /// Internal: locate the matching bracket if `pos` is on one.
@@ -615,6 +645,309 @@ pub fn select_whole_line(text: &RopeSlice, _anchor: usize, head: usize) -> (usiz
     (start, end)
 }
 
+// ── Text objects ────────────────────────────────────────────────────────────
+//
+// Selection-shape kernels for `i`/`a` objects. Each returns a forward,
+// half-open `(from, to)` range around `head`, or `(anchor, head)` unchanged
+// when there's no object under the cursor.
+
+/// Internal: the half-open run around `head` whose chars all satisfy `same`
+/// as the char under `head`. Never crosses a line ending.
+fn run_around(text: &RopeSlice, head: usize, same: impl Fn(char, char) -> bool) -> (usize, usize) {
+    let c = text.char(head);
+
+    let mut from = head;
+    while from > 0 && same(c, text.char(from - 1)) {
+        from -= 1;
+    }
+
+    let len = text.len_chars();
+    let mut to = head + 1;
+    while to < len && same(c, text.char(to)) {
+        to += 1;
+    }
+
+    (from, to)
+}
+
+fn same_class(a: char, b: char) -> bool {
+    char_class(a) == char_class(b)
+}
+
+fn same_big_class(a: char, b: char) -> bool {
+    let ca = char_class(a);
+    let cb = char_class(b);
+    if ca == CharClass::Eol || cb == CharClass::Eol {
+        return false;
+    }
+    is_big_word(a) == is_big_word(b)
+}
+
+/// Internal: grow a word run to include trailing blanks on the same line, or
+/// leading blanks if there are none after it.
+fn around_word(text: &RopeSlice, (from, to): (usize, usize)) -> (usize, usize) {
+    let len = text.len_chars();
+    let mut end = to;
+    while end < len && char_class(text.char(end)) == CharClass::Whitespace {
+        end += 1;
+    }
+    if end > to {
+        return (from, end);
+    }
+
+    let mut start = from;
+    while start > 0 && char_class(text.char(start - 1)) == CharClass::Whitespace {
+        start -= 1;
+    }
+    (start, to)
+}
+
+fn word_object(
+    text: &RopeSlice,
+    anchor: usize,
+    head: usize,
+    same: fn(char, char) -> bool,
+    around: bool,
+) -> (usize, usize) {
+    if head >= text.len_chars() || char_class(text.char(head)) == CharClass::Eol {
+        return (anchor, head);
+    }
+
+    let run = run_around(text, head, same);
+    let on_blank = char_class(text.char(head)) == CharClass::Whitespace;
+
+    if around && !on_blank {
+        around_word(text, run)
+    } else {
+        run
+    }
+}
+
+/// `iw` — the word, punctuation run or blank run under the cursor.
+pub fn select_inner_word(text: &RopeSlice, anchor: usize, head: usize) -> (usize, usize) {
+    word_object(text, anchor, head, same_class, false)
+}
+
+/// `aw` — `iw` plus the blanks after it (or before it, at end of line).
+pub fn select_around_word(text: &RopeSlice, anchor: usize, head: usize) -> (usize, usize) {
+    word_object(text, anchor, head, same_class, true)
+}
+
+/// `iW` — the WORD (non-blank run) under the cursor.
+pub fn select_inner_word_big(text: &RopeSlice, anchor: usize, head: usize) -> (usize, usize) {
+    word_object(text, anchor, head, same_big_class, false)
+}
+
+/// `aW` — `iW` plus surrounding blanks, as for `aw`.
+pub fn select_around_word_big(text: &RopeSlice, anchor: usize, head: usize) -> (usize, usize) {
+    word_object(text, anchor, head, same_big_class, true)
+}
+
+/// Internal: lines `[first, last]` around `line` that share its blankness.
+fn line_run(text: &RopeSlice, line: usize, blank: bool) -> (usize, usize) {
+    let last_line = last_navigable_line(text);
+
+    let mut first = line;
+    while first > 0 && is_blank_line(text, first - 1) == blank {
+        first -= 1;
+    }
+
+    let mut last = line;
+    while last < last_line && is_blank_line(text, last + 1) == blank {
+        last += 1;
+    }
+
+    (first, last)
+}
+
+/// Char offset just past line `line` (start of the next line, or doc end).
+fn line_end_exclusive(text: &RopeSlice, line: usize) -> usize {
+    if line + 1 < text.len_lines() {
+        text.line_to_char(line + 1)
+    } else {
+        text.len_chars()
+    }
+}
+
+/// `ip` — the paragraph (or run of blank lines) under the cursor, linewise.
+pub fn select_inner_paragraph(text: &RopeSlice, _anchor: usize, head: usize) -> (usize, usize) {
+    let line = text.char_to_line(head).min(last_navigable_line(text));
+    let (first, last) = line_run(text, line, is_blank_line(text, line));
+
+    (text.line_to_char(first), line_end_exclusive(text, last))
+}
+
+/// `ap` — `ip` plus the blank lines after it (or the paragraph after a blank run).
+pub fn select_around_paragraph(text: &RopeSlice, _anchor: usize, head: usize) -> (usize, usize) {
+    let last_line = last_navigable_line(text);
+    let line = text.char_to_line(head).min(last_line);
+    let blank = is_blank_line(text, line);
+    let (first, mut last) = line_run(text, line, blank);
+
+    if last < last_line {
+        last = line_run(text, last + 1, !blank).1;
+    }
+
+    (text.line_to_char(first), line_end_exclusive(text, last))
+}
+
+/// Internal: position of the innermost unmatched `open` enclosing `head`.
+/// The cursor sitting on `open` or `close` counts as inside the pair.
+fn enclosing_open(text: &RopeSlice, head: usize, open: char, close: char) -> Option<usize> {
+    if head < text.len_chars() {
+        let c = text.char(head);
+        if c == open {
+            return Some(head);
+        }
+        if c == close {
+            return matching_bracket_pos(text, head);
+        }
+    }
+
+    let mut depth = 0usize;
+    let mut p = head.min(text.len_chars());
+    while p > 0 {
+        p -= 1;
+        let c = text.char(p);
+        if c == close {
+            depth += 1;
+        } else if c == open {
+            if depth == 0 {
+                return Some(p);
+            }
+            depth -= 1;
+        }
+    }
+
+    None
+}
+
+fn pair_object(
+    text: &RopeSlice,
+    anchor: usize,
+    head: usize,
+    (open, close): (char, char),
+    around: bool,
+) -> (usize, usize) {
+    let found = enclosing_open(text, head, open, close)
+        .and_then(|o| matching_bracket_pos(text, o).map(|c| (o, c)));
+
+    match found {
+        Some((o, c)) if around => (o, c + 1),
+        Some((o, c)) => (o + 1, c),
+        None => (anchor, head),
+    }
+}
+
+/// `i(` / `ib` — inside the innermost enclosing parentheses.
+pub fn select_inner_paren(text: &RopeSlice, anchor: usize, head: usize) -> (usize, usize) {
+    pair_object(text, anchor, head, ('(', ')'), false)
+}
+
+/// `a(` / `ab` — the innermost enclosing parentheses, brackets included.
+pub fn select_around_paren(text: &RopeSlice, anchor: usize, head: usize) -> (usize, usize) {
+    pair_object(text, anchor, head, ('(', ')'), true)
+}
+
+/// `i[` — inside the innermost enclosing square brackets.
+pub fn select_inner_bracket(text: &RopeSlice, anchor: usize, head: usize) -> (usize, usize) {
+    pair_object(text, anchor, head, ('[', ']'), false)
+}
+
+/// `a[` — the innermost enclosing square brackets, brackets included.
+pub fn select_around_bracket(text: &RopeSlice, anchor: usize, head: usize) -> (usize, usize) {
+    pair_object(text, anchor, head, ('[', ']'), true)
+}
+
+/// `i{` / `iB` — inside the innermost enclosing braces.
+pub fn select_inner_brace(text: &RopeSlice, anchor: usize, head: usize) -> (usize, usize) {
+    pair_object(text, anchor, head, ('{', '}'), false)
+}
+
+/// `a{` / `aB` — the innermost enclosing braces, braces included.
+pub fn select_around_brace(text: &RopeSlice, anchor: usize, head: usize) -> (usize, usize) {
+    pair_object(text, anchor, head, ('{', '}'), true)
+}
+
+/// `i<` — inside the innermost enclosing angle brackets.
+pub fn select_inner_angle(text: &RopeSlice, anchor: usize, head: usize) -> (usize, usize) {
+    pair_object(text, anchor, head, ('<', '>'), false)
+}
+
+/// `a<` — the innermost enclosing angle brackets, brackets included.
+pub fn select_around_angle(text: &RopeSlice, anchor: usize, head: usize) -> (usize, usize) {
+    pair_object(text, anchor, head, ('<', '>'), true)
+}
+
+/// Internal: the quoted span on the current line containing `head`, or the
+/// first one after it. Quotes pair up left to right; backslash-escaped quotes
+/// are skipped. Returns the positions of the two quote chars.
+fn quote_pair(text: &RopeSlice, head: usize, quote: char) -> Option<(usize, usize)> {
+    let line = text.char_to_line(head.min(text.len_chars()));
+    let start = text.line_to_char(line);
+
+    let mut quotes = Vec::new();
+    let mut escaped = false;
+    for (i, c) in text.line(line).chars().enumerate() {
+        if char_class(c) == CharClass::Eol {
+            break;
+        }
+        if c == quote && !escaped {
+            quotes.push(start + i);
+        }
+        escaped = c == '\\' && !escaped;
+    }
+
+    quotes
+        .chunks_exact(2)
+        .map(|pair| (pair[0], pair[1]))
+        .find(|&(_, close)| head <= close)
+}
+
+fn quote_object(
+    text: &RopeSlice,
+    anchor: usize,
+    head: usize,
+    quote: char,
+    around: bool,
+) -> (usize, usize) {
+    match quote_pair(text, head, quote) {
+        Some((o, c)) if around => (o, c + 1),
+        Some((o, c)) => (o + 1, c),
+        None => (anchor, head),
+    }
+}
+
+/// `i"` — inside the double-quoted string on the current line.
+pub fn select_inner_double_quote(text: &RopeSlice, anchor: usize, head: usize) -> (usize, usize) {
+    quote_object(text, anchor, head, '"', false)
+}
+
+/// `a"` — the double-quoted string on the current line, quotes included.
+pub fn select_around_double_quote(text: &RopeSlice, anchor: usize, head: usize) -> (usize, usize) {
+    quote_object(text, anchor, head, '"', true)
+}
+
+/// `i'` — inside the single-quoted string on the current line.
+pub fn select_inner_single_quote(text: &RopeSlice, anchor: usize, head: usize) -> (usize, usize) {
+    quote_object(text, anchor, head, '\'', false)
+}
+
+/// `a'` — the single-quoted string on the current line, quotes included.
+pub fn select_around_single_quote(text: &RopeSlice, anchor: usize, head: usize) -> (usize, usize) {
+    quote_object(text, anchor, head, '\'', true)
+}
+
+/// `` i` `` — inside the backtick-quoted span on the current line.
+pub fn select_inner_backtick(text: &RopeSlice, anchor: usize, head: usize) -> (usize, usize) {
+    quote_object(text, anchor, head, '`', false)
+}
+
+/// `` a` `` — the backtick-quoted span on the current line, backticks included.
+pub fn select_around_backtick(text: &RopeSlice, anchor: usize, head: usize) -> (usize, usize) {
+    quote_object(text, anchor, head, '`', true)
+}
+
 // ── Tests ────────────────────────────────────────────────────────────────────
 
 #[cfg(test)]
@@ -807,6 +1140,38 @@ mod tests {
         assert_eq!(find_char_backward(&rope("abc\ndef").slice(..), 6, 'a'), 6);
     }
 
+    // ── search ──────────────────────────────────────────────────────────
+
+    #[test]
+    fn search_fwd_skips_match_under_cursor() {
+        assert_eq!(search_forward(&rope("foo bar foo").slice(..), 0, "foo"), 8);
+    }
+
+    #[test]
+    fn search_fwd_wraps() {
+        assert_eq!(search_forward(&rope("foo bar foo").slice(..), 8, "foo"), 0);
+    }
+
+    #[test]
+    fn search_fwd_miss_stays() {
+        assert_eq!(search_forward(&rope("foo bar").slice(..), 2, "baz"), 2);
+    }
+
+    #[test]
+    fn search_fwd_invalid_pattern_stays() {
+        assert_eq!(search_forward(&rope("foo (bar").slice(..), 0, "("), 0);
+    }
+
+    #[test]
+    fn search_bwd_finds_previous() {
+        assert_eq!(search_backward(&rope("foo bar foo").slice(..), 8, "foo"), 0);
+    }
+
+    #[test]
+    fn search_bwd_wraps() {
+        assert_eq!(search_backward(&rope("foo bar foo").slice(..), 0, "foo"), 8);
+    }
+
     // ── match_bracket ───────────────────────────────────────────────────
 
     #[test]
@@ -891,6 +1256,102 @@ mod tests {
         assert_eq!(move_paragraph_backward(&rope("aaa\n\nbbb").slice(..), 7), 4);
     }
 
+    // ── text objects ───────────────────────────────────────────────────
+
+    #[test]
+    fn inner_word_selects_run() {
+        let t = rope("foo bar.baz");
+        assert_eq!(select_inner_word(&t.slice(..), 5, 5), (4, 7));
+        assert_eq!(select_inner_word(&t.slice(..), 7, 7), (7, 8));
+    }
+
+    #[test]
+    fn inner_word_on_blank_selects_blank_run() {
+        assert_eq!(select_inner_word(&rope("a   b").slice(..), 2, 2), (1, 4));
+    }
+
+    #[test]
+    fn around_word_takes_trailing_blanks() {
+        assert_eq!(
+            select_around_word(&rope("foo  bar").slice(..), 1, 1),
+            (0, 5)
+        );
+    }
+
+    #[test]
+    fn around_word_at_line_end_takes_leading_blanks() {
+        assert_eq!(
+            select_around_word(&rope("foo bar\n").slice(..), 5, 5),
+            (3, 7)
+        );
+    }
+
+    #[test]
+    fn inner_word_big_spans_punct() {
+        assert_eq!(
+            select_inner_word_big(&rope("a foo.bar b").slice(..), 3, 3),
+            (2, 9)
+        );
+    }
+
+    #[test]
+    fn word_object_on_empty_line_is_noop() {
+        assert_eq!(select_inner_word(&rope("a\n\nb").slice(..), 2, 2), (2, 2));
+    }
+
+    #[test]
+    fn inner_paragraph_is_linewise() {
+        let t = rope("a\nb\n\nc\n");
+        assert_eq!(select_inner_paragraph(&t.slice(..), 0, 2), (0, 4));
+    }
+
+    #[test]
+    fn around_paragraph_takes_trailing_blank_lines() {
+        let t = rope("a\nb\n\n\nc\n");
+        assert_eq!(select_around_paragraph(&t.slice(..), 0, 0), (0, 6));
+    }
+
+    #[test]
+    fn inner_paren_from_inside() {
+        let t = rope("f(a, (b), c)");
+        assert_eq!(select_inner_paren(&t.slice(..), 3, 3), (2, 11));
+        assert_eq!(select_inner_paren(&t.slice(..), 6, 6), (6, 7));
+    }
+
+    #[test]
+    fn around_paren_on_bracket() {
+        let t = rope("x (ab) y");
+        assert_eq!(select_around_paren(&t.slice(..), 2, 2), (2, 6));
+        assert_eq!(select_around_paren(&t.slice(..), 5, 5), (2, 6));
+    }
+
+    #[test]
+    fn pair_object_miss_is_noop() {
+        assert_eq!(
+            select_inner_brace(&rope("no braces").slice(..), 1, 3),
+            (1, 3)
+        );
+    }
+
+    #[test]
+    fn inner_quote_containing_cursor() {
+        let t = rope(r#"say "hi there" now"#);
+        assert_eq!(select_inner_double_quote(&t.slice(..), 6, 6), (5, 13));
+        assert_eq!(select_around_double_quote(&t.slice(..), 6, 6), (4, 14));
+    }
+
+    #[test]
+    fn quote_object_seeks_forward_on_line() {
+        let t = rope(r#"x = 'a' + 'b'"#);
+        assert_eq!(select_inner_single_quote(&t.slice(..), 0, 0), (5, 6));
+    }
+
+    #[test]
+    fn quote_object_skips_escaped_quotes() {
+        let t = rope(r#""a\"b""#);
+        assert_eq!(select_inner_double_quote(&t.slice(..), 1, 1), (1, 5));
+    }
+
     // ── selection-shape ────────────────────────────────────────────────
 
     #[test]
@@ -9,8 +9,9 @@
 //! threading where a pre-edit position lands post-edit.
 
 use ropey::Rope;
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Mutation {
     p: usize,
     q: usize,
@@ -24,6 +25,18 @@ impl Mutation {
         Self { p, q, t }
     }
 
+    pub fn p(&self) -> usize {
+        self.p
+    }
+
+    pub fn q(&self) -> usize {
+        self.q
+    }
+
+    pub fn t(&self) -> &str {
+        &self.t
+    }
+
     /// Splice: remove `[p, q)` and insert `t` at `p`. Returns the removed
     /// text `d` so the caller can build the inverse with `invert(d)`.
     pub fn apply(&self, rope: &mut Rope) -> Mutation {
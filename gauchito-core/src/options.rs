@@ -1,3 +1,7 @@
+use encoding_rs::{Encoding, UTF_8};
+
+use crate::editorconfig::EditorConfigRules;
+
 /// Line ending style for a file on disk.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum LineEnding {
@@ -14,6 +18,29 @@ impl LineEnding {
     }
 }
 
+/// What one level of indentation inserts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndentStyle {
+    Tabs,
+    Spaces(usize),
+}
+
+impl IndentStyle {
+    /// The text of one indent level.
+    pub fn unit(self) -> String {
+        match self {
+            IndentStyle::Tabs => "\t".to_string(),
+            IndentStyle::Spaces(n) => " ".repeat(n),
+        }
+    }
+}
+
+impl Default for IndentStyle {
+    fn default() -> Self {
+        IndentStyle::Spaces(4)
+    }
+}
+
 /// Resolved file-format options stored on `Document`, applied at save time.
 #[derive(Debug, Clone)]
 pub struct DocumentOptions {
@@ -21,6 +48,14 @@ pub struct DocumentOptions {
     pub final_newline: bool,
     pub bom: bool,
     pub trim_trailing_whitespace: bool,
+    pub indent_style: IndentStyle,
+    /// Display width of a `\t`.
+    pub tab_width: usize,
+    /// Encoding on disk. The rope is always UTF-8; this is what load
+    /// decoded from and what save encodes to.
+    pub encoding: &'static Encoding,
+    /// Soft limit for renderers (a ruler); never enforced on edit or save.
+    pub max_line_length: Option<usize>,
 }
 
 impl Default for DocumentOptions {
@@ -30,27 +65,77 @@ impl Default for DocumentOptions {
             final_newline: true,
             bom: false,
             trim_trailing_whitespace: false,
+            indent_style: IndentStyle::default(),
+            tab_width: 4,
+            encoding: UTF_8,
+            max_line_length: None,
+        }
+    }
+}
+
+/// What a file's own bytes say about its format.
+#[derive(Debug, Clone)]
+pub(crate) struct Sniffed {
+    pub line_ending: LineEnding,
+    pub final_newline: bool,
+    pub bom: bool,
+    pub encoding: &'static Encoding,
+    /// Inferred from existing indentation; `None` if nothing is indented.
+    pub indent: Option<IndentStyle>,
+    /// False for binary files and bytes the encoding couldn't represent
+    /// losslessly: saving would change the file.
+    pub lossless: bool,
+}
+
+impl Default for Sniffed {
+    /// A file that doesn't exist yet.
+    fn default() -> Self {
+        Self {
+            line_ending: LineEnding::Lf,
+            final_newline: true,
+            bom: false,
+            encoding: UTF_8,
+            indent: None,
+            lossless: true,
         }
     }
 }
 
 // TODO: this should be a responsability of file IO
 impl DocumentOptions {
-    /// Merge sniffed file properties with overrides.
+    /// Merge sniffed file properties with `.editorconfig` overrides.
     /// Overrides take precedence; anything unspecified falls back to what was observed.
-    pub(crate) fn resolve(
-        line_ending: LineEnding,
-        final_newline: bool,
-        bom: bool,
-        override_line_ending: Option<LineEnding>,
-        override_final_newline: Option<bool>,
-        override_trim_trailing_ws: Option<bool>,
-    ) -> Self {
+    pub(crate) fn resolve(sniffed: Sniffed, rules: EditorConfigRules) -> Self {
+        let defaults = DocumentOptions::default();
+
+        let tabs = rules
+            .indent_tabs
+            .unwrap_or(sniffed.indent == Some(IndentStyle::Tabs));
+        let indent_style = if tabs {
+            IndentStyle::Tabs
+        } else {
+            let inferred = match sniffed.indent {
+                Some(IndentStyle::Spaces(n)) => Some(n),
+                _ => None,
+            };
+            IndentStyle::Spaces(rules.indent_size.or(inferred).unwrap_or(4))
+        };
+
+        // `tab_width` defaults to `indent_size`, per the editorconfig spec.
+        let tab_width = rules
+            .tab_width
+            .or(rules.indent_size)
+            .unwrap_or(defaults.tab_width);
+
         Self {
-            line_ending: override_line_ending.unwrap_or(line_ending),
-            final_newline: override_final_newline.unwrap_or(final_newline),
-            bom,
-            trim_trailing_whitespace: override_trim_trailing_ws.unwrap_or(false),
+            line_ending: rules.line_ending.unwrap_or(sniffed.line_ending),
+            final_newline: rules.final_newline.unwrap_or(sniffed.final_newline),
+            bom: rules.bom.unwrap_or(sniffed.bom),
+            trim_trailing_whitespace: rules.trim_trailing_whitespace.unwrap_or(false),
+            indent_style,
+            tab_width,
+            encoding: rules.encoding.unwrap_or(sniffed.encoding),
+            max_line_length: rules.max_line_length,
         }
     }
 }
@@ -0,0 +1,412 @@
+//! Registers: named slots holding yanked and deleted text.
+//!
+//! Names follow vim:
+//!
+//! - `"` unnamed — whatever was yanked or deleted last
+//! - `a`–`z` named; writing `A`–`Z` appends to the lowercase one
+//! - `0` the last yank, `1`–`9` deletes, newest first
+//! - `-` the last delete within one line, which skips `1`–`9`
+//! - `.` the text typed in the last insert; read-only
+//! - `_` black hole; writes are dropped
+//! - `+` / `*` the system clipboard and primary selection (see
+//!   [`crate::clipboard`])
+//!
+//! A register holds one entry per cursor that filled it, so a multi-cursor
+//! yank can be put back one entry per cursor. The named ones, which also
+//! hold macros, are kept between sessions as JSON ([`Registers::write_named`]).
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+use ropey::Rope;
+use serde::{Deserialize, Serialize};
+
+use crate::clipboard::{Clipboard, Provider, Target};
+use crate::fileio;
+use crate::history::Transaction;
+use crate::mutation::phi;
+
+/// Deletes kept in `1`–`9`.
+const DELETE_HISTORY: usize = 9;
+
+const VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Register {
+    /// One per cursor, in selection order.
+    pub entries: Vec<String>,
+    /// Whole lines: put goes on its own line instead of at the cursor.
+    pub linewise: bool,
+}
+
+impl Register {
+    pub fn new(entries: Vec<String>, linewise: bool) -> Self {
+        Register { entries, linewise }
+    }
+
+    /// Every entry as one text, for putting at a single cursor. Linewise
+    /// entries already end in a newline; others are joined with one.
+    pub fn joined(&self) -> String {
+        if self.linewise {
+            self.entries.concat()
+        } else {
+            self.entries.join("\n")
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.iter().all(String::is_empty)
+    }
+
+    /// No entry reaches past the end of its line.
+    fn is_within_line(&self) -> bool {
+        !self.linewise && !self.entries.iter().any(|e| e.contains('\n'))
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct RegisterFile {
+    version: u32,
+    named: BTreeMap<char, Register>,
+}
+
+/// Why text is being written, which decides the numbered register it lands in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WriteKind {
+    Yank,
+    Delete,
+}
+
+#[derive(Debug, Default)]
+pub struct Registers {
+    unnamed: Register,
+    named: HashMap<char, Register>,
+    yanked: Register,
+    deleted: VecDeque<Register>,
+    small_delete: Register,
+    last_insert: Register,
+    clipboard: Clipboard,
+}
+
+impl Registers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_clipboard(&mut self, provider: Provider) {
+        self.clipboard = Clipboard::new(provider);
+    }
+
+    /// The register called `name`, asking the clipboard for `+` and `*`.
+    pub fn read(&self, name: char) -> io::Result<Option<Register>> {
+        match Target::of_register(name) {
+            Some(target) => self.clipboard.paste(target),
+            None => Ok(self.get(name).cloned()),
+        }
+    }
+
+    /// The register called `name`, if it has ever been written. `+` and `*`
+    /// live outside; see [`Registers::read`].
+    pub fn get(&self, name: char) -> Option<&Register> {
+        let found = match name {
+            '"' => &self.unnamed,
+            'a'..='z' | 'A'..='Z' => self.named.get(&name.to_ascii_lowercase())?,
+            '0' => &self.yanked,
+            '1'..='9' => self.deleted.get(name as usize - '1' as usize)?,
+            '-' => &self.small_delete,
+            '.' => &self.last_insert,
+            _ => return None,
+        };
+        (!found.is_empty()).then_some(found)
+    }
+
+    /// Store `value` in `name` (`"` for the default), and in the unnamed
+    /// register unless it's the black hole. Fails for names that can't be
+    /// written and when the clipboard refuses the text.
+    pub fn write(&mut self, name: char, kind: WriteKind, value: Register) -> io::Result<()> {
+        match name {
+            '_' => return Ok(()),
+            '"' => match kind {
+                WriteKind::Yank => self.yanked = value.clone(),
+                WriteKind::Delete if value.is_within_line() => {
+                    self.small_delete = value.clone();
+                }
+                WriteKind::Delete => {
+                    self.deleted.push_front(value.clone());
+                    self.deleted.truncate(DELETE_HISTORY);
+                }
+            },
+            'a'..='z' => {
+                self.named.insert(name, value.clone());
+            }
+            'A'..='Z' => {
+                let reg = self.named.entry(name.to_ascii_lowercase()).or_default();
+                // Appending lines to characters makes the whole thing lines.
+                if value.linewise && !reg.linewise && !reg.entries.is_empty() {
+                    for entry in &mut reg.entries {
+                        entry.push('\n');
+                    }
+                }
+                reg.linewise |= value.linewise;
+                if reg.entries.len() == value.entries.len() {
+                    for (entry, more) in reg.entries.iter_mut().zip(&value.entries) {
+                        entry.push_str(more);
+                    }
+                } else {
+                    reg.entries = vec![reg.joined() + &value.joined()];
+                }
+                self.unnamed = reg.clone();
+                return Ok(());
+            }
+            '+' | '*' => {
+                let target = Target::of_register(name).unwrap();
+                self.clipboard.copy(target, value.clone())?;
+            }
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("register {name} can't be written"),
+                ));
+            }
+        }
+        self.unnamed = value;
+        Ok(())
+    }
+
+    /// Store a recorded macro in `name`, leaving the unnamed register alone.
+    pub fn record(&mut self, name: char, text: String) -> io::Result<()> {
+        let unnamed = self.unnamed.clone();
+        self.write(name, WriteKind::Yank, Register::new(vec![text], false))?;
+        self.unnamed = unnamed;
+        Ok(())
+    }
+
+    /// Save `a`–`z` to `path`.
+    pub fn write_named(&self, path: &Path) -> io::Result<()> {
+        let file = RegisterFile {
+            version: VERSION,
+            named: self
+                .named
+                .iter()
+                .filter(|(_, reg)| !reg.is_empty())
+                .map(|(&name, reg)| (name, reg.clone()))
+                .collect(),
+        };
+        let json = serde_json::to_vec(&file).map_err(io::Error::other)?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        // Only we may read them: yanks can hold passwords.
+        fileio::write_private(path, |f| f.write_all(&json))
+    }
+
+    /// Restore `a`–`z` from `path`, keeping any written since. A missing
+    /// file or one in a newer format leaves them as they are.
+    pub fn read_named(&mut self, path: &Path) -> io::Result<()> {
+        let json = match fs::read(path) {
+            Ok(json) => json,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        let file: RegisterFile = serde_json::from_slice(&json).map_err(io::Error::other)?;
+        if file.version != VERSION {
+            return Ok(());
+        }
+        for (name, reg) in file.named {
+            if name.is_ascii_lowercase() {
+                self.named.entry(name).or_insert(reg);
+            }
+        }
+        Ok(())
+    }
+
+    /// Record what the last insert typed, for `.`.
+    pub fn set_last_insert(&mut self, text: String) {
+        self.last_insert = Register::new(vec![text], false);
+    }
+}
+
+/// What `txn` typed at its primary cursor, read back from `text` (the
+/// document after it): the span from where the cursor started, held left of
+/// insertions there, to where the typing ended, pushed right by them.
+pub fn inserted_text(txn: &Transaction, text: &Rope) -> Option<String> {
+    let before = txn.selection_before.as_ref()?;
+    let head = before.ranges.get(before.primary)?.1;
+
+    let (mut start, mut end) = (head, head);
+    for m in &txn.mutations {
+        start = if start <= m.p() {
+            start
+        } else if start <= m.q() {
+            m.p()
+        } else {
+            phi(m, start)
+        };
+        end = phi(m, end);
+    }
+
+    let end = end.min(text.len_chars());
+    (start < end).then(|| text.slice(start..end).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::history::SelectionSnapshot;
+    use crate::mutation::Mutation;
+
+    fn chars(text: &str) -> Register {
+        Register::new(vec![text.to_string()], false)
+    }
+
+    #[test]
+    fn yank_and_delete_history() {
+        let mut regs = Registers::new();
+        regs.write('"', WriteKind::Yank, chars("y")).unwrap();
+        regs.write('"', WriteKind::Delete, chars("d1\n")).unwrap();
+        regs.write('"', WriteKind::Delete, chars("d\n2")).unwrap();
+
+        assert_eq!(regs.get('0'), Some(&chars("y")));
+        assert_eq!(regs.get('1'), Some(&chars("d\n2")));
+        assert_eq!(regs.get('2'), Some(&chars("d1\n")));
+        assert_eq!(regs.get('"'), Some(&chars("d\n2")));
+        assert_eq!(regs.get('3'), None);
+    }
+
+    #[test]
+    fn small_deletes_skip_the_history() {
+        let mut regs = Registers::new();
+        regs.write('"', WriteKind::Delete, chars("line\n")).unwrap();
+        regs.write('"', WriteKind::Delete, chars("word")).unwrap();
+
+        assert_eq!(regs.get('-'), Some(&chars("word")));
+        assert_eq!(regs.get('"'), Some(&chars("word")));
+        assert_eq!(regs.get('1'), Some(&chars("line\n")));
+        assert_eq!(regs.get('2'), None);
+
+        let lines = Register::new(vec!["whole".into()], true);
+        regs.write('"', WriteKind::Delete, lines.clone()).unwrap();
+        assert_eq!(regs.get('1'), Some(&lines));
+        assert_eq!(regs.get('-'), Some(&chars("word")));
+    }
+
+    #[test]
+    fn named_append_and_special_names() {
+        let mut regs = Registers::new();
+        regs.write('a', WriteKind::Yank, chars("one")).unwrap();
+        regs.write('A', WriteKind::Yank, chars(" two")).unwrap();
+        assert_eq!(regs.get('a'), Some(&chars("one two")));
+        assert_eq!(regs.get('"'), Some(&chars("one two")));
+        assert_eq!(regs.get('0'), None);
+
+        regs.write(
+            'A',
+            WriteKind::Yank,
+            Register::new(vec!["line\n".into()], true),
+        )
+        .unwrap();
+        assert_eq!(regs.get('a').unwrap().joined(), "one two\nline\n");
+
+        regs.write('_', WriteKind::Delete, chars("gone")).unwrap();
+        assert_eq!(
+            regs.get('"'),
+            Some(&Register::new(vec!["one two\nline\n".into()], true))
+        );
+        assert!(regs.write('.', WriteKind::Yank, chars("no")).is_err());
+        assert!(regs.write('%', WriteKind::Yank, chars("no")).is_err());
+    }
+
+    #[test]
+    fn per_cursor_entries() {
+        let mut regs = Registers::new();
+        regs.write(
+            'b',
+            WriteKind::Yank,
+            Register::new(vec!["x".into(), "y".into()], false),
+        )
+        .unwrap();
+        regs.write(
+            'B',
+            WriteKind::Yank,
+            Register::new(vec!["1".into(), "2".into()], false),
+        )
+        .unwrap();
+        assert_eq!(regs.get('b').unwrap().entries, vec!["x1", "y2"]);
+        assert_eq!(regs.get('b').unwrap().joined(), "x1\ny2");
+    }
+
+    #[test]
+    fn clipboard_registers() {
+        let mut regs = Registers::new();
+        regs.write('+', WriteKind::Yank, chars("out")).unwrap();
+        assert_eq!(regs.read('+').unwrap(), Some(chars("out")));
+        assert_eq!(regs.read('*').unwrap(), None);
+        assert_eq!(regs.get('"'), Some(&chars("out")));
+        // Not a yank into `0`.
+        assert_eq!(regs.get('0'), None);
+    }
+
+    #[test]
+    fn named_registers_persist() {
+        let dir = std::env::temp_dir().join(format!("gauchito-regs-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let path = dir.join("registers.json");
+
+        let mut regs = Registers::new();
+        regs.read_named(&path).unwrap();
+        regs.write('"', WriteKind::Yank, chars("not kept")).unwrap();
+        regs.record('q', "dw<esc>".into()).unwrap();
+        assert_eq!(regs.get('"'), Some(&chars("not kept")));
+        regs.write_named(&path).unwrap();
+
+        let mut next = Registers::new();
+        next.write('x', WriteKind::Yank, chars("newer")).unwrap();
+        next.read_named(&path).unwrap();
+        assert_eq!(next.get('q'), Some(&chars("dw<esc>")));
+        assert_eq!(next.get('x'), Some(&chars("newer")));
+        assert_eq!(next.get('0'), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn only_the_owner_reads_them() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("gauchito-regs-mode-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let path = dir.join("registers.json");
+
+        let mut regs = Registers::new();
+        regs.write('a', WriteKind::Yank, chars("password")).unwrap();
+        regs.write_named(&path).unwrap();
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    #[test]
+    fn typed_text_of_an_insert() {
+        // "ab|" → type "xyz", backspace once → "abxy".
+        let mut text = Rope::from_str("ab");
+        let mutations = vec![
+            Mutation::new(2, 2, "x".into()),
+            Mutation::new(3, 3, "y".into()),
+            Mutation::new(4, 4, "z".into()),
+            Mutation::new(4, 5, String::new()),
+        ];
+        for m in &mutations {
+            m.apply(&mut text);
+        }
+        let txn = Transaction {
+            mutations,
+            inverses: Vec::new(),
+            selection_before: Some(SelectionSnapshot {
+                ranges: vec![(2, 2)],
+                primary: 0,
+            }),
+            selection_after: None,
+        };
+        assert_eq!(inserted_text(&txn, &text).as_deref(), Some("xy"));
+    }
+}
@@ -0,0 +1,404 @@
+//! Regex search over a [`RopeSlice`], streamed chunk by chunk.
+//!
+//! The rope is never flattened into a `String`. A lazy DFA from
+//! `regex-automata` is fed one byte at a time across chunk boundaries: a
+//! forward pass finds where the leftmost match ends, then an anchored reverse
+//! pass from that end finds where it starts (the same two-pass strategy the
+//! `regex` crate uses internally).
+//!
+//! The lazy DFA gives up on Unicode word boundaries (`\b`) once it sees a
+//! non-ASCII byte. Those searches are redone with the `meta` regex engine
+//! over the text as one string, so they're slower but never miss. That
+//! string is made at most once per search, however many matches it takes.
+//!
+//! Patterns are compiled in multi-line mode, so `^` / `$` match at line
+//! boundaries. All offsets in and out are char offsets.
+
+use std::borrow::Cow;
+use std::cell::{OnceCell, RefCell};
+use std::rc::Rc;
+
+use regex_automata::hybrid::dfa::{self, DFA};
+use regex_automata::hybrid::regex::{Cache, Regex};
+use regex_automata::util::start;
+use regex_automata::util::syntax;
+use regex_automata::{Anchored, Input, meta};
+use ropey::RopeSlice;
+
+/// A compiled search pattern.
+pub struct Pattern {
+    source: String,
+    regex: Regex,
+    /// Lazy DFA state, kept from one search to the next.
+    cache: RefCell<Cache>,
+    /// For searches the lazy DFA quits on; built the first time one does.
+    fallback: OnceCell<Option<meta::Regex>>,
+}
+
+/// The DFA met a byte it can't handle; the search has to be redone.
+struct Quit;
+
+/// The text as one string, made the first time a search falls back to the
+/// `meta` engine and kept for the rest of that search.
+type Flat<'a> = OnceCell<Cow<'a, str>>;
+
+thread_local! {
+    /// The pattern compiled last. Kernels are called with the same pattern
+    /// for every cursor, keystroke and `n`, so one entry is enough.
+    static LAST: RefCell<Option<Rc<Pattern>>> = const { RefCell::new(None) };
+}
+
+impl Pattern {
+    /// Compile `pattern`. Returns `None` if it isn't a valid regex — callers
+    /// typically treat that as "no match" while the user is still typing.
+    pub fn new(pattern: &str) -> Option<Self> {
+        let regex = Regex::builder()
+            .syntax(syntax::Config::new().multi_line(true))
+            .dfa(dfa::Config::new().unicode_word_boundary(true))
+            .build(pattern)
+            .ok()?;
+
+        Some(Self {
+            source: pattern.to_string(),
+            cache: RefCell::new(regex.create_cache()),
+            regex,
+            fallback: OnceCell::new(),
+        })
+    }
+
+    /// Like [`Pattern::new`], but reuses the last pattern compiled this way
+    /// when `pattern` is the same.
+    pub fn cached(pattern: &str) -> Option<Rc<Self>> {
+        LAST.with_borrow_mut(|last| {
+            if let Some(pat) = last
+                && pat.source == pattern
+            {
+                return Some(pat.clone());
+            }
+            let pat = Rc::new(Pattern::new(pattern)?);
+            *last = Some(pat.clone());
+            Some(pat)
+        })
+    }
+
+    /// Leftmost match starting at or after `from`, as a `(start, end)` char range.
+    pub fn find_forward(&self, text: &RopeSlice, from: usize) -> Option<(usize, usize)> {
+        let from = text.char_to_byte(from.min(text.len_chars()));
+        let (start, end) = self.find_bytes(text, from, &Flat::new())?;
+
+        Some((text.byte_to_char(start), text.byte_to_char(end)))
+    }
+
+    /// Last match starting strictly before `before`, as a `(start, end)` char range.
+    ///
+    /// Scans forward from a line start some distance above `before`, doubling
+    /// the window until a match is found or the document start is reached.
+    /// Windows start on line boundaries, so matches never begin mid-way
+    /// through a longer match that started above the window.
+    pub fn find_backward(&self, text: &RopeSlice, before: usize) -> Option<(usize, usize)> {
+        let before = text.char_to_byte(before.min(text.len_chars()));
+        let last_line = text.byte_to_line(before);
+        let mut span = 1;
+        let flat = Flat::new();
+
+        loop {
+            let first_line = last_line.saturating_sub(span);
+            let window = text.line_to_byte(first_line);
+
+            let mut found = None;
+            let mut at = window;
+            while let Some((start, end)) = self.find_bytes(text, at, &flat) {
+                if start >= before {
+                    break;
+                }
+                found = Some((start, end));
+                at = if end > start {
+                    end
+                } else {
+                    next_char_byte(text, start)
+                };
+                if at > text.len_bytes() {
+                    break;
+                }
+            }
+
+            if let Some((start, end)) = found {
+                return Some((text.byte_to_char(start), text.byte_to_char(end)));
+            }
+            if first_line == 0 {
+                return None;
+            }
+            span *= 2;
+        }
+    }
+
+    /// Byte-offset search: leftmost match with `start >= from`.
+    fn find_bytes<'a>(
+        &self,
+        text: &RopeSlice<'a>,
+        from: usize,
+        flat: &Flat<'a>,
+    ) -> Option<(usize, usize)> {
+        let mut cache = self.cache.borrow_mut();
+        let (fwd_cache, rev_cache) = cache.as_parts_mut();
+
+        let found = find_end(self.regex.forward(), fwd_cache, text, from).and_then(|end| {
+            let Some(end) = end else { return Ok(None) };
+            let start = find_start(self.regex.reverse(), rev_cache, text, from, end)?;
+            Ok(start.map(|start| (start, end)))
+        });
+        match found {
+            Ok(found) => found,
+            Err(Quit) => self.find_bytes_slow(text, from, flat),
+        }
+    }
+
+    /// [`Pattern::find_bytes`] without the lazy DFA, on the flattened text.
+    fn find_bytes_slow<'a>(
+        &self,
+        text: &RopeSlice<'a>,
+        from: usize,
+        flat: &Flat<'a>,
+    ) -> Option<(usize, usize)> {
+        let regex = self
+            .fallback
+            .get_or_init(|| {
+                meta::Regex::builder()
+                    .syntax(syntax::Config::new().multi_line(true))
+                    .build(&self.source)
+                    .ok()
+            })
+            .as_ref()?;
+        let haystack = flat.get_or_init(|| (*text).into());
+        let m = regex.search(&Input::new(haystack.as_bytes()).range(from..))?;
+        Some((m.start(), m.end()))
+    }
+}
+
+/// Forward pass: walk bytes from `from` until the DFA dies, remembering the
+/// last match position. Matches are reported one byte late by the DFA, so a
+/// match state seen after consuming the byte at `at` means a match ends at `at`.
+fn find_end(
+    dfa: &DFA,
+    cache: &mut dfa::Cache,
+    text: &RopeSlice,
+    from: usize,
+) -> Result<Option<usize>, Quit> {
+    let look_behind = from.checked_sub(1).map(|b| text.byte(b));
+    let config = start::Config::new()
+        .anchored(Anchored::No)
+        .look_behind(look_behind);
+    let mut sid = dfa.start_state(cache, &config).map_err(|_| Quit)?;
+    let mut last = None;
+
+    if from < text.len_bytes() {
+        let (chunks, chunk_start, _, _) = text.chunks_at_byte(from);
+        let mut at = chunk_start;
+
+        for chunk in chunks {
+            for &b in chunk.as_bytes() {
+                if at >= from {
+                    sid = dfa.next_state(cache, sid, b).map_err(|_| Quit)?;
+                    if sid.is_tagged() {
+                        if sid.is_match() {
+                            last = Some(at);
+                        } else if sid.is_dead() {
+                            return Ok(last);
+                        } else if sid.is_quit() {
+                            return Err(Quit);
+                        }
+                    }
+                }
+                at += 1;
+            }
+        }
+    }
+
+    sid = dfa.next_eoi_state(cache, sid).map_err(|_| Quit)?;
+    if sid.is_match() {
+        last = Some(text.len_bytes());
+    }
+
+    Ok(last)
+}
+
+/// Reverse pass: anchored at `end`, walk bytes backward down to `from`,
+/// remembering the earliest position the match could start.
+fn find_start(
+    dfa: &DFA,
+    cache: &mut dfa::Cache,
+    text: &RopeSlice,
+    from: usize,
+    end: usize,
+) -> Result<Option<usize>, Quit> {
+    let look_ahead = (end < text.len_bytes()).then(|| text.byte(end));
+    let config = start::Config::new()
+        .anchored(Anchored::Yes)
+        .look_behind(look_ahead);
+    let mut sid = dfa.start_state(cache, &config).map_err(|_| Quit)?;
+    let mut last = None;
+
+    if end > from {
+        let (mut chunks, chunk_start, _, _) = text.chunks_at_byte(end);
+        let mut chunk_end = text.len_bytes();
+
+        // `chunks_at_byte` positions the iterator before the chunk containing
+        // `end`. Step over it once so the `prev()` walk below includes it.
+        if end < text.len_bytes() {
+            let chunk = chunks.next().unwrap_or("");
+            chunk_end = chunk_start + chunk.len();
+        }
+
+        'outer: while let Some(chunk) = chunks.prev() {
+            let chunk_start = chunk_end - chunk.len();
+            for (i, &b) in chunk.as_bytes().iter().enumerate().rev() {
+                let at = chunk_start + i;
+                if at >= end {
+                    continue;
+                }
+                if at < from {
+                    break 'outer;
+                }
+                sid = dfa.next_state(cache, sid, b).map_err(|_| Quit)?;
+                if sid.is_tagged() {
+                    if sid.is_match() {
+                        last = Some(at + 1);
+                    } else if sid.is_dead() {
+                        return Ok(last);
+                    } else if sid.is_quit() {
+                        return Err(Quit);
+                    }
+                }
+            }
+            chunk_end = chunk_start;
+        }
+    }
+
+    sid = if from > 0 {
+        dfa.next_state(cache, sid, text.byte(from - 1))
+    } else {
+        dfa.next_eoi_state(cache, sid)
+    }
+    .map_err(|_| Quit)?;
+    if sid.is_quit() {
+        return Err(Quit);
+    }
+    if sid.is_match() {
+        last = Some(from);
+    }
+
+    Ok(last)
+}
+
+fn next_char_byte(text: &RopeSlice, byte: usize) -> usize {
+    if byte >= text.len_bytes() {
+        return byte + 1;
+    }
+    let c = text.byte_to_char(byte);
+    text.char_to_byte(c + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ropey::Rope;
+
+    fn fwd(s: &str, pat: &str, from: usize) -> Option<(usize, usize)> {
+        let rope = Rope::from_str(s);
+        Pattern::new(pat)
+            .unwrap()
+            .find_forward(&rope.slice(..), from)
+    }
+
+    fn bwd(s: &str, pat: &str, before: usize) -> Option<(usize, usize)> {
+        let rope = Rope::from_str(s);
+        Pattern::new(pat)
+            .unwrap()
+            .find_backward(&rope.slice(..), before)
+    }
+
+    #[test]
+    fn forward_literal() {
+        assert_eq!(fwd("hello world", "wor", 0), Some((6, 9)));
+    }
+
+    #[test]
+    fn forward_respects_from() {
+        assert_eq!(fwd("abc abc", "abc", 1), Some((4, 7)));
+    }
+
+    #[test]
+    fn forward_miss() {
+        assert_eq!(fwd("hello", "xyz", 0), None);
+    }
+
+    #[test]
+    fn forward_leftmost_first() {
+        assert_eq!(fwd("foobar", "foo|foobar", 0), Some((0, 3)));
+        assert_eq!(fwd("aaa", "a+", 0), Some((0, 3)));
+    }
+
+    #[test]
+    fn forward_multi_line_anchors() {
+        assert_eq!(fwd("ab\ncd\n", "^c", 0), Some((3, 4)));
+        assert_eq!(fwd("ab\ncd\n", "b$", 0), Some((1, 2)));
+    }
+
+    #[test]
+    fn forward_counts_chars_not_bytes() {
+        assert_eq!(fwd("héllo wörld", "w.r", 0), Some((6, 9)));
+    }
+
+    #[test]
+    fn forward_across_chunks() {
+        // Long enough that ropey splits it into several chunks.
+        let mut s = "x".repeat(5000);
+        s.push_str("needle");
+        s.push_str(&"y".repeat(5000));
+        assert_eq!(fwd(&s, "needle", 0), Some((5000, 5006)));
+        assert_eq!(fwd(&s, "x+needle", 4990), Some((4990, 5006)));
+    }
+
+    #[test]
+    fn backward_finds_last_before() {
+        assert_eq!(bwd("abc abc abc", "abc", 8), Some((4, 7)));
+        assert_eq!(bwd("abc abc abc", "abc", 9), Some((8, 11)));
+    }
+
+    #[test]
+    fn backward_across_lines() {
+        let s = "foo\nbar\nbaz\nqux\n";
+        assert_eq!(bwd(s, "foo", 14), Some((0, 3)));
+    }
+
+    #[test]
+    fn backward_miss() {
+        assert_eq!(bwd("hello", "hello", 0), None);
+    }
+
+    #[test]
+    fn invalid_pattern_is_none() {
+        assert!(Pattern::new("(").is_none());
+    }
+
+    #[test]
+    fn word_boundary() {
+        assert_eq!(fwd("cat concat cat", r"\bcat\b", 1), Some((11, 14)));
+    }
+
+    #[test]
+    fn word_boundary_past_non_ascii() {
+        assert_eq!(fwd("añ foo é", r"\bfoo\b", 0), Some((3, 6)));
+        assert_eq!(fwd("ñfoo foo", r"\bfoo\b", 0), Some((5, 8)));
+        assert_eq!(bwd("foo é foo ñ", r"\bfoo\b", 11), Some((6, 9)));
+    }
+
+    #[test]
+    fn cached_pattern_is_reused() {
+        let a = Pattern::cached("ab+").unwrap();
+        let b = Pattern::cached("ab+").unwrap();
+        assert!(Rc::ptr_eq(&a, &b));
+        assert!(!Rc::ptr_eq(&a, &Pattern::cached("ab*").unwrap()));
+        assert!(Pattern::cached("(").is_none());
+    }
+}
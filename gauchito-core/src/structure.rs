@@ -0,0 +1,421 @@
+//! Structural selection kernels over the syntax tree.
+//!
+//! Same shape as the selection kernels in [`movement`](crate::movement):
+//! `fn(&SyntaxTree, &RopeSlice, anchor, head) -> (anchor, head)`, with the
+//! selection read as the half-open range between anchor and head. Results are
+//! forward half-open node ranges; on a miss the input is returned unchanged.
+//!
+//! All of them are stateless, so they map cleanly over every cursor.
+//! `shrink_selection` therefore can't remember where `expand_selection`
+//! started: it steps into the child under the head.
+
+use ropey::RopeSlice;
+use tree_sitter::Node;
+
+use crate::syntax::{Language, SyntaxTree};
+
+// ── Node categories ─────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Object {
+    Function,
+    Class,
+    Argument,
+}
+
+fn is_function(language: Language, kind: &str) -> bool {
+    match language {
+        Language::Rust => matches!(
+            kind,
+            "function_item" | "function_signature_item" | "closure_expression"
+        ),
+        Language::Lua => matches!(kind, "function_declaration" | "function_definition"),
+        Language::Toml | Language::Markdown => false,
+    }
+}
+
+fn is_class(language: Language, kind: &str) -> bool {
+    match language {
+        Language::Rust => matches!(
+            kind,
+            "struct_item" | "enum_item" | "union_item" | "trait_item" | "impl_item" | "mod_item"
+        ),
+        Language::Lua => false,
+        Language::Toml => matches!(kind, "table" | "table_array_element"),
+        Language::Markdown => kind == "section",
+    }
+}
+
+/// Argument lists: every named child (other than a comment) is an argument.
+fn is_argument_list(language: Language, kind: &str) -> bool {
+    match language {
+        Language::Rust => matches!(
+            kind,
+            "parameters"
+                | "arguments"
+                | "closure_parameters"
+                | "type_parameters"
+                | "type_arguments"
+        ),
+        Language::Lua => matches!(kind, "parameters" | "arguments"),
+        Language::Toml => kind == "array",
+        Language::Markdown => false,
+    }
+}
+
+fn is_object(language: Language, node: &Node, object: Object) -> bool {
+    match object {
+        Object::Function => is_function(language, node.kind()),
+        Object::Class => is_class(language, node.kind()),
+        Object::Argument => {
+            node.is_named()
+                && !node.kind().contains("comment")
+                && node
+                    .parent()
+                    .is_some_and(|p| is_argument_list(language, p.kind()))
+        }
+    }
+}
+
+// ── Offsets ─────────────────────────────────────────────────────────────────
+
+fn byte_range(text: &RopeSlice, anchor: usize, head: usize) -> (usize, usize) {
+    let len = text.len_chars();
+    let from = anchor.min(head).min(len);
+    let to = anchor.max(head).min(len);
+    (text.char_to_byte(from), text.char_to_byte(to))
+}
+
+/// Clamped to the text, so a tree parsed from older text can't index past
+/// its end.
+fn char_range(text: &RopeSlice, node: &Node) -> (usize, usize) {
+    let len = text.len_bytes();
+    (
+        text.byte_to_char(node.start_byte().min(len)),
+        text.byte_to_char(node.end_byte().min(len)),
+    )
+}
+
+/// Smallest named node covering the byte range `[from, to)`.
+fn covering<'t>(tree: &'t SyntaxTree, from: usize, to: usize) -> Option<Node<'t>> {
+    tree.root().named_descendant_for_byte_range(from, to)
+}
+
+fn same_range(node: &Node, from: usize, to: usize) -> bool {
+    node.start_byte() == from && node.end_byte() == to
+}
+
+// ── Expand / shrink ─────────────────────────────────────────────────────────
+
+/// Select the smallest named node strictly larger than the selection (alt-o).
+pub fn expand_selection(
+    tree: &SyntaxTree,
+    text: &RopeSlice,
+    anchor: usize,
+    head: usize,
+) -> (usize, usize) {
+    let (from, to) = byte_range(text, anchor, head);
+    let Some(mut node) = covering(tree, from, to) else {
+        return (anchor, head);
+    };
+
+    while same_range(&node, from, to) {
+        match node.parent() {
+            Some(parent) => node = parent,
+            None => return (anchor, head),
+        }
+    }
+
+    char_range(text, &node)
+}
+
+/// Select the named child of the selected node at or before the head, or
+/// its first named child when the head precedes them all (alt-i).
+pub fn shrink_selection(
+    tree: &SyntaxTree,
+    text: &RopeSlice,
+    anchor: usize,
+    head: usize,
+) -> (usize, usize) {
+    let (from, to) = byte_range(text, anchor, head);
+    let Some(node) = covering(tree, from, to) else {
+        return (anchor, head);
+    };
+
+    // The head byte: last selected byte when the head is the high end.
+    let at = if head > anchor {
+        text.char_to_byte(head - 1)
+    } else {
+        from
+    };
+
+    let mut cursor = node.walk();
+    let children: Vec<Node> = node.named_children(&mut cursor).collect();
+    let child = children
+        .iter()
+        .rev()
+        .find(|c| c.start_byte() <= at)
+        .or(children.first());
+
+    match child {
+        Some(child) if !same_range(child, from, to) => char_range(text, child),
+        _ => (anchor, head),
+    }
+}
+
+// ── Siblings ────────────────────────────────────────────────────────────────
+
+/// Select the next named sibling of the selected node, climbing to the
+/// parent's sibling when the node is the last child (alt-n).
+pub fn select_next_sibling(
+    tree: &SyntaxTree,
+    text: &RopeSlice,
+    anchor: usize,
+    head: usize,
+) -> (usize, usize) {
+    sibling(tree, text, anchor, head, |n| n.next_named_sibling())
+}
+
+/// Select the previous named sibling of the selected node (alt-p).
+pub fn select_prev_sibling(
+    tree: &SyntaxTree,
+    text: &RopeSlice,
+    anchor: usize,
+    head: usize,
+) -> (usize, usize) {
+    sibling(tree, text, anchor, head, |n| n.prev_named_sibling())
+}
+
+fn sibling<'t>(
+    tree: &'t SyntaxTree,
+    text: &RopeSlice,
+    anchor: usize,
+    head: usize,
+    step: impl Fn(&Node<'t>) -> Option<Node<'t>>,
+) -> (usize, usize) {
+    let (from, to) = byte_range(text, anchor, head);
+    let mut node = covering(tree, from, to);
+
+    while let Some(n) = node {
+        if let Some(s) = step(&n) {
+            return char_range(text, &s);
+        }
+        node = n.parent();
+    }
+
+    (anchor, head)
+}
+
+// ── Object jumps ────────────────────────────────────────────────────────────
+
+/// First node of `object` (in document order) starting after the selection start.
+fn next_object(tree: &SyntaxTree, from: usize, object: Object) -> Option<Node<'_>> {
+    let language = tree.language;
+    let mut cursor = tree.root().walk();
+
+    loop {
+        let node = cursor.node();
+        if node.start_byte() > from && is_object(language, &node, object) {
+            return Some(node);
+        }
+        // Only descend where a later start is still possible.
+        if node.end_byte() > from && cursor.goto_first_child() {
+            continue;
+        }
+        loop {
+            if cursor.goto_next_sibling() {
+                break;
+            }
+            if !cursor.goto_parent() {
+                return None;
+            }
+        }
+    }
+}
+
+/// Last node of `object` (in document order) starting before the selection start.
+fn prev_object(tree: &SyntaxTree, from: usize, object: Object) -> Option<Node<'_>> {
+    let language = tree.language;
+    let mut cursor = tree.root().walk();
+    let mut found = None;
+
+    loop {
+        let node = cursor.node();
+        if node.start_byte() < from {
+            if is_object(language, &node, object) {
+                found = Some(node);
+            }
+            if cursor.goto_first_child() {
+                continue;
+            }
+        }
+        // Siblings only start later, so stop at the first one past `from`.
+        loop {
+            if cursor.goto_next_sibling() && cursor.node().start_byte() < from {
+                break;
+            }
+            if !cursor.goto_parent() {
+                return found;
+            }
+        }
+    }
+}
+
+fn jump(
+    tree: &SyntaxTree,
+    text: &RopeSlice,
+    anchor: usize,
+    head: usize,
+    object: Object,
+    forward: bool,
+) -> (usize, usize) {
+    let (from, _) = byte_range(text, anchor, head);
+    let node = if forward {
+        next_object(tree, from, object)
+    } else {
+        prev_object(tree, from, object)
+    };
+
+    match node {
+        Some(node) => char_range(text, &node),
+        None => (anchor, head),
+    }
+}
+
+macro_rules! object_kernels {
+    ($next:ident, $prev:ident, $object:expr, $what:literal) => {
+        #[doc = concat!("Select the next ", $what, " starting after the selection.")]
+        pub fn $next(
+            tree: &SyntaxTree,
+            text: &RopeSlice,
+            anchor: usize,
+            head: usize,
+        ) -> (usize, usize) {
+            jump(tree, text, anchor, head, $object, true)
+        }
+
+        #[doc = concat!("Select the previous ", $what, " starting before the selection.")]
+        pub fn $prev(
+            tree: &SyntaxTree,
+            text: &RopeSlice,
+            anchor: usize,
+            head: usize,
+        ) -> (usize, usize) {
+            jump(tree, text, anchor, head, $object, false)
+        }
+    };
+}
+
+object_kernels!(
+    select_next_function,
+    select_prev_function,
+    Object::Function,
+    "function"
+);
+object_kernels!(
+    select_next_class,
+    select_prev_class,
+    Object::Class,
+    "class-like item"
+);
+object_kernels!(
+    select_next_argument,
+    select_prev_argument,
+    Object::Argument,
+    "argument"
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::syntax::Syntax;
+    use ropey::Rope;
+
+    const SRC: &str = "struct S;\nfn a(x: u8, y: u8) {}\nfn b() { a(1, 2); }\n";
+
+    fn run(
+        src: &str,
+        k: fn(&SyntaxTree, &RopeSlice, usize, usize) -> (usize, usize),
+        anchor: usize,
+        head: usize,
+    ) -> String {
+        let text = Rope::from_str(src);
+        let syntax = Syntax::new(Language::Rust, &text).unwrap();
+        let (a, h) = k(syntax.tree(), &text.slice(..), anchor, head);
+        text.slice(a.min(h)..a.max(h)).to_string()
+    }
+
+    #[test]
+    fn expand_climbs_to_strictly_larger_node() {
+        // Cursor on `x` (collapsed) → the identifier.
+        assert_eq!(run(SRC, expand_selection, 15, 15), "x");
+        // The identifier selected → its parameter.
+        assert_eq!(run(SRC, expand_selection, 15, 16), "x: u8");
+        assert_eq!(run(SRC, expand_selection, 15, 20), "(x: u8, y: u8)");
+    }
+
+    #[test]
+    fn expand_at_root_is_noop() {
+        let text = Rope::from_str(SRC);
+        let syntax = Syntax::new(Language::Rust, &text).unwrap();
+        let len = text.len_chars();
+        assert_eq!(
+            expand_selection(syntax.tree(), &text.slice(..), 0, len),
+            (0, len)
+        );
+    }
+
+    #[test]
+    fn shrink_steps_into_child_under_head() {
+        // `(x: u8, y: u8)` selected with the head past `y: u8`.
+        assert_eq!(run(SRC, shrink_selection, 14, 28), "y: u8");
+        // Head on the low end picks the first child.
+        assert_eq!(run(SRC, shrink_selection, 28, 14), "x: u8");
+    }
+
+    #[test]
+    fn siblings() {
+        // `x: u8` → `y: u8` and back.
+        assert_eq!(run(SRC, select_next_sibling, 15, 20), "y: u8");
+        assert_eq!(run(SRC, select_prev_sibling, 22, 27), "x: u8");
+    }
+
+    #[test]
+    fn sibling_climbs_when_last() {
+        // `y: u8` is the last parameter; next is the function body.
+        assert_eq!(run(SRC, select_next_sibling, 22, 27), "{}");
+    }
+
+    #[test]
+    fn function_jumps() {
+        assert_eq!(
+            run(SRC, select_next_function, 0, 0),
+            "fn a(x: u8, y: u8) {}"
+        );
+        assert_eq!(
+            run(SRC, select_next_function, 10, 10),
+            "fn b() { a(1, 2); }"
+        );
+        assert_eq!(
+            run(SRC, select_prev_function, 32, 51),
+            "fn a(x: u8, y: u8) {}"
+        );
+        // No function before the first one.
+        assert_eq!(run(SRC, select_prev_function, 5, 5), "");
+    }
+
+    #[test]
+    fn class_jumps() {
+        assert_eq!(run(SRC, select_prev_class, 20, 20), "struct S;");
+        assert_eq!(run(SRC, select_next_class, 0, 0), "");
+    }
+
+    #[test]
+    fn argument_jumps() {
+        assert_eq!(run(SRC, select_next_argument, 10, 10), "x: u8");
+        assert_eq!(run(SRC, select_next_argument, 15, 20), "y: u8");
+        // Call arguments count too.
+        assert_eq!(run(SRC, select_next_argument, 42, 42), "1");
+        assert_eq!(run(SRC, select_prev_argument, 46, 47), "1");
+    }
+}
@@ -0,0 +1,162 @@
+//! Swap files: a copy of each open document's text, kept on disk so a crash
+//! doesn't lose unsaved work.
+//!
+//! One swap file per file path, named after the path with `/` replaced by
+//! `%` (see [`fileio::keyed_path`]), in a directory the caller picks. The first line is a
+//! header with the owning process id; the rest is the text, UTF-8. A swap
+//! whose owner is still running means another instance is editing the file;
+//! one whose owner is gone is left over from a crash and can be recovered.
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use ropey::Rope;
+
+use crate::fileio;
+
+const HEADER: &str = "gauchito-swap 1";
+
+/// A swap file read back from disk.
+#[derive(Debug, Clone)]
+pub struct SwapFile {
+    pub path: PathBuf,
+    /// Process that wrote it.
+    pub pid: u32,
+    pub text: String,
+}
+
+impl SwapFile {
+    /// Written by this process.
+    pub fn is_ours(&self) -> bool {
+        self.pid == std::process::id()
+    }
+
+    /// Its owner is still running, so it isn't left over from a crash.
+    pub fn owner_alive(&self) -> bool {
+        self.is_ours() || is_alive(self.pid)
+    }
+}
+
+/// Swap file for `file` inside `dir`.
+pub fn path_for(dir: &Path, file: &Path) -> PathBuf {
+    fileio::keyed_path(dir, file, "swp")
+}
+
+/// Read the swap file for `file`, if there is one.
+pub fn read(dir: &Path, file: &Path) -> io::Result<Option<SwapFile>> {
+    let path = path_for(dir, file);
+    let contents = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, "not a gauchito swap file");
+    let (header, text) = contents.split_once('\n').ok_or_else(invalid)?;
+    let pid = header
+        .strip_prefix(HEADER)
+        .and_then(|rest| rest.trim().parse().ok())
+        .ok_or_else(invalid)?;
+
+    Ok(Some(SwapFile {
+        path,
+        pid,
+        text: text.to_string(),
+    }))
+}
+
+/// Write `text` as the swap file for `file`, owned by this process and
+/// readable by its user only. A crash mid-write keeps the previous swap.
+pub fn write(dir: &Path, file: &Path, text: &Rope) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+    fileio::write_private(&path_for(dir, file), |f| {
+        let mut out = io::BufWriter::new(f);
+        writeln!(out, "{HEADER} {}", std::process::id())?;
+        for chunk in text.chunks() {
+            out.write_all(chunk.as_bytes())?;
+        }
+        out.flush()
+    })
+}
+
+/// Delete the swap file for `file`. A missing one is not an error.
+pub fn remove(dir: &Path, file: &Path) -> io::Result<()> {
+    match fs::remove_file(path_for(dir, file)) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(unix)]
+fn is_alive(pid: u32) -> bool {
+    let Ok(pid) = libc::pid_t::try_from(pid) else {
+        return false;
+    };
+    // Signal 0 only checks that the process exists. EPERM: it does, as
+    // another user's.
+    let found = unsafe { libc::kill(pid, 0) } == 0;
+    found || io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+#[cfg(not(unix))]
+fn is_alive(_pid: u32) -> bool {
+    // No cheap check; treat every foreign swap as a crash leftover.
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("gauchito-swap-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn path_flattens_separators() {
+        let p = path_for(Path::new("/swap"), Path::new("/home/u/a.rs"));
+        assert_eq!(p, Path::new("/swap/%home%u%a.rs.swp"));
+    }
+
+    #[test]
+    fn write_read_remove() {
+        let dir = scratch("roundtrip");
+        let file = Path::new("/tmp/notes.txt");
+        assert!(read(&dir, file).unwrap().is_none());
+
+        write(&dir, file, &Rope::from_str("unsaved\nwork\n")).unwrap();
+        let swap = read(&dir, file).unwrap().unwrap();
+        assert_eq!(swap.text, "unsaved\nwork\n");
+        assert!(swap.is_ours());
+        assert!(swap.owner_alive());
+
+        remove(&dir, file).unwrap();
+        assert!(read(&dir, file).unwrap().is_none());
+        remove(&dir, file).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn only_the_owner_reads_it() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = scratch("mode");
+        let file = Path::new("/tmp/secret.txt");
+        write(&dir, file, &Rope::from_str("password\n")).unwrap();
+        let meta = fs::metadata(path_for(&dir, file)).unwrap();
+        let mode = meta.permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    #[test]
+    fn rejects_foreign_files() {
+        let dir = scratch("foreign");
+        let file = Path::new("/tmp/x");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(path_for(&dir, file), "something else\n").unwrap();
+        assert!(read(&dir, file).is_err());
+    }
+}
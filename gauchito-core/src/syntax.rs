@@ -0,0 +1,255 @@
+//! Tree-sitter syntax trees, kept in sync with a document's text.
+//!
+//! A [`Syntax`] owns the parser and the current tree for one document. Every
+//! [`Mutation`] is reported through [`Syntax::edit`] *before* it is applied to
+//! the rope, so byte and row/column positions are computed against the text
+//! the tree was parsed from. [`Syntax::reparse`] then reparses incrementally,
+//! reusing every subtree the edits didn't touch.
+//!
+//! Consumers (kernels, renderers, the Lua API) take a [`SyntaxTree`]: a cheap,
+//! reference-counted snapshot of the tree plus its language.
+
+use std::path::Path;
+
+use ropey::Rope;
+use tree_sitter::{InputEdit, Parser};
+
+pub use tree_sitter::{Node, Point, Tree};
+
+use crate::mutation::Mutation;
+
+// ── Languages ───────────────────────────────────────────────────────────────
+
+/// Bundled grammars.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Language {
+    Rust,
+    Lua,
+    Toml,
+    Markdown,
+}
+
+impl Language {
+    /// Pick a grammar from the file extension.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "rs" => Some(Language::Rust),
+            "lua" => Some(Language::Lua),
+            "toml" => Some(Language::Toml),
+            "md" | "markdown" => Some(Language::Markdown),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Language::Rust => "rust",
+            Language::Lua => "lua",
+            Language::Toml => "toml",
+            Language::Markdown => "markdown",
+        }
+    }
+
+    /// Markdown is the block-level grammar only; inline spans aren't parsed.
+    pub fn grammar(self) -> tree_sitter::Language {
+        match self {
+            Language::Rust => tree_sitter_rust::LANGUAGE.into(),
+            Language::Lua => tree_sitter_lua::LANGUAGE.into(),
+            Language::Toml => tree_sitter_toml_ng::LANGUAGE.into(),
+            Language::Markdown => tree_sitter_md::LANGUAGE.into(),
+        }
+    }
+}
+
+// ── Trees ───────────────────────────────────────────────────────────────────
+
+/// A parsed tree and the language it was parsed with. Cloning is O(1).
+#[derive(Clone)]
+pub struct SyntaxTree {
+    pub language: Language,
+    pub tree: Tree,
+}
+
+impl SyntaxTree {
+    pub fn root(&self) -> Node<'_> {
+        self.tree.root_node()
+    }
+}
+
+pub struct Syntax {
+    parser: Parser,
+    current: SyntaxTree,
+}
+
+impl Syntax {
+    /// Parse `text` from scratch. `None` if the grammar can't be loaded.
+    pub fn new(language: Language, text: &Rope) -> Option<Self> {
+        let mut parser = Parser::new();
+        parser.set_language(&language.grammar()).ok()?;
+
+        let tree = parse(&mut parser, text, None)?;
+
+        Some(Self {
+            parser,
+            current: SyntaxTree { language, tree },
+        })
+    }
+
+    pub fn language(&self) -> Language {
+        self.current.language
+    }
+
+    pub fn tree(&self) -> &SyntaxTree {
+        &self.current
+    }
+
+    /// Record `m` on the tree. `text` must be the rope *before* `m` is applied.
+    pub fn edit(&mut self, text: &Rope, m: &Mutation) {
+        self.current.tree.edit(&input_edit(text, m));
+    }
+
+    /// Reparse after one or more [`Syntax::edit`]s. `text` is the current rope.
+    pub fn reparse(&mut self, text: &Rope) {
+        if let Some(tree) = parse(&mut self.parser, text, Some(&self.current.tree)) {
+            self.current.tree = tree;
+        }
+    }
+
+    /// Parse `text` from scratch, for edits the tree was never told about.
+    pub fn parse_again(&mut self, text: &Rope) {
+        if let Some(tree) = parse(&mut self.parser, text, None) {
+            self.current.tree = tree;
+        }
+    }
+}
+
+/// Feed the rope to the parser chunk by chunk.
+fn parse(parser: &mut Parser, text: &Rope, old: Option<&Tree>) -> Option<Tree> {
+    let len = text.len_bytes();
+
+    parser.parse_with_options(
+        &mut |byte, _| {
+            if byte >= len {
+                return &[] as &[u8];
+            }
+            let (chunk, start, _, _) = text.chunk_at_byte(byte);
+            &chunk.as_bytes()[byte - start..]
+        },
+        old,
+        None,
+    )
+}
+
+/// Row / byte-column of char offset `pos` in `text`.
+fn point_at(text: &Rope, pos: usize) -> Point {
+    let row = text.char_to_line(pos);
+    let column = text.char_to_byte(pos) - text.line_to_byte(row);
+    Point { row, column }
+}
+
+/// Translate a char-offset splice into tree-sitter's byte + point edit.
+fn input_edit(text: &Rope, m: &Mutation) -> InputEdit {
+    let start_byte = text.char_to_byte(m.p());
+    let old_end_byte = text.char_to_byte(m.q());
+    let new_end_byte = start_byte + m.t().len();
+
+    let start_position = point_at(text, m.p());
+    let old_end_position = point_at(text, m.q());
+    let new_end_position = match m.t().rfind('\n') {
+        Some(i) => Point {
+            row: start_position.row + m.t().matches('\n').count(),
+            column: m.t().len() - i - 1,
+        },
+        None => Point {
+            row: start_position.row,
+            column: start_position.column + m.t().len(),
+        },
+    };
+
+    InputEdit {
+        start_byte,
+        old_end_byte,
+        new_end_byte,
+        start_position,
+        old_end_position,
+        new_end_position,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sexp(syntax: &Syntax) -> String {
+        syntax.tree().root().to_sexp()
+    }
+
+    #[test]
+    fn language_from_extension() {
+        assert_eq!(
+            Language::from_path(Path::new("a/b.rs")),
+            Some(Language::Rust)
+        );
+        assert_eq!(
+            Language::from_path(Path::new("x.md")),
+            Some(Language::Markdown)
+        );
+        assert_eq!(Language::from_path(Path::new("Makefile")), None);
+    }
+
+    #[test]
+    fn parses_rust() {
+        let text = Rope::from_str("fn main() {}\n");
+        let syntax = Syntax::new(Language::Rust, &text).unwrap();
+        assert_eq!(syntax.tree().root().kind(), "source_file");
+        assert!(!syntax.tree().root().has_error());
+    }
+
+    #[test]
+    fn incremental_edit_matches_fresh_parse() {
+        let mut text = Rope::from_str("fn a() {}\nfn b() {}\n");
+        let mut syntax = Syntax::new(Language::Rust, &text).unwrap();
+
+        // Rename `b` to `beta` and add a parameter to `a`.
+        let edits = [
+            Mutation::new(13, 14, "beta".into()),
+            Mutation::new(5, 5, "x: u8".into()),
+        ];
+        for m in &edits {
+            syntax.edit(&text, m);
+            m.apply(&mut text);
+        }
+        syntax.reparse(&text);
+
+        let fresh = Syntax::new(Language::Rust, &text).unwrap();
+        assert_eq!(text.to_string(), "fn a(x: u8) {}\nfn beta() {}\n");
+        assert_eq!(sexp(&syntax), sexp(&fresh));
+    }
+
+    #[test]
+    fn multiline_insert_and_delete() {
+        let mut text = Rope::from_str("[a]\nk = 1\n");
+        let mut syntax = Syntax::new(Language::Toml, &text).unwrap();
+
+        let edits = [
+            Mutation::new(10, 10, "[b]\nj = 2\n".into()),
+            Mutation::new(0, 4, String::new()),
+        ];
+        for m in &edits {
+            syntax.edit(&text, m);
+            m.apply(&mut text);
+        }
+        syntax.reparse(&text);
+
+        let fresh = Syntax::new(Language::Toml, &text).unwrap();
+        assert_eq!(sexp(&syntax), sexp(&fresh));
+    }
+
+    #[test]
+    fn parses_across_chunks() {
+        let body: String = (0..2000).map(|i| format!("let x{i} = {i};\n")).collect();
+        let text = Rope::from_str(&format!("fn f() {{\n{body}}}\n"));
+        let syntax = Syntax::new(Language::Rust, &text).unwrap();
+        assert!(!syntax.tree().root().has_error());
+    }
+}
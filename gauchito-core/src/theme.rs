@@ -0,0 +1,89 @@
+//! Highlight theme: capture name → style.
+//!
+//! Renderer-agnostic; the UI converts [`Style`] into its own type. Lookups
+//! fall back along dotted capture names, so a theme that only defines
+//! `function` also covers `function.method` and `function.builtin`.
+
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rgb(pub u8, pub u8, pub u8);
+
+impl Rgb {
+    /// Parse `#rrggbb` (the leading `#` is optional).
+    pub fn parse(s: &str) -> Option<Self> {
+        let hex = s.strip_prefix('#').unwrap_or(s);
+        if hex.len() != 6 || !hex.is_ascii() {
+            return None;
+        }
+        let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+
+        Some(Rgb(channel(0)?, channel(2)?, channel(4)?))
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Style {
+    pub fg: Option<Rgb>,
+    pub bg: Option<Rgb>,
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Theme {
+    styles: HashMap<String, Style>,
+}
+
+impl Theme {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set(&mut self, name: impl Into<String>, style: Style) {
+        self.styles.insert(name.into(), style);
+    }
+
+    /// Style for a capture, trying `a.b.c`, then `a.b`, then `a`.
+    pub fn get(&self, mut name: &str) -> Option<&Style> {
+        loop {
+            if let Some(style) = self.styles.get(name) {
+                return Some(style);
+            }
+            name = &name[..name.rfind('.')?];
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.styles.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_hex() {
+        assert_eq!(Rgb::parse("#ff8000"), Some(Rgb(255, 128, 0)));
+        assert_eq!(Rgb::parse("00ff00"), Some(Rgb(0, 255, 0)));
+        assert_eq!(Rgb::parse("#fff"), None);
+        assert_eq!(Rgb::parse("#gg0000"), None);
+    }
+
+    #[test]
+    fn dotted_fallback() {
+        let mut theme = Theme::new();
+        let keyword = Style {
+            bold: true,
+            ..Style::default()
+        };
+        theme.set("function", Style::default());
+        theme.set("keyword", keyword);
+
+        assert_eq!(theme.get("keyword"), Some(&keyword));
+        assert_eq!(theme.get("function.method.call"), Some(&Style::default()));
+        assert_eq!(theme.get("string"), None);
+    }
+}
@@ -0,0 +1,266 @@
+//! Undo files: a document's [`History`], kept on disk between sessions.
+//!
+//! Written on save, one per file path (named like swap files, `/` → `%`),
+//! as JSON holding the history and a hash of the text it was recorded
+//! against. On load the history is restored only if the file's text still
+//! hashes the same; anything else (edited elsewhere, trimmed on save, a
+//! newer format) drops it. [`prune`] keeps the directory bounded by age and
+//! total size.
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use ropey::Rope;
+use serde::{Deserialize, Serialize};
+
+use crate::fileio;
+use crate::history::History;
+
+const VERSION: u32 = 2;
+
+/// Bounds on the undo directory.
+#[derive(Debug, Clone, Copy)]
+pub struct Limits {
+    /// Undo files not written for this long are deleted.
+    pub max_age: Duration,
+    /// Total size of the directory; the oldest files go first.
+    pub max_total_bytes: u64,
+    /// A history larger than this isn't kept at all.
+    pub max_file_bytes: u64,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_age: Duration::from_secs(90 * 24 * 60 * 60),
+            max_total_bytes: 64 << 20,
+            max_file_bytes: 8 << 20,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct UndoFile<H> {
+    version: u32,
+    hash: u64,
+    history: H,
+}
+
+/// Undo file for `file` inside `dir`.
+pub fn path_for(dir: &Path, file: &Path) -> PathBuf {
+    fileio::keyed_path(dir, file, "undo")
+}
+
+/// FNV-1a over the text's UTF-8 bytes.
+pub fn content_hash(text: &Rope) -> u64 {
+    text.chunks().fold(fileio::FNV_OFFSET, |hash, chunk| {
+        fileio::fnv1a(hash, chunk.as_bytes())
+    })
+}
+
+/// Record `history` for `file`, whose text reads back as `text`. A history over
+/// `limits.max_file_bytes` removes the undo file instead.
+pub fn write(
+    dir: &Path,
+    file: &Path,
+    text: &Rope,
+    history: &History,
+    limits: Limits,
+) -> io::Result<()> {
+    let undo = UndoFile {
+        version: VERSION,
+        hash: content_hash(text),
+        history,
+    };
+    let json = serde_json::to_vec(&undo).map_err(io::Error::other)?;
+
+    let path = path_for(dir, file);
+    if json.len() as u64 > limits.max_file_bytes {
+        return remove_file(&path);
+    }
+
+    fs::create_dir_all(dir)?;
+    fileio::write_private(&path, |f| f.write_all(&json))
+}
+
+/// The history saved for `file`, if it was recorded against `text`.
+pub fn read(dir: &Path, file: &Path, text: &Rope) -> io::Result<Option<History>> {
+    let json = match fs::read(path_for(dir, file)) {
+        Ok(json) => json,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+
+    let undo: UndoFile<History> = serde_json::from_slice(&json).map_err(io::Error::other)?;
+    if undo.version != VERSION || undo.hash != content_hash(text) {
+        return Ok(None);
+    }
+    Ok(Some(undo.history))
+}
+
+/// Delete undo files older than `limits.max_age`, then the oldest ones until
+/// the rest fit in `limits.max_total_bytes`.
+pub fn prune(dir: &Path, limits: Limits) -> io::Result<()> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+
+    let now = SystemTime::now();
+    let mut kept = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        let meta = entry.metadata()?;
+        if !meta.is_file() {
+            continue;
+        }
+        let modified = meta.modified().unwrap_or(now);
+        let age = now.duration_since(modified).unwrap_or_default();
+        if age > limits.max_age {
+            remove_file(&entry.path())?;
+        } else {
+            kept.push((modified, meta.len(), entry.path()));
+        }
+    }
+
+    // Newest first; drop everything past the budget.
+    kept.sort_by_key(|&(modified, _, _)| std::cmp::Reverse(modified));
+    let mut total = 0;
+    for (_, len, path) in kept {
+        total += len;
+        if total > limits.max_total_bytes {
+            remove_file(&path)?;
+        }
+    }
+    Ok(())
+}
+
+/// Delete the undo file for `file`. A missing one is not an error.
+pub fn remove(dir: &Path, file: &Path) -> io::Result<()> {
+    remove_file(&path_for(dir, file))
+}
+
+fn remove_file(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::history::Transaction;
+    use crate::mutation::Mutation;
+
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("gauchito-undo-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    /// "hello" → "hello world", committed.
+    fn edited() -> (Rope, History) {
+        let mut text = Rope::from_str("hello");
+        let mutations = vec![Mutation::new(5, 5, " world".to_string())];
+        let inverses = mutations.iter().map(|m| m.apply(&mut text)).collect();
+        let mut history = History::new();
+        history.commit(Transaction {
+            mutations,
+            inverses,
+            selection_before: None,
+            selection_after: None,
+        });
+        (text, history)
+    }
+
+    #[test]
+    fn round_trip() {
+        let dir = scratch("roundtrip");
+        let file = Path::new("/tmp/a.txt");
+        let (mut text, history) = edited();
+        write(&dir, file, &text, &history, Limits::default()).unwrap();
+
+        let mut restored = read(&dir, file, &text).unwrap().unwrap();
+        let (inverses, _) = restored.undo().unwrap();
+        for m in inverses.iter().rev() {
+            m.apply(&mut text);
+        }
+        assert_eq!(text.to_string(), "hello");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn only_the_owner_reads_it() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = scratch("mode");
+        let file = Path::new("/tmp/a.txt");
+        let (text, history) = edited();
+        write(&dir, file, &text, &history, Limits::default()).unwrap();
+        let meta = fs::metadata(path_for(&dir, file)).unwrap();
+        assert_eq!(meta.permissions().mode() & 0o777, 0o600);
+    }
+
+    #[test]
+    fn stale_hash_is_ignored() {
+        let dir = scratch("stale");
+        let file = Path::new("/tmp/a.txt");
+        let (text, history) = edited();
+        write(&dir, file, &text, &history, Limits::default()).unwrap();
+
+        let changed = Rope::from_str("hello world!");
+        assert!(read(&dir, file, &changed).unwrap().is_none());
+    }
+
+    #[test]
+    fn oversized_history_is_dropped() {
+        let dir = scratch("oversized");
+        let file = Path::new("/tmp/a.txt");
+        let (text, history) = edited();
+        write(&dir, file, &text, &history, Limits::default()).unwrap();
+
+        let tiny = Limits {
+            max_file_bytes: 8,
+            ..Limits::default()
+        };
+        write(&dir, file, &text, &history, tiny).unwrap();
+        assert!(read(&dir, file, &text).unwrap().is_none());
+    }
+
+    #[test]
+    fn prune_by_total_size() {
+        let dir = scratch("prune");
+        fs::create_dir_all(&dir).unwrap();
+        for name in ["a", "b", "c"] {
+            fs::write(dir.join(name), [0u8; 100]).unwrap();
+        }
+
+        let limits = Limits {
+            max_total_bytes: 250,
+            ..Limits::default()
+        };
+        prune(&dir, limits).unwrap();
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+
+        prune(&dir.join("missing"), limits).unwrap();
+    }
+
+    #[test]
+    fn prune_by_age() {
+        let dir = scratch("age");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("a"), "x").unwrap();
+
+        let limits = Limits {
+            max_age: Duration::ZERO,
+            ..Limits::default()
+        };
+        std::thread::sleep(Duration::from_millis(10));
+        prune(&dir, limits).unwrap();
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+    }
+}
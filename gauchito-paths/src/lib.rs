@@ -30,6 +30,21 @@ pub fn data_dir() -> PathBuf {
         .join("gauchito")
 }
 
+/// Swap files of open documents, for crash recovery.
+pub fn swap_dir() -> PathBuf {
+    data_dir().join("swap")
+}
+
+/// Undo histories of saved files, restored when they're reopened.
+pub fn undo_dir() -> PathBuf {
+    data_dir().join("undo")
+}
+
+/// Named registers, macros included, kept between sessions.
+pub fn registers_file() -> PathBuf {
+    data_dir().join("registers.json")
+}
+
 pub fn log_dir() -> PathBuf {
     dirs::cache_dir()
         .expect("no cache directory")
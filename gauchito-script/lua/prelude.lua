@@ -7,18 +7,30 @@
 --   bv.k.*               per-cursor motion / selection / char-find kernels
 --   bv.delete_*          mutation kernels (return changesets)
 --   bv.insert_*          mutation kernels (return changesets)
+--   bv.replace_all(b, s) changeset rewriting the buffer to `s`, minimally
 --
 --   bv.collapse(kernel)  ctx-action: move head, collapse anchor onto it
 --   bv.extend(kernel)    ctx-action: move head, anchor stays
 --   bv.select(kernel)    ctx-action: head moves; old head becomes anchor
 --   bv.lift(kernel)      ctx-action: kernel returns {anchor, head}
 --   bv.fold(mutation)    ctx-action: apply mutation across selections
+--   bv.fold_indent(m)    bv.fold, passing the indent unit and tab width too
 --   bv.rep(n, op)        ctx-action: repeat `op` `n` times
+--   bv.select_lines(n)   ctx-action: select `n` whole lines from each cursor
 --   bv.seq(...)          ctx-action: run ops in order
+--   bv.repeatable(f)     handler: `f` is a change `ctx:repeat_last_change` redoes
+--   bv.jump(f)           handler: `f` jumps; where it left from goes on the jump list
 --
 --   bv.read_key()        yield, return (ctx, key)
 --   bv.read_char()       yield, return (ctx, ch)  -- ch nil if non-printable
+--   bv.read_input()      yield, return (ctx, key, ch); `esc` is delivered too
+--   bv.prompt(ctx, p, f) read a line in the prompt overlay
+--   bv.search(ctx, p, k) incremental search prompt over a search kernel
 --   bv.read_count(d)     yield until non-digit; return (ctx, count, key)
+--   bv.undo_tree(ctx)    browse the undo tree, previewing each revision
+--   bv.save(ctx)         save, asking for a file name if there is none
+--   bv.quit(ctx)         quit, asking first about unsaved changes
+--   bv.close_view(ctx)   close the view, asking first about unsaved changes
 --   bv.is_digit(key)     true if `key` is a single digit
 --
 --   bv.expand_high(n)    grow each range's high end by n
@@ -38,6 +50,200 @@ function bv.read_char()
     return ctx, ch
 end
 
+-- Like read_key, but the bridge won't cancel the sequence on `esc`: it is
+-- handed back as a regular key so the caller can clean up first.
+function bv.read_input()
+    local ctx, key, ch = coroutine.yield("input")
+    return ctx, key, ch
+end
+
+-- ── Prompt ─────────────────────────────────────────────────────────────────
+
+-- Drop the last UTF-8 character of `s`.
+local function pop_char(s)
+    return (s:gsub("[%z\1-\127\194-\244][\128-\191]*$", ""))
+end
+
+-- Read a line of text in the prompt overlay, shown after `prefix`. Calls
+-- `on_change(ctx, text)` after every edit (if given). Returns `(ctx, text)`
+-- on enter, or `(ctx, nil)` on esc / backspace past the start.
+function bv.prompt(ctx, prefix, on_change)
+    local text = ""
+    ctx:set_prompt(prefix)
+    while true do
+        local key, ch
+        ctx, key, ch = bv.read_input()
+        if key == "enter" then
+            ctx:clear_prompt()
+            return ctx, text
+        elseif key == "esc" or (key == "backspace" and text == "") then
+            ctx:clear_prompt()
+            return ctx, nil
+        elseif key == "backspace" then
+            text = pop_char(text)
+        elseif ch then
+            text = text .. ch
+        else
+            goto continue
+        end
+        ctx:set_prompt(prefix .. text)
+        if on_change then on_change(ctx, text) end
+        ::continue::
+    end
+end
+
+-- Incremental search. Every keystroke re-runs `kernel(buf, head, pattern)`
+-- from the selection the prompt was opened with, so the cursor previews the
+-- match as you type. Enter keeps it; esc restores the original selection.
+-- Returns `(ctx, pattern)`, pattern nil if cancelled.
+function bv.search(ctx, prefix, kernel)
+    local origin = ctx:selection()
+    local function preview(ctx, pattern)
+        ctx:set_selection(origin)
+        if pattern == "" then return end
+        local buf = ctx:text()
+        ctx:map_selections(function(_, head)
+            local h = kernel(buf, head, pattern)
+            return h, h
+        end)
+    end
+    local ctx, pattern = bv.prompt(ctx, prefix, preview)
+    if pattern == nil or pattern == "" then
+        ctx:set_selection(origin)
+        return ctx, nil
+    end
+    return ctx, pattern
+end
+
+-- ── Undo tree ─────────────────────────────────────────────────────────────
+
+-- Rows of the tree shown at once, around the selected one.
+local UNDO_TREE_ROWS = 9
+
+local function undo_row_text(row, selected, origin)
+    local mark = selected and ">" or " "
+    local here = row.id == origin and "  (was here)" or ""
+    local saved = row.saved and "  saved" or ""
+    return string.format("%s %s%d  %s%s%s", mark, row.graph, row.id,
+        os.date("%H:%M:%S", math.floor(row.time)), saved, here)
+end
+
+-- The window of rows around `at`, one per line, and a footer.
+local function undo_tree_text(rows, at, origin)
+    local first = at - math.floor(UNDO_TREE_ROWS / 2)
+    first = math.max(1, math.min(first, #rows - UNDO_TREE_ROWS + 1))
+    local lines = {}
+    for i = first, math.min(#rows, first + UNDO_TREE_ROWS - 1) do
+        lines[#lines + 1] = undo_row_text(rows[i], i == at, origin)
+    end
+    lines[#lines + 1] = string.format("undo tree: %d revisions; j/k, enter, esc", #rows)
+    return table.concat(lines, "\n")
+end
+
+-- Browse the undo tree in the prompt area, several rows at a time as drawn
+-- by `ctx:undo_tree()`. j/k (or down/up) walk the rows depth first, moving
+-- the document to each revision as a preview. Enter keeps it; esc goes back
+-- to where it started.
+function bv.undo_tree(ctx)
+    local rows = ctx:undo_tree()
+    local at, origin = 1, 0
+    for i, row in ipairs(rows) do
+        if row.current then at, origin = i, row.id end
+    end
+    while true do
+        ctx:set_prompt(undo_tree_text(rows, at, origin))
+        local key
+        ctx, key = bv.read_input()
+        if key == "j" or key == "down" then
+            at = math.min(at + 1, #rows)
+        elseif key == "k" or key == "up" then
+            at = math.max(at - 1, 1)
+        elseif key == "enter" then
+            ctx:clear_prompt()
+            return ctx
+        elseif key == "esc" then
+            ctx:undo_goto(origin)
+            ctx:clear_prompt()
+            return ctx
+        end
+        ctx:undo_goto(rows[at].id)
+    end
+end
+
+-- ── Saving ────────────────────────────────────────────────────────────────
+
+local function file_exists(path)
+    local f = io.open(path, "r")
+    if f then f:close() end
+    return f ~= nil
+end
+
+-- Save the focused document. A scratch buffer asks for a path first; its
+-- directories are created as needed, and a file already there is only
+-- replaced once confirmed. Errors are shown by `ctx:save*`.
+function bv.save(ctx)
+    if ctx:document():path() then
+        ctx:save()
+        return ctx
+    end
+    local ctx, path = bv.prompt(ctx, "save as: ")
+    if path == nil or path == "" then return ctx end
+
+    local opts = { create_dirs = true }
+    if file_exists(path) then
+        ctx:set_prompt(path .. " exists: [o]verwrite, [c]ancel")
+        while true do
+            local key
+            ctx, key = bv.read_input()
+            if key == "o" then
+                opts.force = true
+                break
+            elseif key == "c" or key == "esc" then
+                ctx:clear_prompt()
+                return ctx
+            end
+        end
+        ctx:clear_prompt()
+    end
+    ctx:save_as(path, opts)
+    return ctx
+end
+
+-- ── Unsaved changes ───────────────────────────────────────────────────────
+
+-- Ask what to do with unsaved changes, then call `retry(ctx, opts)` with
+-- `{ write = true }` (s), `{ force = true }` (d), or not at all (c / esc).
+local function confirm_unsaved(ctx, retry)
+    local names = {}
+    for _, doc in ipairs(ctx:documents()) do
+        if doc:is_modified() then names[#names + 1] = doc:name() end
+    end
+    ctx:set_prompt("unsaved changes in " .. table.concat(names, ", ")
+        .. ": [s]ave all, [d]iscard, [c]ancel")
+    while true do
+        local key
+        ctx, key = bv.read_input()
+        if key == "s" or key == "d" then
+            ctx:clear_prompt()
+            retry(ctx, key == "s" and { write = true } or { force = true })
+            return ctx
+        elseif key == "c" or key == "esc" then
+            ctx:clear_prompt()
+            return ctx
+        end
+    end
+end
+
+function bv.quit(ctx)
+    if ctx:quit() then return ctx end
+    return confirm_unsaved(ctx, function(ctx, opts) ctx:quit(opts) end)
+end
+
+function bv.close_view(ctx)
+    if ctx:close_view() then return ctx end
+    return confirm_unsaved(ctx, function(ctx, opts) ctx:close_view(opts) end)
+end
+
 -- ── Digits / counts ───────────────────────────────────────────────────────
 
 function bv.is_digit(key)
@@ -111,6 +317,13 @@ function bv.fold(mutation)
     end
 end
 
+-- For indent-aware mutations `(buf, sel, unit, tab_width) -> changeset`.
+function bv.fold_indent(mutation)
+    return function(ctx)
+        ctx:edit(mutation(ctx:text(), ctx:selection(), ctx:indent_unit(), ctx:tab_width()))
+    end
+end
+
 -- ── Sequencing ─────────────────────────────────────────────────────────────
 
 function bv.rep(n, op)
@@ -126,6 +339,24 @@ function bv.seq(...)
     end
 end
 
+-- Mark a handler as a repeatable change. Extra arguments (an operator's
+-- count) pass through.
+function bv.repeatable(f)
+    return function(ctx, ...)
+        ctx:begin_change()
+        return f(ctx, ...)
+    end
+end
+
+-- Mark a handler as a jump: where the cursor was goes on the jump list
+-- first, for ctx:jump_back().
+function bv.jump(f)
+    return function(ctx, ...)
+        ctx:push_jump()
+        return f(ctx, ...)
+    end
+end
+
 -- ── Selection-shape combinators ────────────────────────────────────────────
 
 -- Grow each range's high end by `n` chars. Forward ranges grow head;
@@ -141,6 +372,20 @@ function bv.expand_high(n)
     end
 end
 
+-- Select `n` whole lines, from each cursor's line down. The last line is
+-- taken as far as it goes when there are fewer than `n`.
+function bv.select_lines(n)
+    return function(ctx)
+        local buf = ctx:text()
+        ctx:map_selections(function(_, head)
+            local h = head
+            for _ = 2, n do h = bv.k.move_down(buf, h) end
+            return bv.k.select_whole_line(buf, 0, head).anchor,
+                bv.k.select_whole_line(buf, 0, h).head
+        end)
+    end
+end
+
 -- ── Multi-cursor combinators ───────────────────────────────────────────────
 
 -- Push a new collapsed cursor at `kernel(buf, primary.head)`. No-op if the
@@ -182,8 +427,13 @@ end
 --                               linewise  = bool },  -- expand to whole lines
 --                     … }
 --     char_finds  = { [key] = char_kernel, … }       (always inclusive)
+--     text_objects = { i = { [key] = selection_kernel, … },
+--                      a = { [key] = selection_kernel, … } }
+--                   optional; `i`/`a` + key selects the object's range
 --     self_key    = optional, e.g. "d" — triggers self_action when key matches
 --     self_action = optional ctx-action repeated `count_pre` times on self_key
+--     self_lines  = optional ctx-action run once on self_key over the
+--                   count's whole lines (dd, yy, cc); wins over self_action
 -- }
 --
 -- The returned function is `op(ctx, count_pre)`. count_pre defaults to 1.
@@ -194,11 +444,27 @@ function bv.operator(opts)
         local total = (count_post == 0) and count_pre or (count_pre * count_post)
 
         -- Self-key shortcut (dd, yy, cc).
+        if opts.self_key and key == opts.self_key and opts.self_lines then
+            bv.select_lines(total)(ctx)
+            opts.self_lines(ctx)
+            return
+        end
         if opts.self_key and key == opts.self_key and opts.self_action then
             bv.rep(count_pre, opts.self_action)(ctx)
             return
         end
 
+        -- Text objects (diw, ci(, da"): the kernel shapes the range itself.
+        local objects = opts.text_objects and opts.text_objects[key]
+        if objects then
+            local ctx, obj_key = bv.read_key()
+            local obj = objects[obj_key]
+            if obj == nil then return end
+            bv.lift(obj)(ctx)
+            opts.mutation(ctx)
+            return
+        end
+
         -- Char-find motions — always inclusive.
         local ck = opts.char_finds and opts.char_finds[key]
         if ck then
@@ -238,3 +504,23 @@ function bv.operator(opts)
         opts.mutation(ctx)
     end
 end
+
+-- ── Default theme ──────────────────────────────────────────────────────────
+-- Keys are tree-sitter capture names; `function` also covers
+-- `function.method`, `function.builtin`, …. A gauchito.theme{…} call in
+-- init.lua replaces the whole table.
+
+gauchito.theme{
+    keyword        = { fg = "#c678dd" },
+    ["function"]   = { fg = "#61afef" },
+    type           = { fg = "#e5c07b" },
+    constructor    = { fg = "#e5c07b" },
+    string         = { fg = "#98c379" },
+    escape         = { fg = "#56b6c2" },
+    number         = { fg = "#d19a66" },
+    constant       = { fg = "#d19a66" },
+    comment        = { fg = "#5c6370", italic = true },
+    property       = { fg = "#e06c75" },
+    punctuation    = { fg = "#abb2bf" },
+    ["text.title"] = { fg = "#e06c75", bold = true },
+}
@@ -1,10 +1,91 @@
 -- Micro preset for gauchito.
 --
 -- Single-mode editor, readline-style. Arrow keys move, printable chars insert
--- via the `__fallback` handler, ctrl-combos for save/quit/undo/redo.
+-- via the `__fallback` handler, ctrl-combos for save/quit/undo/redo/find and
+-- the system clipboard (ctrl-c / ctrl-x / ctrl-v), alt-s to save all, alt-u to
+-- browse the undo tree, tab / backtab to shift selected lines.
 
 local k = bv.k
 
+-- Last pattern searched with ctrl-f, reused by ctrl-n / ctrl-p.
+local last_search = nil
+
+local function find(ctx)
+    local _, pattern = bv.search(ctx, "Find: ", k.search_forward)
+    if pattern then last_search = pattern end
+end
+
+local function find_next(kernel)
+    return function(ctx)
+        if last_search == nil then return end
+        local buf = ctx:text()
+        ctx:map_selections(function(_, head)
+            local h = kernel(buf, head, last_search)
+            return h, h
+        end)
+    end
+end
+
+-- tab shifts the lines of a non-empty selection and inserts an indent
+-- otherwise; backtab always shifts back.
+local function has_selection(ctx)
+    local sel = ctx:selection()
+    for i = 0, sel:len() - 1 do
+        local r = sel:range(i)
+        if r.anchor ~= r.head then return true end
+    end
+    return false
+end
+
+local function tab(ctx)
+    if has_selection(ctx) then
+        bv.fold_indent(bv.indent_lines)(ctx)
+    else
+        ctx:edit(bv.insert_text(ctx:text(), ctx:selection(), ctx:indent_unit()))
+    end
+end
+
+-- ctrl-c / ctrl-x take the selection, or the cursor's whole line when
+-- nothing is selected; ctrl-v replaces the selection.
+local function copy(ctx)
+    if has_selection(ctx) then
+        ctx:yank("+")
+        return
+    end
+    -- Put each cursor back where it was after copying its line.
+    local heads, i = {}, 0
+    ctx:map_selections(function(anchor, head)
+        heads[#heads + 1] = head
+        return anchor, head
+    end)
+    bv.select_lines(1)(ctx)
+    ctx:yank("+")
+    ctx:map_selections(function()
+        i = i + 1
+        return heads[i], heads[i]
+    end)
+end
+
+local function cut(ctx)
+    if not has_selection(ctx) then bv.select_lines(1)(ctx) end
+    if ctx:yank("+", "delete") then bv.fold(bv.delete_selection)(ctx) end
+end
+
+-- One undo step, and with as many clipboard entries as cursors each cursor
+-- gets its own.
+local function paste(ctx)
+    local text = ctx:register("+")
+    if text == nil then return end
+    ctx:transaction_start()
+    if has_selection(ctx) then bv.fold(bv.delete_selection)(ctx) end
+    -- Whole lines go above the cursor's; other text leaves the cursor on
+    -- its last char, so step past it.
+    if ctx:put("+", true) and text:sub(-1) ~= "\n" then
+        ctx:map_selections(function(_, head) return head + 1, head + 1 end)
+    end
+    ctx:transaction_commit()
+end
+
 local keys = {
     -- Motion.
     left      = bv.collapse(k.move_left),
@@ -17,23 +98,40 @@ local keys = {
     -- Editing.
     backspace = bv.fold(bv.delete_char_backward),
     del       = bv.fold(bv.delete_char_forward),
-    enter     = bv.fold(bv.insert_newline),
-    tab       = function(ctx) ctx:edit(bv.insert_text(ctx:text(), ctx:selection(), "    ")) end,
+    enter     = bv.fold_indent(bv.insert_newline_indent),
+    tab       = tab,
+    backtab   = bv.fold_indent(bv.dedent_lines),
 
     -- Commands.
-    ["ctrl-s"] = function(ctx) ctx:save() end,
-    ["ctrl-q"] = function(ctx) ctx:quit() end,
+    ["ctrl-s"] = bv.save,
+    ["alt-s"]  = function(ctx) ctx:save_all() end,
+    ["ctrl-q"] = bv.quit,
+    ["ctrl-c"] = copy,
+    ["ctrl-x"] = cut,
+    ["ctrl-v"] = paste,
     ["ctrl-z"] = function(ctx) ctx:undo() end,
     ["ctrl-y"] = function(ctx) ctx:redo() end,
+    ["alt-u"]  = bv.undo_tree,
+    ["ctrl-f"] = find,
+    ["ctrl-n"] = find_next(k.search_forward),
+    ["ctrl-p"] = find_next(k.search_backward),
 
     -- Printable fall-through.
     __fallback = function(ctx, _, ch)
         if ch then
-            ctx:edit(bv.insert_char(ctx:text(), ctx:selection(), ch))
+            ctx:edit(bv.insert_char_indent(
+                ctx:text(), ctx:selection(), ch, ctx:indent_unit(), ctx:tab_width()
+            ))
         end
     end,
 }
 
+-- ── Undo ───────────────────────────────────────────────────────────────────
+-- A run of typing or of backspaces is one undo step; moving the cursor or
+-- pausing for a second starts the next.
+
+gauchito.undo{ typing = true, deleting = true, idle = 1000 }
+
 return {
     initial_mode = "edit",
     modes = {
@@ -1,8 +1,12 @@
 -- Vim preset for gauchito.
 --
--- Three modes (normal/visual/insert), counts, operator-pending (d), char-find
--- (f/F/t/T), prefix sequences (gg/ge, ctrl-w-*), big-word motions, paragraph
--- motions, bracket match, multi-cursor (C/,), undo/redo.
+-- Three modes (normal/visual/insert), counts, operators (d c y > <) with text
+-- objects (iw, a(, i", ip, …), registers ("a p P), char-find (f/F/t/T),
+-- incremental search (/ ? n N), prefix sequences (gg/ge, ctrl-w-*), big-word
+-- motions, paragraph motions, bracket match, syntax-tree selection
+-- (alt-o/i/n/p, ]f [c ]a …), multi-cursor (C/,), undo/redo and the undo tree
+-- (g- g+ U), macros (q @), dot-repeat (.), marks (m ` ') and the jump list
+-- (ctrl-o ctrl-i), ZZ / ZQ.
 --
 -- Algebra and operator-pending live in `bv.*` (prelude). Here we just declare
 -- motion tables and wire keys.
@@ -31,6 +35,9 @@ local collapse_motions = {
     ["%"] = bv.collapse(k.match_bracket),
 }
 
+-- Normal-mode motions that go on the jump list.
+local jump_motions = { G = true, ["%"] = true }
+
 local extend_motions = {
     h     = bv.extend(k.move_left_inline),
     l     = bv.extend(k.move_right_inline),
@@ -80,6 +87,46 @@ local char_kernels = {
     T = k.find_char_backward_after,
 }
 
+-- Text objects for operator-pending `i` / `a`.
+local text_objects = {
+    i = {
+        w     = k.select_inner_word,
+        W     = k.select_inner_word_big,
+        p     = k.select_inner_paragraph,
+        ["("] = k.select_inner_paren,
+        [")"] = k.select_inner_paren,
+        b     = k.select_inner_paren,
+        ["["] = k.select_inner_bracket,
+        ["]"] = k.select_inner_bracket,
+        ["{"] = k.select_inner_brace,
+        ["}"] = k.select_inner_brace,
+        B     = k.select_inner_brace,
+        ["<"] = k.select_inner_angle,
+        [">"] = k.select_inner_angle,
+        ['"'] = k.select_inner_double_quote,
+        ["'"] = k.select_inner_single_quote,
+        ["`"] = k.select_inner_backtick,
+    },
+    a = {
+        w     = k.select_around_word,
+        W     = k.select_around_word_big,
+        p     = k.select_around_paragraph,
+        ["("] = k.select_around_paren,
+        [")"] = k.select_around_paren,
+        b     = k.select_around_paren,
+        ["["] = k.select_around_bracket,
+        ["]"] = k.select_around_bracket,
+        ["{"] = k.select_around_brace,
+        ["}"] = k.select_around_brace,
+        B     = k.select_around_brace,
+        ["<"] = k.select_around_angle,
+        [">"] = k.select_around_angle,
+        ['"'] = k.select_around_double_quote,
+        ["'"] = k.select_around_single_quote,
+        ["`"] = k.select_around_backtick,
+    },
+}
+
 -- ── Mode helpers ───────────────────────────────────────────────────────────
 
 local function enter_insert(ctx)
@@ -98,21 +145,157 @@ local function enter_visual(ctx)
     ctx:set_cursor_style("block")
 end
 
--- ── Operator d ─────────────────────────────────────────────────────────────
+-- ── Registers ──────────────────────────────────────────────────────────────
+-- `"x` picks register x for the next yank, delete, change or put; without
+-- one they use the unnamed register.
 
-local delete_line = bv.seq(
-    bv.lift(k.select_whole_line),
-    bv.fold(bv.delete_selection)
-)
+local pending_register = nil
+
+local function take_register()
+    local name = pending_register
+    pending_register = nil
+    return name
+end
+
+local function register_prefix(ctx)
+    local ctx, key = bv.read_key()
+    if #key == 1 then pending_register = key end
+end
+
+-- Copy the selections into the picked register; `kind` is "yank" or "delete".
+local function yank(kind)
+    return function(ctx) ctx:yank(take_register(), kind) end
+end
+
+-- Put the picked register `n` times (once without `n`). It's picked once,
+-- so `"a3p` puts a three times.
+local function put(before, n)
+    return function(ctx)
+        local name = take_register()
+        for _ = 1, n or 1 do ctx:put(name, before) end
+    end
+end
+
+local function collapse_to_start(ctx)
+    ctx:map_selections(function(anchor, head)
+        local from = math.min(anchor, head)
+        return from, from
+    end)
+end
+
+-- ── Macros ─────────────────────────────────────────────────────────────────
+-- `qa` records into register a until the next `q`; `@a` replays it and `@@`
+-- replays the last one replayed. `qA` appends to a.
+
+local last_macro = nil
+
+local function record_macro(ctx)
+    if ctx:recording() then
+        ctx:stop_macro()
+        return
+    end
+    local ctx, key = bv.read_key()
+    if key:match("^%a$") then ctx:record_macro(key) end
+end
+
+local function replay_macro(count)
+    return function(ctx)
+        local ctx, key = bv.read_key()
+        if key == "@" then key = last_macro end
+        if key == nil or #key ~= 1 then return end
+        last_macro = key
+        ctx:replay_macro(key, count)
+    end
+end
+
+-- ── Operators d c y ────────────────────────────────────────────────────────
+
+local delete = bv.seq(yank("delete"), bv.fold(bv.delete_selection))
+
+-- Operators that edit are repeatable changes for `.`; so are the single-key
+-- edits and insert entries in the normal keymap.
+local op_d = bv.repeatable(bv.operator({
+    mutation     = delete,
+    motions      = motion_kernels,
+    char_finds   = char_kernels,
+    text_objects = text_objects,
+    self_key     = "d",
+    self_lines   = delete,
+}))
+
+-- `x` / `X`: the `n` chars after (before) each cursor, deleted like `d` so
+-- they land in the registers.
+local function delete_chars(forward, n)
+    return bv.seq(
+        function(ctx)
+            local len = ctx:text():len()
+            ctx:map_selections(function(_, head)
+                if forward then return head, math.min(head + n, len) end
+                return math.max(head - n, 0), head
+            end)
+        end,
+        delete
+    )
+end
 
-local op_d = bv.operator({
-    mutation    = bv.fold(bv.delete_selection),
-    motions     = motion_kernels,
-    char_finds  = char_kernels,
-    self_key    = "d",
-    self_action = delete_line,
+-- The delete happens inside the insert's transaction, so `c` and what's
+-- typed after it undo together.
+local change = bv.seq(yank("delete"), enter_insert, bv.fold(bv.delete_selection))
+
+local op_c = bv.repeatable(bv.operator({
+    mutation     = change,
+    motions      = motion_kernels,
+    char_finds   = char_kernels,
+    text_objects = text_objects,
+    self_key     = "c",
+    -- `cc` keeps the last line's newline.
+    self_lines   = bv.seq(
+        function(ctx)
+            local buf = ctx:text()
+            ctx:map_selections(function(anchor, head)
+                if head > anchor and buf:char(head - 1) == "\n" then
+                    return anchor, head - 1
+                end
+                return anchor, head
+            end)
+        end,
+        change
+    ),
+}))
+
+local op_y = bv.operator({
+    mutation     = bv.seq(yank("yank"), collapse_to_start),
+    motions      = motion_kernels,
+    char_finds   = char_kernels,
+    text_objects = text_objects,
+    self_key     = "y",
+    self_lines   = bv.seq(yank("yank"), collapse_to_start),
 })
 
+-- ── Operators > < ──────────────────────────────────────────────────────────
+
+-- After shifting, land on the first non-blank of the first shifted line.
+local to_first_non_blank = bv.seq(
+    bv.lift(k.head_to_start),
+    bv.collapse(k.move_first_non_whitespace)
+)
+
+local function shift_operator(key, kernel)
+    local shift = bv.seq(bv.fold_indent(kernel), to_first_non_blank)
+    return bv.repeatable(bv.operator({
+        mutation     = shift,
+        motions      = motion_kernels,
+        char_finds   = char_kernels,
+        text_objects = text_objects,
+        self_key     = key,
+        -- `3>>` shifts three lines one level.
+        self_lines   = shift,
+    }))
+end
+
+local op_indent = shift_operator(">", bv.indent_lines)
+local op_dedent = shift_operator("<", bv.dedent_lines)
+
 -- ── Standalone char-find (no operator) ─────────────────────────────────────
 
 local function char_find(flavour, ck, count)
@@ -129,26 +312,95 @@ local function char_find(flavour, ck, count)
     end
 end
 
+-- ── Search ─────────────────────────────────────────────────────────────────
+
+-- Last accepted pattern and its direction, reused by n / N.
+local last_search = { pattern = nil, forward = true }
+
+local function search_prompt(forward)
+    return function(ctx)
+        local prefix = forward and "/" or "?"
+        local kernel = forward and k.search_forward or k.search_backward
+        local _, pattern = bv.search(ctx, prefix, kernel)
+        if pattern then
+            last_search.pattern = pattern
+            last_search.forward = forward
+        end
+    end
+end
+
+-- n repeats the last search in its direction; N reverses it.
+local function search_next(flavour, same_direction, count)
+    return function(ctx)
+        local pattern = last_search.pattern
+        if pattern == nil then return end
+        local forward = (last_search.forward == same_direction)
+        local kernel = forward and k.search_forward or k.search_backward
+        local buf = ctx:text()
+        ctx:map_selections(function(anchor, head)
+            local h = head
+            for _ = 1, count do h = kernel(buf, h, pattern) end
+            if flavour == "extend" then return anchor, h end
+            return h, h
+        end)
+    end
+end
+
+-- ── Syntax tree ────────────────────────────────────────────────────────────
+
+-- Structural kernels work on half-open ranges; visual selections include the
+-- char under the head. Convert on the way in and out.
+local function visual_object(kernel)
+    return bv.seq(bv.expand_high(1), bv.lift(kernel), bv.expand_high(-1))
+end
+
+-- Normal mode lands on the start of the node the kernel selects.
+local function jump_to(kernel)
+    return function(ctx)
+        ctx:push_jump()
+        local buf = ctx:text()
+        ctx:map_selections(function(anchor, head)
+            local r = kernel(buf, anchor, head)
+            local from = math.min(r.anchor, r.head)
+            return from, from
+        end)
+    end
+end
+
+local tree_jumps = {
+    ["]"] = { f = k.select_next_function, c = k.select_next_class, a = k.select_next_argument },
+    ["["] = { f = k.select_prev_function, c = k.select_prev_class, a = k.select_prev_argument },
+}
+
+-- `]f` / `[c` / `]a` …: next / previous function, class or argument.
+local function bracket_prefix(dir, wrap)
+    return function(ctx)
+        local ctx, key = bv.read_key()
+        local kernel = tree_jumps[dir][key]
+        if kernel then wrap(kernel)(ctx) end
+    end
+end
+
 -- ── Prefix sequences ───────────────────────────────────────────────────────
 
-local function g_prefix(motions)
+local function g_prefix(actions)
     return function(ctx)
         local ctx, key = bv.read_key()
-        if key == "g" then
-            motions.doc_start(ctx)
-        elseif key == "e" then
-            motions.doc_end(ctx)
-        end
+        local action = actions[key]
+        if action then action(ctx) end
     end
 end
 
 local g_collapse = {
-    doc_start = bv.collapse(k.move_doc_start),
-    doc_end   = bv.collapse(k.move_doc_end),
+    g       = bv.jump(bv.collapse(k.move_doc_start)),
+    e       = bv.jump(bv.collapse(k.move_doc_end)),
+    -- undo tree, in the order revisions were made (across branches)
+    ["-"]   = function(ctx) ctx:earlier(1) end,
+    ["+"]   = function(ctx) ctx:later(1) end,
 }
 local g_extend = {
-    doc_start = bv.extend(k.move_doc_start),
-    doc_end   = bv.extend(k.move_doc_end),
+    g       = bv.extend(k.move_doc_start),
+    e       = bv.extend(k.move_doc_end),
 }
 
 local function ctrl_w_prefix(ctx)
@@ -162,7 +414,35 @@ local function ctrl_w_prefix(ctx)
     elseif key == "W" then
         ctx:focus_prev()
     elseif key == "q" then
-        ctx:close_view()
+        bv.close_view(ctx)
+    end
+end
+
+-- ZZ: write every modified document and quit. ZQ: quit, discarding them.
+local function z_prefix(ctx)
+    local ctx, key = bv.read_key()
+    if key == "Z" then
+        ctx:quit{ write = true }
+    elseif key == "Q" then
+        ctx:quit{ force = true }
+    end
+end
+
+-- ── Marks ──────────────────────────────────────────────────────────────────
+
+-- m{a-zA-Z}: lowercase marks are per file, uppercase ones global.
+local function set_mark(ctx)
+    local ctx, key = bv.read_key()
+    ctx:set_mark(key)
+end
+
+-- `{mark} goes to the mark itself; '{mark} to the first non-blank of its line.
+local function goto_mark(linewise)
+    return function(ctx)
+        local ctx, key = bv.read_key()
+        if ctx:jump_to_mark(key) and linewise then
+            bv.collapse(k.move_first_non_whitespace)(ctx)
+        end
     end
 end
 
@@ -174,24 +454,47 @@ local function dispatch_counted(ctx, count, key)
     if key == "d" then
         op_d(ctx, n); return
     end
+    if key == "c" then
+        op_c(ctx, n); return
+    end
+    if key == "y" then
+        op_y(ctx, n); return
+    end
+    if key == ">" then
+        op_indent(ctx, n); return
+    end
+    if key == "<" then
+        op_dedent(ctx, n); return
+    end
     if key == "g" then
         g_prefix(g_collapse)(ctx); return
     end
+    if key == "@" then
+        replay_macro(n)(ctx); return
+    end
 
     local ck = char_kernels[key]
     if ck then
         char_find("collapse", ck, n)(ctx); return
     end
 
+    if key == "n" or key == "N" then
+        bv.jump(search_next("collapse", key == "n", n))(ctx); return
+    end
+
     local m = collapse_motions[key]
     if m then
+        if jump_motions[key] then m = bv.jump(m) end
         bv.rep(n, m)(ctx); return
     end
 
-    if key == "x" then
-        bv.rep(n, bv.fold(bv.delete_char_forward))(ctx); return
+    if key == "x" or key == "X" then
+        bv.repeatable(delete_chars(key == "x", n))(ctx); return
     end
-    if key == "X" then bv.rep(n, bv.fold(bv.delete_char_backward))(ctx) end
+    if key == "p" or key == "P" then
+        bv.repeatable(put(key == "P", n))(ctx); return
+    end
+    if key == "." then ctx:repeat_last_change(count ~= 0 and count or nil) end
 end
 
 local function vdispatch_counted(ctx, count, key)
@@ -206,6 +509,10 @@ local function vdispatch_counted(ctx, count, key)
         char_find("extend", ck, n)(ctx); return
     end
 
+    if key == "n" or key == "N" then
+        search_next("extend", key == "n", n)(ctx); return
+    end
+
     local m = extend_motions[key]
     if m then bv.rep(n, m)(ctx) end
 end
@@ -227,10 +534,10 @@ local normal_keys = {
     ["0"]      = collapse_motions["0"],
     ["$"]      = collapse_motions["$"],
     ["^"]      = collapse_motions["^"],
-    G          = collapse_motions.G,
+    G          = bv.jump(collapse_motions.G),
     ["{"]      = collapse_motions["{"],
     ["}"]      = collapse_motions["}"],
-    ["%"]      = collapse_motions["%"],
+    ["%"]      = bv.jump(collapse_motions["%"]),
 
     -- char-find
     f          = char_find("collapse", k.find_char_forward, 1),
@@ -238,27 +545,59 @@ local normal_keys = {
     t          = char_find("collapse", k.find_char_forward_before, 1),
     T          = char_find("collapse", k.find_char_backward_after, 1),
 
+    -- search
+    ["/"]      = bv.jump(search_prompt(true)),
+    ["?"]      = bv.jump(search_prompt(false)),
+    n          = bv.jump(search_next("collapse", true, 1)),
+    N          = bv.jump(search_next("collapse", false, 1)),
+
     -- prefixes
     g          = g_prefix(g_collapse),
     ["ctrl-w"] = ctrl_w_prefix,
+    Z          = z_prefix,
+    ["]"]      = bracket_prefix("]", jump_to),
+    ["["]      = bracket_prefix("[", jump_to),
+
+    -- syntax tree: select the node around the cursor
+    ["alt-o"]  = bv.seq(visual_object(k.expand_selection), enter_visual),
 
     -- operator
     d          = function(ctx) op_d(ctx, 1) end,
-    D          = bv.seq(
+    c          = function(ctx) op_c(ctx, 1) end,
+    y          = function(ctx) op_y(ctx, 1) end,
+    [">"]      = function(ctx) op_indent(ctx, 1) end,
+    ["<"]      = function(ctx) op_dedent(ctx, 1) end,
+    D          = bv.repeatable(bv.seq(
         bv.extend(k.move_line_end),
         bv.expand_high(1),
-        bv.fold(bv.delete_selection)
-    ),
+        delete
+    )),
+
+    -- registers / macros
+    ['"']      = register_prefix,
+    p          = bv.repeatable(put(false)),
+    P          = bv.repeatable(put(true)),
+    q          = record_macro,
+    ["@"]      = replay_macro(1),
+
+    -- marks / jump list
+    m          = set_mark,
+    ["`"]      = goto_mark(false),
+    ["'"]      = goto_mark(true),
+    ["ctrl-o"] = function(ctx) ctx:jump_back() end,
+    ["ctrl-i"] = function(ctx) ctx:jump_forward() end,
+    tab        = function(ctx) ctx:jump_forward() end,
 
     -- single-key edits
-    x          = bv.fold(bv.delete_char_forward),
-    X          = bv.fold(bv.delete_char_backward),
-    J          = bv.seq(
+    x          = bv.repeatable(delete_chars(true, 1)),
+    X          = bv.repeatable(delete_chars(false, 1)),
+    ["."]      = function(ctx) ctx:repeat_last_change() end,
+    J          = bv.repeatable(bv.seq(
         bv.collapse(k.move_line_end),
         bv.collapse(k.move_right),
         bv.fold(bv.delete_char_backward),
         function(ctx) ctx:edit(bv.insert_text(ctx:text(), ctx:selection(), " ")) end
-    ),
+    )),
 
     -- multi-cursor
     C          = bv.add_cursor(k.move_down),
@@ -267,27 +606,32 @@ local normal_keys = {
     -- history / system
     u          = function(ctx) ctx:undo() end,
     ["ctrl-r"] = function(ctx) ctx:redo() end,
-    ["ctrl-s"] = function(ctx) ctx:save() end,
-    ["ctrl-q"] = function(ctx) ctx:quit() end,
-
-    -- mode switches
-    i          = enter_insert,
-    I          = bv.seq(bv.collapse(k.move_first_non_whitespace), enter_insert),
-    a          = bv.seq(bv.collapse(k.move_right), enter_insert),
-    A          = bv.seq(bv.collapse(k.move_line_end), bv.collapse(k.move_right), enter_insert),
+    U          = bv.undo_tree,
+    ["ctrl-s"] = bv.save,
+    ["ctrl-q"] = bv.quit,
+
+    -- mode switches (what's typed until esc is part of the change)
+    i          = bv.repeatable(enter_insert),
+    I          = bv.repeatable(bv.seq(bv.collapse(k.move_first_non_whitespace), enter_insert)),
+    a          = bv.repeatable(bv.seq(bv.collapse(k.move_right), enter_insert)),
+    A          = bv.repeatable(bv.seq(
+        bv.collapse(k.move_line_end),
+        bv.collapse(k.move_right),
+        enter_insert
+    )),
 
-    o          = bv.seq(
+    o          = bv.repeatable(bv.seq(
         bv.collapse(k.move_line_end),
         bv.collapse(k.move_right),
         enter_insert,
-        function(ctx) ctx:edit(bv.insert_char(ctx:text(), ctx:selection(), "\n")) end
-    ),
-    O          = bv.seq(
+        bv.fold_indent(bv.insert_newline_indent)
+    )),
+    O          = bv.repeatable(bv.seq(
         bv.collapse(k.move_line_start),
         enter_insert,
-        function(ctx) ctx:edit(bv.insert_char(ctx:text(), ctx:selection(), "\n")) end,
+        bv.fold(bv.insert_line_above_indent),
         bv.collapse(k.move_left)
-    ),
+    )),
 
     v          = enter_visual,
 
@@ -300,13 +644,31 @@ local normal_keys = {
 }
 
 -- Visual-mode "make selection inclusive on delete": same shape as
--- bv.expand_high(1). Reused for v-mode `d` and `x`.
+-- bv.expand_high(1). Reused for v-mode `d`, `x`, `c` and `y`.
 local visual_delete = bv.seq(
     bv.expand_high(1),
-    bv.fold(bv.delete_selection),
+    delete,
+    enter_normal
+)
+
+local visual_change = bv.seq(bv.expand_high(1), change)
+
+local visual_yank = bv.seq(
+    bv.expand_high(1),
+    yank("yank"),
+    collapse_to_start,
     enter_normal
 )
 
+local function visual_shift(kernel)
+    return bv.seq(
+        bv.expand_high(1),
+        bv.fold_indent(kernel),
+        to_first_non_blank,
+        enter_normal
+    )
+end
+
 local visual_keys = {
     h          = extend_motions.h,
     l          = extend_motions.l,
@@ -331,13 +693,28 @@ local visual_keys = {
     t          = char_find("extend", k.find_char_forward_before, 1),
     T          = char_find("extend", k.find_char_backward_after, 1),
 
+    n          = search_next("extend", true, 1),
+    N          = search_next("extend", false, 1),
+
     g          = g_prefix(g_extend),
+    ["]"]      = bracket_prefix("]", visual_object),
+    ["["]      = bracket_prefix("[", visual_object),
+
+    ["alt-o"]  = visual_object(k.expand_selection),
+    ["alt-i"]  = visual_object(k.shrink_selection),
+    ["alt-n"]  = visual_object(k.select_next_sibling),
+    ["alt-p"]  = visual_object(k.select_prev_sibling),
 
     o          = function(ctx)
         ctx:map_selections(function(anchor, head) return head, anchor end)
     end,
     d          = visual_delete,
     x          = visual_delete,
+    c          = visual_change,
+    y          = visual_yank,
+    ['"']      = register_prefix,
+    [">"]      = visual_shift(bv.indent_lines),
+    ["<"]      = visual_shift(bv.dedent_lines),
     esc        = bv.seq(
         function(ctx)
             ctx:map_selections(function(_, head) return head, head end)
@@ -345,8 +722,8 @@ local visual_keys = {
         enter_normal
     ),
 
-    ["ctrl-s"] = function(ctx) ctx:save() end,
-    ["ctrl-q"] = function(ctx) ctx:quit() end,
+    ["ctrl-s"] = bv.save,
+    ["ctrl-q"] = bv.quit,
 
     __fallback = function(ctx, key)
         if not bv.is_digit(key) then return end
@@ -368,21 +745,29 @@ local insert_keys = {
     down       = bv.collapse(k.move_down),
     backspace  = bv.fold(bv.delete_char_backward),
     del        = bv.fold(bv.delete_char_forward),
-    enter      = bv.fold(bv.insert_newline),
-    tab        = function(ctx) ctx:edit(bv.insert_text(ctx:text(), ctx:selection(), "    ")) end,
+    enter      = bv.fold_indent(bv.insert_newline_indent),
+    tab        = function(ctx) ctx:edit(bv.insert_text(ctx:text(), ctx:selection(), ctx:indent_unit())) end,
     home       = bv.collapse(k.move_line_start),
     ["end"]    = bv.collapse(k.move_line_end),
-    ["ctrl-s"] = function(ctx) ctx:save() end,
-    ["ctrl-q"] = function(ctx) ctx:quit() end,
+    ["ctrl-s"] = bv.save,
+    ["ctrl-q"] = bv.quit,
 
     -- Printable fall-through. `ch` is nil for non-printable keys.
     __fallback = function(ctx, _, ch)
         if ch then
-            ctx:edit(bv.insert_char(ctx:text(), ctx:selection(), ch))
+            ctx:edit(bv.insert_char_indent(
+                ctx:text(), ctx:selection(), ch, ctx:indent_unit(), ctx:tab_width()
+            ))
         end
     end,
 }
 
+-- ── Undo ───────────────────────────────────────────────────────────────────
+-- Insert mode is one explicit transaction; in normal mode every command is
+-- its own step, so `xxx` takes three `u`s as in vim.
+
+gauchito.undo{ typing = false, deleting = false }
+
 return {
     initial_mode = "normal",
     modes = {
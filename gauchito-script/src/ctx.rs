@@ -5,35 +5,64 @@
 //! per-dispatch effects accumulator. Methods take the cell out via
 //! `borrow_mut()` for the duration of one Lua call — never across a yield.
 //!
-//! The bridge is intentionally narrow: queries (`text`, `selection`, `mode`),
-//! one-shot mutations (`set_selection`, `map_selections`, `edit`), mode and
-//! transaction state, splits, and lifecycle effects. All motion / shape /
+//! The bridge is intentionally narrow: queries (`text`, `selection`,
+//! `document`, `mode`, `syntax`), one-shot mutations (`set_selection`,
+//! `map_selections`, `edit`), registers, macros and marks, mode, transaction and
+//! undo-tree state, the prompt line, splits, and lifecycle effects. All motion / shape /
 //! mutation logic lives in pure kernels (`bv.k.*`) and Lua combinators
 //! (`bv.collapse`, `bv.fold`, …); the preset composes them.
 
 use std::cell::RefCell;
+use std::path::Path;
 use std::rc::Rc;
+use std::time::{Duration, UNIX_EPOCH};
 
+use encoding_rs::Encoding;
 use mlua::prelude::*;
+use ropey::Rope;
 
+use gauchito_core::changeset::{ChangeBuilder, ChangeSet};
+use gauchito_core::document::{Document, DocumentId};
+use gauchito_core::fileio::{self, DiskStamp};
 use gauchito_core::history::SelectionSnapshot;
+use gauchito_core::macros::Macros;
+use gauchito_core::marks::Marks;
+use gauchito_core::registers::{self, Register, Registers, WriteKind};
 use gauchito_core::selection::{Range, Selection};
 use gauchito_ui::{CursorStyle, EditorState, Effect, SplitDirection};
 
-use crate::userdata::{LuaBuffer, LuaChangeSet, LuaSelection};
+use crate::userdata::{LuaBuffer, LuaChangeSet, LuaDocument, LuaNode, LuaSelection};
 
 pub type SharedState = Rc<RefCell<EditorState>>;
 pub type SharedEffects = Rc<RefCell<Vec<Effect>>>;
+pub type SharedRegisters = Rc<RefCell<Registers>>;
+pub type SharedMacros = Rc<RefCell<Macros>>;
+pub type SharedMarks = Rc<RefCell<Marks>>;
 
 #[derive(Clone)]
 pub struct Ctx {
     state: SharedState,
     effects: SharedEffects,
+    registers: SharedRegisters,
+    macros: SharedMacros,
+    marks: SharedMarks,
 }
 
 impl Ctx {
-    pub fn new(state: SharedState, effects: SharedEffects) -> Self {
-        Ctx { state, effects }
+    pub fn new(
+        state: SharedState,
+        effects: SharedEffects,
+        registers: SharedRegisters,
+        macros: SharedMacros,
+        marks: SharedMarks,
+    ) -> Self {
+        Ctx {
+            state,
+            effects,
+            registers,
+            macros,
+            marks,
+        }
     }
 }
 
@@ -44,7 +73,26 @@ impl LuaUserData for Ctx {
         methods.add_method("mode", |_, this, ()| Ok(this.state.borrow().mode.clone()));
 
         methods.add_method("text", |_, this, ()| {
-            Ok(LuaBuffer(this.state.borrow().focused_doc().text.clone()))
+            let mut s = this.state.borrow_mut();
+            let doc = focused_doc_mut(&mut s);
+            let tree = doc.syntax_tree().cloned();
+            Ok(LuaBuffer(doc.text.clone(), tree))
+        });
+
+        // Root node of the focused document's syntax tree, or nil.
+        methods.add_method("syntax", |_, this, ()| {
+            let mut s = this.state.borrow_mut();
+            let doc = focused_doc_mut(&mut s);
+            let tree = doc.syntax_tree().cloned();
+            Ok(tree.map(|tree| LuaNode::root(doc.text.clone(), tree)))
+        });
+
+        methods.add_method("language", |_, this, ()| {
+            let s = this.state.borrow();
+            Ok(s.focused_doc()
+                .syntax
+                .as_ref()
+                .map(|syntax| syntax.language().name()))
         });
 
         methods.add_method("selection", |_, this, ()| {
@@ -54,6 +102,42 @@ impl LuaUserData for Ctx {
             Ok(LuaSelection(view.selection.snapshot(&doc.anchors)))
         });
 
+        // File-level state of the focused document: `doc:name()`,
+        // `doc:path()`, `doc:is_modified()`, … (see `LuaDocument`).
+        methods.add_method("document", |_, this, ()| {
+            Ok(LuaDocument::of(this.state.borrow().focused_doc()))
+        });
+
+        // One indent level for the focused document (`"\t"` or spaces).
+        methods.add_method("indent_unit", |_, this, ()| {
+            Ok(this.state.borrow().focused_doc().options.indent_style.unit())
+        });
+
+        // Columns a tab takes up in the focused document.
+        methods.add_method("tab_width", |_, this, ()| {
+            Ok(this.state.borrow().focused_doc().options.tab_width)
+        });
+
+        // Ruler column for the focused document, or nil.
+        methods.add_method("max_line_length", |_, this, ()| {
+            Ok(this.state.borrow().focused_doc().options.max_line_length)
+        });
+
+        // Encoding the focused document is saved in, e.g. `"UTF-8"`.
+        methods.add_method("encoding", |_, this, ()| {
+            Ok(this.state.borrow().focused_doc().options.encoding.name())
+        });
+
+        // Save the focused document in the encoding named by `label`
+        // (`"latin1"`, `"utf-16le"`, `"shift_jis"`, …). The text stays as it
+        // is; `reload_as` decodes the file again instead.
+        methods.add_method("set_encoding", |_, this, label: String| {
+            let encoding = encoding_for(&label)?;
+            let mut s = this.state.borrow_mut();
+            focused_doc_mut(&mut s).options.encoding = encoding;
+            Ok(())
+        });
+
         // ── Mode / cursor style ─────────────────────────────────────────
 
         methods.add_method("set_mode", |_, this, name: String| {
@@ -70,6 +154,20 @@ impl LuaUserData for Ctx {
             Ok(())
         });
 
+        // ── Prompt overlay ──────────────────────────────────────────────
+        // The overlay only displays a line of text; reading keys into it is
+        // the preset's job (see `bv.prompt` in the prelude).
+
+        methods.add_method("set_prompt", |_, this, text: String| {
+            this.state.borrow_mut().prompt = Some(text);
+            Ok(())
+        });
+
+        methods.add_method("clear_prompt", |_, this, ()| {
+            this.state.borrow_mut().prompt = None;
+            Ok(())
+        });
+
         // ── Selection mutation ──────────────────────────────────────────
 
         methods.add_method("set_selection", |_, this, sel: LuaSelection| {
@@ -88,25 +186,24 @@ impl LuaUserData for Ctx {
                 view.selection.snapshot(&doc.anchors)
             };
 
-            let new_ranges: Vec<(usize, usize)> = snap
-                .ranges
-                .iter()
-                .map(|&(a, h)| {
-                    let result: LuaMultiValue = f.call((a, h))?;
-                    let vals: Vec<LuaValue> = result.into_vec();
-                    let na: usize = vals
-                        .first()
-                        .and_then(|v| v.as_integer())
-                        .ok_or_else(|| LuaError::runtime("map_selections: anchor not int"))?
-                        as usize;
-                    let nh: usize = vals
-                        .get(1)
-                        .and_then(|v| v.as_integer())
-                        .ok_or_else(|| LuaError::runtime("map_selections: head not int"))?
-                        as usize;
-                    Ok((na, nh))
-                })
-                .collect::<LuaResult<Vec<_>>>()?;
+            let new_ranges: Vec<(usize, usize)> =
+                snap.ranges
+                    .iter()
+                    .map(|&(a, h)| {
+                        let result: LuaMultiValue = f.call((a, h))?;
+                        let vals: Vec<LuaValue> = result.into_vec();
+                        let na: usize =
+                            vals.first().and_then(|v| v.as_integer()).ok_or_else(|| {
+                                LuaError::runtime("map_selections: anchor not int")
+                            })? as usize;
+                        let nh: usize = vals
+                            .get(1)
+                            .and_then(|v| v.as_integer())
+                            .ok_or_else(|| LuaError::runtime("map_selections: head not int"))?
+                            as usize;
+                        Ok((na, nh))
+                    })
+                    .collect::<LuaResult<Vec<_>>>()?;
 
             replace_focused_selection(
                 this,
@@ -122,11 +219,229 @@ impl LuaUserData for Ctx {
 
         methods.add_method("edit", |_, this, cs: LuaChangeSet| {
             let mut s = this.state.borrow_mut();
+            if s.focused_doc().read_only {
+                return Ok(());
+            }
             let doc_id = s.focused_doc().id;
-            s.apply_edit(doc_id, cs.0);
+            crate::apply_edit(&mut s, doc_id, cs.0);
+            this.macros.borrow_mut().note_edit();
+            Ok(())
+        });
+
+        // ── Registers ───────────────────────────────────────────────────
+        // `register` is a one-char name (see `gauchito_core::registers`);
+        // nil means the unnamed one. `+` and `*` reach the system clipboard;
+        // when it fails the error goes to the prompt line.
+
+        // Copy each selected range into `register`, one entry per cursor.
+        // `kind` `"delete"` files it with the deletes (`1`–`9`) instead of
+        // the last yank (`0`); the caller deletes the text itself. Returns
+        // false if the register couldn't take it.
+        methods.add_method(
+            "yank",
+            |_, this, (name, kind): (Option<String>, Option<String>)| {
+                let name = register_name(name)?;
+                let kind = match kind.as_deref() {
+                    None | Some("yank") => WriteKind::Yank,
+                    Some("delete") => WriteKind::Delete,
+                    Some(other) => {
+                        return Err(LuaError::runtime(format!("unknown yank kind: {other}")));
+                    }
+                };
+                let value = {
+                    let s = this.state.borrow();
+                    let view = s.focused_view();
+                    let doc = &s.documents[&view.doc_id];
+                    selected_text(&doc.text, &view.selection.snapshot(&doc.anchors))
+                };
+                let written = this.registers.borrow_mut().write(name, kind, value);
+                Ok(register_result(this, written).is_some())
+            },
+        );
+
+        // Put `register` after each cursor, or before it with `before`.
+        // With as many entries as cursors each cursor gets its own, else
+        // each gets all of them; linewise text goes on new lines below
+        // (above) the cursor's. Returns false if there was nothing to put.
+        methods.add_method(
+            "put",
+            |_, this, (name, before): (Option<String>, Option<bool>)| {
+                let name = register_name(name)?;
+                let read = this.registers.borrow().read(name);
+                let Some(Some(reg)) = register_result(this, read) else {
+                    return Ok(false);
+                };
+                let (cs, snap) = {
+                    let s = this.state.borrow();
+                    let view = s.focused_view();
+                    let doc = &s.documents[&view.doc_id];
+                    if doc.read_only {
+                        return Ok(false);
+                    }
+                    let snap = view.selection.snapshot(&doc.anchors);
+                    put_changes(&doc.text, &snap, &reg, before.unwrap_or(false))
+                };
+                {
+                    let mut s = this.state.borrow_mut();
+                    let doc_id = s.focused_doc().id;
+                    crate::apply_edit(&mut s, doc_id, cs);
+                }
+                this.macros.borrow_mut().note_edit();
+                replace_focused_selection(this, snap);
+                Ok(true)
+            },
+        );
+
+        // Contents of `register` as one string, or nil if it's empty.
+        methods.add_method("register", |_, this, name: Option<String>| {
+            let name = register_name(name)?;
+            let read = this.registers.borrow().read(name);
+            Ok(register_result(this, read)
+                .flatten()
+                .map(|reg| reg.joined()))
+        });
+
+        // ── Macros ──────────────────────────────────────────────────────
+        // Recorded into and replayed from registers; see
+        // `gauchito_core::macros` for the text they're kept as.
+
+        // Start recording keys into `register` (`a`–`z`, or `A`–`Z` to
+        // append).
+        methods.add_method("record_macro", |_, this, name: String| {
+            let name = register_name(Some(name))?;
+            if !name.is_ascii_alphabetic() {
+                return Err(LuaError::runtime(format!(
+                    "can't record into register {name}"
+                )));
+            }
+            this.macros.borrow_mut().start(name);
+            notify(&mut this.state.borrow_mut(), format!("recording @{name}"));
             Ok(())
         });
 
+        // Stop recording. The keys of the command calling this aren't kept.
+        // Returns the register recorded into, or nil if none was.
+        methods.add_method("stop_macro", |_, this, ()| {
+            let Some((name, text)) = this.macros.borrow_mut().stop() else {
+                return Ok(None);
+            };
+            let recorded = this.registers.borrow_mut().record(name, text);
+            if register_result(this, recorded).is_none() {
+                return Ok(None);
+            }
+            this.state.borrow_mut().prompt = None;
+            Ok(Some(name.to_string()))
+        });
+
+        // The register being recorded into, or nil.
+        methods.add_method("recording", |_, this, ()| {
+            Ok(this.macros.borrow().recording().map(String::from))
+        });
+
+        // Replay the macro in `register` `count` times once this key's
+        // handler returns. Returns false if the register is empty.
+        methods.add_method(
+            "replay_macro",
+            |_, this, (name, count): (String, Option<usize>)| {
+                let name = register_name(Some(name))?;
+                let read = this.registers.borrow().read(name);
+                let Some(Some(reg)) = register_result(this, read) else {
+                    return Ok(false);
+                };
+                let queued = this
+                    .macros
+                    .borrow_mut()
+                    .queue(&reg.joined(), count.unwrap_or(1));
+                Ok(queued)
+            },
+        );
+
+        // ── Dot-repeat ──────────────────────────────────────────────────
+
+        // This key's command is a repeatable change: `repeat_last_change`
+        // replays its keys, from the command's first through its end or
+        // the commit of the transaction it opens. Presets call it through
+        // `bv.repeatable`.
+        methods.add_method("begin_change", |_, this, ()| {
+            this.macros.borrow_mut().begin_change();
+            Ok(())
+        });
+
+        // Replay the last change once this key's handler returns, on the
+        // cursors as they are then. `count` replaces the count it was typed
+        // with. Returns false if nothing has changed yet.
+        methods.add_method("repeat_last_change", |_, this, count: Option<usize>| {
+            Ok(this.macros.borrow_mut().repeat_last_change(count))
+        });
+
+        // ── Marks and jumps ─────────────────────────────────────────────
+        // Kept as anchors, so they follow edits; see `gauchito_core::marks`.
+
+        // Put mark `name` on the primary cursor: `a`–`z` in this document,
+        // `A`–`Z` across documents. Returns false for other names.
+        methods.add_method("set_mark", |_, this, name: String| {
+            let Some(name) = mark_name(&name) else {
+                return Ok(false);
+            };
+            let mut s = this.state.borrow_mut();
+            let (doc, offset) = focused_cursor(&s);
+            Ok(this
+                .marks
+                .borrow_mut()
+                .set(name, doc, offset, &mut s.documents))
+        });
+
+        // Move to mark `name`, switching document for a global mark, and
+        // record the jump. Returns false if the mark isn't set or its
+        // document has been closed.
+        methods.add_method("jump_to_mark", |_, this, name: String| {
+            let to = {
+                let s = this.state.borrow();
+                let (doc, _) = focused_cursor(&s);
+                mark_name(&name).and_then(|c| this.marks.borrow().get(c, doc, &s.documents))
+            };
+            let Some(to) = to else {
+                notify(&mut this.state.borrow_mut(), format!("mark {name} not set"));
+                return Ok(false);
+            };
+            push_jump(this);
+            jump(this, to);
+            Ok(true)
+        });
+
+        // Remember the primary cursor in the jump list. Presets call it
+        // before large motions, through `bv.jump`.
+        methods.add_method("push_jump", |_, this, ()| {
+            push_jump(this);
+            Ok(())
+        });
+
+        // Back / forward through the jump list. Return false at either end.
+        methods.add_method("jump_back", |_, this, ()| {
+            let to = {
+                let mut s = this.state.borrow_mut();
+                let (doc, offset) = focused_cursor(&s);
+                this.marks.borrow_mut().back(doc, offset, &mut s.documents)
+            };
+            let Some(to) = to else {
+                return Ok(false);
+            };
+            jump(this, to);
+            Ok(true)
+        });
+
+        methods.add_method("jump_forward", |_, this, ()| {
+            let to = {
+                let s = this.state.borrow();
+                this.marks.borrow_mut().forward(&s.documents)
+            };
+            let Some(to) = to else {
+                return Ok(false);
+            };
+            jump(this, to);
+            Ok(true)
+        });
+
         // ── History / transactions ──────────────────────────────────────
 
         methods.add_method("undo", |_, this, ()| {
@@ -139,19 +454,110 @@ impl LuaUserData for Ctx {
             Ok(())
         });
 
+        // ── Undo tree ───────────────────────────────────────────────────
+        // Revision ids count commits across every branch, so `earlier` /
+        // `later` by steps walk all of them in the order they were made.
+
+        // Every revision of the focused document as `{id, parent, graph,
+        // time, saved, current}`, depth first; `graph` is the tree drawn
+        // left of the revision and `time` is seconds since the epoch.
+        methods.add_method("undo_tree", |lua, this, ()| {
+            let s = this.state.borrow();
+            let history = s.focused_doc().history();
+            let rows = lua.create_table()?;
+            for row in history.tree() {
+                let info = history.revision(row.id).unwrap();
+                let time = info
+                    .timestamp
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0.0, |d| d.as_secs_f64());
+                let t = lua.create_table()?;
+                t.set("id", row.id)?;
+                t.set("parent", info.parent)?;
+                t.set("graph", row.graph)?;
+                t.set("time", time)?;
+                t.set("saved", info.saved)?;
+                t.set("current", row.id == history.current())?;
+                rows.push(t)?;
+            }
+            Ok(rows)
+        });
+
+        // Tips of every branch, oldest first.
+        methods.add_method("undo_branches", |_, this, ()| {
+            Ok(this.state.borrow().focused_doc().history().branches())
+        });
+
+        methods.add_method("undo_goto", |_, this, id: usize| {
+            goto_revision(this, id);
+            Ok(())
+        });
+
+        // `spec` is a step count, or a string: `"30s"`, `"5m"`, `"2h"`,
+        // `"1d"` for wall-clock time, `"1f"` for save points.
+        methods.add_method("earlier", |_, this, spec: LuaValue| {
+            let target = undo_target(this, &spec, false)?;
+            goto_revision(this, target);
+            Ok(())
+        });
+
+        methods.add_method("later", |_, this, spec: LuaValue| {
+            let target = undo_target(this, &spec, true)?;
+            goto_revision(this, target);
+            Ok(())
+        });
+
+        // Explicit transactions override coalescing: the group starts and
+        // ends exactly here. While a macro replays they're folded into the
+        // replay's own transaction.
         methods.add_method("transaction_start", |_, this, ()| {
+            let replaying = {
+                let mut macros = this.macros.borrow_mut();
+                macros.set_transaction(true);
+                macros.replaying()
+            };
+            if replaying {
+                return Ok(());
+            }
             let mut s = this.state.borrow_mut();
             let doc_id = s.focused_doc().id;
             let view_id = s.focused;
+            s.documents.get_mut(&doc_id).unwrap().seal_history();
             s.transaction_start(doc_id, view_id);
             Ok(())
         });
 
         methods.add_method("transaction_commit", |_, this, ()| {
+            let replaying = {
+                let mut macros = this.macros.borrow_mut();
+                macros.set_transaction(false);
+                macros.replaying()
+            };
+            if replaying {
+                return Ok(());
+            }
             let mut s = this.state.borrow_mut();
             let doc_id = s.focused_doc().id;
             let view_id = s.focused;
             s.transaction_commit(doc_id, view_id);
+            let doc = s.documents.get_mut(&doc_id).unwrap();
+            doc.seal_history();
+            // What was typed becomes the `.` register.
+            if let Some(text) = doc
+                .history()
+                .last_transaction()
+                .and_then(|txn| registers::inserted_text(txn, &doc.text))
+            {
+                this.registers.borrow_mut().set_last_insert(text);
+            }
+            Ok(())
+        });
+
+        // Make the next edit start a new undo step.
+        methods.add_method("undo_boundary", |_, this, ()| {
+            let mut s = this.state.borrow_mut();
+            let doc_id = s.focused_doc().id;
+            s.documents.get_mut(&doc_id).unwrap().seal_history();
             Ok(())
         });
 
@@ -224,29 +630,460 @@ impl LuaUserData for Ctx {
             Ok(())
         });
 
-        methods.add_method("close_view", |_, this, ()| {
-            if this.state.borrow_mut().close_view() {
+        // Refused, like `quit`, when this is the last view of a modified
+        // document (or the last view at all). Takes the same options.
+        methods.add_method("close_view", |_, this, opts: Option<LuaTable>| {
+            let opts = QuitOptions::from_lua(opts)?;
+            let mut s = this.state.borrow_mut();
+            let closing: Vec<DocumentId> = if s.views.len() == 1 {
+                s.documents.keys().copied().collect()
+            } else {
+                let doc_id = s.focused_doc().id;
+                let shown = s.views.values().filter(|v| v.doc_id == doc_id).count();
+                if shown == 1 { vec![doc_id] } else { Vec::new() }
+            };
+            if !release(&mut s, &closing, opts) {
+                return Ok(false);
+            }
+            if s.close_view() {
                 this.effects.borrow_mut().push(Effect::Quit);
             }
-            Ok(())
+            Ok(true)
         });
 
         // ── Effects (deferred to app) ──────────────────────────────────
 
+        // Saving returns `true`, or `false, message` after showing the
+        // message as a notification.
         methods.add_method("save", |_, this, ()| {
-            let _ = this.state.borrow().focused_doc().write();
+            let mut s = this.state.borrow_mut();
+            let doc_id = s.focused_doc().id;
+            let result = save_document(&mut s, doc_id);
+            Ok(reported(&mut s, result))
+        });
+
+        // `ctx:save_as(path, { create_dirs = true, force = true })`. The
+        // document takes the new path, and its options are re-read from
+        // `.editorconfig` there. Another file already at `path` is only
+        // replaced with `force`.
+        methods.add_method(
+            "save_as",
+            |_, this, (path, opts): (String, Option<LuaTable>)| {
+                let (create_dirs, force) = match opts {
+                    Some(t) => (
+                        t.get::<Option<bool>>("create_dirs")?.unwrap_or(false),
+                        t.get::<Option<bool>>("force")?.unwrap_or(false),
+                    ),
+                    None => (false, false),
+                };
+                let mut s = this.state.borrow_mut();
+                let doc = focused_doc_mut(&mut s);
+                let target = fileio::absolute(Path::new(&path));
+                let result = if !force && doc.path.as_ref() != Some(&target) && target.exists() {
+                    Err(format!("{path}: file exists, not overwritten"))
+                } else {
+                    fileio::save_as(doc, target, create_dirs)
+                        .map(|()| doc.mark_saved())
+                        .map_err(|e| format!("{path}: {e}"))
+                };
+                Ok(reported(&mut s, result))
+            },
+        );
+
+        // Every modified document; the message lists each one that failed.
+        methods.add_method("save_all", |_, this, ()| {
+            let mut s = this.state.borrow_mut();
+            let mut ids: Vec<DocumentId> = s.documents.keys().copied().collect();
+            ids.sort_unstable();
+            let mut errors = Vec::new();
+            for id in ids {
+                if s.documents[&id].is_modified()
+                    && let Err(e) = save_document(&mut s, id)
+                {
+                    errors.push(e);
+                }
+            }
+            let result = if errors.is_empty() {
+                Ok(())
+            } else {
+                Err(errors.join("; "))
+            };
+            Ok(reported(&mut s, result))
+        });
+
+        // Re-read the focused document's file, keeping selections and undo
+        // history: only changed lines are replaced, as one undoable edit.
+        methods.add_method("reload", |_, this, ()| {
+            let mut s = this.state.borrow_mut();
+            let doc_id = s.focused_doc().id;
+            let doc = s.documents.get_mut(&doc_id).unwrap();
+            let result = fileio::reload(doc)
+                .map(|_| ())
+                .map_err(|e| format!("{}: {e}", doc.name()));
+            Ok(reported(&mut s, result))
+        });
+
+        // Read the focused document's file again, decoding it as `label`
+        // this time; for files that opened in the wrong encoding. Undoable,
+        // like `reload`.
+        methods.add_method("reload_as", |_, this, label: String| {
+            let encoding = encoding_for(&label)?;
+            let mut s = this.state.borrow_mut();
+            let doc = focused_doc_mut(&mut s);
+            let result = fileio::reload_as(doc, encoding)
+                .map(|_| ())
+                .map_err(|e| format!("{}: {e}", doc.name()));
+            Ok(reported(&mut s, result))
+        });
+
+        // Show `text` in the notification area (the prompt line).
+        methods.add_method("notify", |_, this, text: String| {
+            notify(&mut this.state.borrow_mut(), text);
             Ok(())
         });
 
-        methods.add_method("quit", |_, this, ()| {
+        // Refused while any document is modified: returns false and names
+        // them in the prompt line. `{ force = true }` discards the changes;
+        // `{ write = true }` saves them first and still refuses if a save
+        // fails. `bv.quit` wraps this in a save / discard / cancel prompt.
+        methods.add_method("quit", |_, this, opts: Option<LuaTable>| {
+            let opts = QuitOptions::from_lua(opts)?;
+            let mut s = this.state.borrow_mut();
+            let all: Vec<DocumentId> = s.documents.keys().copied().collect();
+            if !release(&mut s, &all, opts) {
+                return Ok(false);
+            }
             this.effects.borrow_mut().push(Effect::Quit);
-            Ok(())
+            Ok(true)
+        });
+
+        // Every open document, as `LuaDocument`s.
+        methods.add_method("documents", |_, this, ()| {
+            let s = this.state.borrow();
+            let mut docs: Vec<_> = s.documents.values().map(LuaDocument::of).collect();
+            docs.sort_by_key(|d| d.id);
+            Ok(docs)
         });
     }
 }
 
 // ── Internals ───────────────────────────────────────────────────────────────
 
+/// `{ force = bool, write = bool }` as taken by `quit` and `close_view`.
+#[derive(Clone, Copy, Default)]
+struct QuitOptions {
+    force: bool,
+    write: bool,
+}
+
+impl QuitOptions {
+    fn from_lua(opts: Option<LuaTable>) -> LuaResult<Self> {
+        let Some(t) = opts else {
+            return Ok(Self::default());
+        };
+        Ok(QuitOptions {
+            force: t.get::<Option<bool>>("force")?.unwrap_or(false),
+            write: t.get::<Option<bool>>("write")?.unwrap_or(false),
+        })
+    }
+}
+
+/// Whether `docs` can be let go of: none is modified, or `opts` says to
+/// discard or save them. Otherwise says why in the prompt line.
+fn release(s: &mut EditorState, docs: &[DocumentId], opts: QuitOptions) -> bool {
+    if opts.force {
+        return true;
+    }
+    if opts.write {
+        for &id in docs {
+            if s.documents[&id].is_modified() {
+                let _ = save_document(s, id);
+            }
+        }
+    }
+
+    let mut unsaved: Vec<&str> = docs
+        .iter()
+        .map(|id| &s.documents[id])
+        .filter(|doc| doc.is_modified())
+        .map(|doc| doc.name())
+        .collect();
+    if unsaved.is_empty() {
+        return true;
+    }
+    unsaved.sort_unstable();
+    let what = if opts.write {
+        "could not save"
+    } else {
+        "unsaved changes in"
+    };
+    let message = format!("{what} {}", unsaved.join(", "));
+    notify(s, message);
+    false
+}
+
+/// Write one document and mark its revision saved. Errors name the document.
+fn save_document(s: &mut EditorState, doc_id: DocumentId) -> Result<(), String> {
+    let doc = s.documents.get_mut(&doc_id).unwrap();
+    if doc.path.is_none() {
+        return Err(format!("{}: no file name, use save_as", doc.name()));
+    }
+    fileio::write(doc).map_err(|e| format!("{}: {e}", doc.name()))?;
+    doc.mark_saved();
+    doc.disk = doc.path.as_deref().and_then(DiskStamp::of);
+    Ok(())
+}
+
+/// `result` as Lua's `true` / `false, message`, notifying the message.
+fn reported(s: &mut EditorState, result: Result<(), String>) -> (bool, Option<String>) {
+    match result {
+        Ok(()) => (true, None),
+        Err(message) => {
+            notify(s, message.clone());
+            (false, Some(message))
+        }
+    }
+}
+
+/// Show `message` to the user. There's no separate notification widget; the
+/// prompt line shows it until the next prompt replaces or clears it.
+fn notify(s: &mut EditorState, message: String) {
+    s.prompt = Some(message);
+}
+
+/// Move the focused document to revision `id` and restore its selection.
+fn goto_revision(this: &Ctx, id: usize) {
+    let snap = {
+        let mut s = this.state.borrow_mut();
+        let doc_id = s.focused_doc().id;
+        let doc = s.documents.get_mut(&doc_id).unwrap();
+        match doc.goto_revision(id) {
+            Some((_, snap)) => snap,
+            None => return,
+        }
+    };
+    if let Some(snap) = snap {
+        replace_focused_selection(this, snap);
+    }
+}
+
+/// Resolve an `earlier` / `later` spec against the focused document.
+fn undo_target(this: &Ctx, spec: &LuaValue, forward: bool) -> LuaResult<usize> {
+    let s = this.state.borrow();
+    let history = s.focused_doc().history();
+
+    if let Some(n) = spec.as_integer() {
+        let n = n as isize;
+        return Ok(history.step(if forward { n } else { -n }));
+    }
+
+    let bad = || LuaError::runtime(format!("bad undo time: {spec:?}"));
+    let text = spec.as_string().ok_or_else(bad)?.to_str()?.to_string();
+    let split = text.find(|c: char| !c.is_ascii_digit()).ok_or_else(bad)?;
+    let (n, unit) = text.split_at(split);
+    let n: u64 = n.parse().map_err(|_| bad())?;
+
+    let secs = match unit {
+        "f" if forward => return Ok(history.later_saves(n as usize)),
+        "f" => return Ok(history.earlier_saves(n as usize)),
+        "s" => Some(n),
+        "m" => n.checked_mul(60),
+        "h" => n.checked_mul(60 * 60),
+        "d" => n.checked_mul(24 * 60 * 60),
+        _ => None,
+    }
+    .ok_or_else(bad)?;
+    let d = Duration::from_secs(secs);
+    if forward {
+        Ok(history.later(d))
+    } else {
+        Ok(history.earlier(d))
+    }
+}
+
+/// `result`'s value, or None after putting its error on the prompt line.
+fn register_result<T>(this: &Ctx, result: std::io::Result<T>) -> Option<T> {
+    match result {
+        Ok(value) => Some(value),
+        Err(e) => {
+            notify(&mut this.state.borrow_mut(), e.to_string());
+            None
+        }
+    }
+}
+
+fn register_name(name: Option<String>) -> LuaResult<char> {
+    let Some(name) = name else { return Ok('"') };
+    let mut chars = name.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Ok(c),
+        _ => Err(LuaError::runtime(format!("bad register name: {name:?}"))),
+    }
+}
+
+/// The text of every range in `snap`, as a register. It's linewise when each
+/// range covers whole lines.
+fn selected_text(text: &Rope, snap: &SelectionSnapshot) -> Register {
+    let len = text.len_chars();
+    let spans: Vec<(usize, usize)> = snap
+        .ranges
+        .iter()
+        .map(|&(a, h)| (a.min(h).min(len), a.max(h).min(len)))
+        .collect();
+    let linewise = spans.iter().all(|&(from, to)| {
+        from < to
+            && text.line_to_char(text.char_to_line(from)) == from
+            && (to == len || text.char(to - 1) == '\n')
+    });
+    let entries = spans
+        .iter()
+        .map(|&(from, to)| {
+            let mut entry = text.slice(from..to).to_string();
+            if linewise && !entry.ends_with('\n') {
+                entry.push('\n');
+            }
+            entry
+        })
+        .collect();
+    Register::new(entries, linewise)
+}
+
+/// The changeset putting `reg` at every cursor in `snap`, and the selection
+/// after it: on the last char put, or the first line put when linewise.
+fn put_changes(
+    text: &Rope,
+    snap: &SelectionSnapshot,
+    reg: &Register,
+    before: bool,
+) -> (ChangeSet, SelectionSnapshot) {
+    let len = text.len_chars();
+    let per_cursor = reg.entries.len() == snap.ranges.len();
+
+    // (position, text, cursor offset into the text), one per cursor.
+    let mut puts: Vec<(usize, String, usize)> = Vec::with_capacity(snap.ranges.len());
+    for (i, &(a, h)) in snap.ranges.iter().enumerate() {
+        let entry = if per_cursor {
+            reg.entries[i].clone()
+        } else {
+            reg.joined()
+        };
+        let (from, to) = (a.min(h).min(len), a.max(h).min(len));
+        if reg.linewise {
+            let at = if before || from == to { from } else { to - 1 };
+            let line = text.char_to_line(at);
+            let pos = if before {
+                text.line_to_char(line)
+            } else {
+                text.line_to_char((line + 1).min(text.len_lines()))
+            };
+            // The last line has no newline to put after: add one first.
+            if pos == len && len > 0 && text.char(len - 1) != '\n' {
+                let entry = format!("\n{}", entry.strip_suffix('\n').unwrap_or(&entry));
+                puts.push((pos, entry, 1));
+            } else {
+                puts.push((pos, entry, 0));
+            }
+        } else {
+            let pos = if before {
+                from
+            } else if from < to {
+                to
+            } else if h < len && text.char(h) != '\n' {
+                h + 1
+            } else {
+                h
+            };
+            let offset = entry.chars().count().saturating_sub(1);
+            puts.push((pos, entry, offset));
+        }
+    }
+
+    let mut order: Vec<usize> = (0..puts.len()).collect();
+    order.sort_by_key(|&i| puts[i].0);
+
+    let mut b = ChangeBuilder::new(len);
+    let mut cursors = vec![0; puts.len()];
+    let mut shift = 0;
+    let mut last: Option<(usize, usize)> = None;
+    for i in order {
+        let (pos, ref entry, offset) = puts[i];
+        // Cursors at the same spot put once and land together.
+        if let Some((at, cursor)) = last
+            && at == pos
+        {
+            cursors[i] = cursor;
+            continue;
+        }
+        b.advance_to(pos);
+        b.insert(entry);
+        cursors[i] = pos + shift + offset;
+        shift += entry.chars().count();
+        last = Some((pos, cursors[i]));
+    }
+
+    let ranges = cursors.into_iter().map(|c| (c, c)).collect();
+    (
+        b.finish(),
+        SelectionSnapshot {
+            ranges,
+            primary: snap.primary,
+        },
+    )
+}
+
+fn focused_doc_mut(s: &mut EditorState) -> &mut Document {
+    let doc_id = s.focused_doc().id;
+    s.documents.get_mut(&doc_id).unwrap()
+}
+
+fn encoding_for(label: &str) -> LuaResult<&'static Encoding> {
+    Encoding::for_label(label.as_bytes())
+        .ok_or_else(|| LuaError::runtime(format!("unknown encoding: {label}")))
+}
+
+/// A single-char mark name, or None.
+fn mark_name(name: &str) -> Option<char> {
+    let mut chars = name.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if c.is_ascii_alphabetic() => Some(c),
+        _ => None,
+    }
+}
+
+/// The focused document and its primary cursor.
+fn focused_cursor(s: &EditorState) -> (DocumentId, usize) {
+    let view = s.focused_view();
+    let doc = &s.documents[&view.doc_id];
+    let snap = view.selection.snapshot(&doc.anchors);
+    (view.doc_id, snap.ranges[snap.primary].1)
+}
+
+fn push_jump(this: &Ctx) {
+    let mut s = this.state.borrow_mut();
+    let (doc, offset) = focused_cursor(&s);
+    this.marks
+        .borrow_mut()
+        .push_jump(doc, offset, &mut s.documents);
+}
+
+/// Put a single cursor at `offset` in `doc`, showing `doc` in the focused
+/// view first if it isn't there.
+fn jump(this: &Ctx, (doc, offset): (DocumentId, usize)) {
+    {
+        let mut s = this.state.borrow_mut();
+        if s.focused_view().doc_id != doc {
+            s.switch_to_document(doc);
+        }
+    }
+    replace_focused_selection(
+        this,
+        SelectionSnapshot {
+            ranges: vec![(offset, offset)],
+            primary: 0,
+        },
+    );
+}
+
 /// Replace the focused view's selection by allocating fresh anchors from `snap`.
 /// Drops the previous selection's anchors so the [`AnchorTable`] doesn't leak.
 fn replace_focused_selection(this: &Ctx, snap: SelectionSnapshot) {
@@ -263,3 +1100,76 @@ fn replace_focused_selection(this: &Ctx, snap: SelectionSnapshot) {
     old.drop(&mut doc.anchors);
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snap(ranges: &[(usize, usize)]) -> SelectionSnapshot {
+        SelectionSnapshot {
+            ranges: ranges.to_vec(),
+            primary: 0,
+        }
+    }
+
+    fn chars(entries: &[&str]) -> Register {
+        Register::new(entries.iter().map(|e| e.to_string()).collect(), false)
+    }
+
+    /// `text` after putting `reg` at the cursors `heads`, and the cursors
+    /// after it.
+    fn put(text: &str, heads: &[usize], reg: &Register, before: bool) -> (String, Vec<usize>) {
+        let ranges: Vec<_> = heads.iter().map(|&h| (h, h)).collect();
+        let mut doc = Document::from_rope(Rope::from_str(text), None);
+        let (cs, after) = put_changes(&doc.text, &snap(&ranges), reg, before);
+        doc.apply_mutations(&cs);
+        let cursors = after.ranges.iter().map(|&(_, h)| h).collect();
+        (doc.text.to_string(), cursors)
+    }
+
+    #[test]
+    fn selected_text_is_linewise_over_whole_lines() {
+        let text = Rope::from_str("one\ntwo\nthree");
+        let reg = selected_text(&text, &snap(&[(0, 4), (8, 13)]));
+        assert!(reg.linewise);
+        assert_eq!(reg.entries, ["one\n", "three\n"]);
+
+        let reg = selected_text(&text, &snap(&[(6, 1)]));
+        assert!(!reg.linewise);
+        assert_eq!(reg.entries, ["ne\ntw"]);
+    }
+
+    #[test]
+    fn put_chars_after_and_before() {
+        let ab = chars(&["ab"]);
+        assert_eq!(put("xyz", &[0], &ab, false), ("xabyz".into(), vec![2]));
+        assert_eq!(put("xyz", &[1], &ab, true), ("xabyz".into(), vec![2]));
+        // On a newline there's no char to put after.
+        assert_eq!(put("x\n", &[1], &ab, false), ("xab\n".into(), vec![2]));
+    }
+
+    #[test]
+    fn put_lines_below_the_last_line() {
+        let line = Register::new(vec!["new\n".into()], true);
+        assert_eq!(
+            put("a\nb", &[2], &line, false),
+            ("a\nb\nnew".into(), vec![4])
+        );
+        assert_eq!(
+            put("a\nb", &[2], &line, true),
+            ("a\nnew\nb".into(), vec![2])
+        );
+    }
+
+    #[test]
+    fn put_one_entry_per_cursor() {
+        let two = chars(&["1", "2"]);
+        assert_eq!(
+            put("ab\ncd", &[3, 0], &two, true),
+            ("2ab\n1cd".into(), vec![4, 0])
+        );
+
+        // Cursors on one spot put once.
+        let x = chars(&["x"]);
+        assert_eq!(put("ab", &[1, 1], &x, true), ("axb".into(), vec![1, 1]));
+    }
+}
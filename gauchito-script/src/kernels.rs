@@ -9,12 +9,17 @@
 //! - Motion:     `bv.k.*(buf, head)         -> head`
 //! - Selection:  `bv.k.*(buf, anchor, head) -> {anchor, head}`
 //! - Char find:  `bv.k.*(buf, head, ch)     -> head`
+//! - Search:     `bv.k.*(buf, head, pattern) -> head`
+//! - Structural: `bv.k.*(buf, anchor, head) -> {anchor, head}` (syntax tree)
 //! - Mutation:   `bv.*(buf, sel)            -> changeset`
+//! - Rewrite:    `bv.replace_all(buf, text) -> changeset`
 
 use mlua::prelude::*;
 
-use gauchito_core::{edits, movement};
+use gauchito_core::changeset::ChangeSet;
+use gauchito_core::{edits, movement, structure};
 use gauchito_core::history::SelectionSnapshot;
+use gauchito_core::options::DocumentOptions;
 
 use crate::userdata::{LuaBuffer, LuaChangeSet, LuaSelection};
 
@@ -25,6 +30,8 @@ pub fn register(lua: &Lua) -> LuaResult<()> {
     register_motion_kernels(lua, &k)?;
     register_selection_kernels(lua, &k)?;
     register_char_kernels(lua, &k)?;
+    register_search_kernels(lua, &k)?;
+    register_structure_kernels(lua, &k)?;
 
     bv.set("k", k)?;
 
@@ -96,6 +103,28 @@ fn register_selection_kernels(lua: &Lua, k: &LuaTable) -> LuaResult<()> {
     kernel!(head_to_end);
     kernel!(select_whole_line);
 
+    // Text objects (iw, a(, i", …).
+    kernel!(select_inner_word);
+    kernel!(select_around_word);
+    kernel!(select_inner_word_big);
+    kernel!(select_around_word_big);
+    kernel!(select_inner_paragraph);
+    kernel!(select_around_paragraph);
+    kernel!(select_inner_paren);
+    kernel!(select_around_paren);
+    kernel!(select_inner_bracket);
+    kernel!(select_around_bracket);
+    kernel!(select_inner_brace);
+    kernel!(select_around_brace);
+    kernel!(select_inner_angle);
+    kernel!(select_around_angle);
+    kernel!(select_inner_double_quote);
+    kernel!(select_around_double_quote);
+    kernel!(select_inner_single_quote);
+    kernel!(select_around_single_quote);
+    kernel!(select_inner_backtick);
+    kernel!(select_around_backtick);
+
     Ok(())
 }
 
@@ -122,6 +151,63 @@ fn register_char_kernels(lua: &Lua, k: &LuaTable) -> LuaResult<()> {
     Ok(())
 }
 
+// ── Search: (buf, head, pattern) -> head ───────────────────────────────────
+
+fn register_search_kernels(lua: &Lua, k: &LuaTable) -> LuaResult<()> {
+    macro_rules! kernel {
+        ($name:ident) => {
+            k.set(
+                stringify!($name),
+                lua.create_function(|_, (buf, head, pattern): (LuaBuffer, usize, String)| {
+                    Ok(movement::$name(&buf.0.slice(..), head, &pattern))
+                })?,
+            )?;
+        };
+    }
+
+    kernel!(search_forward);
+    kernel!(search_backward);
+
+    Ok(())
+}
+
+// ── Structural: (buf, anchor, head) -> (anchor, head) ──────────────────────
+// Same shape as the selection kernels; a buffer without a syntax tree leaves
+// the selection unchanged.
+
+fn register_structure_kernels(lua: &Lua, k: &LuaTable) -> LuaResult<()> {
+    macro_rules! kernel {
+        ($name:ident) => {
+            k.set(
+                stringify!($name),
+                lua.create_function(|lua, (buf, anchor, head): (LuaBuffer, usize, usize)| {
+                    let (a, h) = match &buf.1 {
+                        Some(tree) => structure::$name(tree, &buf.0.slice(..), anchor, head),
+                        None => (anchor, head),
+                    };
+                    let t = lua.create_table()?;
+                    t.set("anchor", a)?;
+                    t.set("head", h)?;
+                    Ok(t)
+                })?,
+            )?;
+        };
+    }
+
+    kernel!(expand_selection);
+    kernel!(shrink_selection);
+    kernel!(select_next_sibling);
+    kernel!(select_prev_sibling);
+    kernel!(select_next_function);
+    kernel!(select_prev_function);
+    kernel!(select_next_class);
+    kernel!(select_prev_class);
+    kernel!(select_next_argument);
+    kernel!(select_prev_argument);
+
+    Ok(())
+}
+
 // ── Mutations: (buf, sel) -> changeset ─────────────────────────────────────
 
 fn register_mutations(lua: &Lua, bv: &LuaTable) -> LuaResult<()> {
@@ -189,6 +275,82 @@ fn register_mutations(lua: &Lua, bv: &LuaTable) -> LuaResult<()> {
         })?,
     )?;
 
+    // `O`: a line above each head's, indented like it.
+    bv.set(
+        "insert_line_above_indent",
+        lua.create_function(|_, (buf, sel): (LuaBuffer, LuaSelection)| {
+            Ok(LuaChangeSet(edits::insert_line_above_indent(
+                &buf.0.slice(..),
+                &heads(&sel.0),
+            )))
+        })?,
+    )?;
+
+    // Indent-aware variants; `unit` is one indent level (`ctx:indent_unit()`)
+    // and `tab_width` the columns of a tab (`ctx:tab_width()`, default 4).
+    bv.set(
+        "insert_newline_indent",
+        lua.create_function(|_, (buf, sel, unit): (LuaBuffer, LuaSelection, String)| {
+            Ok(LuaChangeSet(edits::insert_newline_indent(
+                &buf.0.slice(..),
+                &heads(&sel.0),
+                buf.1.as_ref().map(|t| t.language),
+                &unit,
+            )))
+        })?,
+    )?;
+
+    bv.set(
+        "insert_char_indent",
+        lua.create_function(
+            |_,
+             (buf, sel, ch, unit, tab_width): (
+                LuaBuffer,
+                LuaSelection,
+                String,
+                String,
+                Option<usize>,
+            )| {
+                let c = ch
+                    .chars()
+                    .next()
+                    .ok_or_else(|| LuaError::runtime("empty char"))?;
+                Ok(LuaChangeSet(edits::insert_char_indent(
+                    &buf.0.slice(..),
+                    &heads(&sel.0),
+                    c,
+                    &unit,
+                    tab_width.unwrap_or(DocumentOptions::default().tab_width),
+                )))
+            },
+        )?,
+    )?;
+
+    bv.set(
+        "indent_lines",
+        lua.create_function(|_, (buf, sel, unit): (LuaBuffer, LuaSelection, String)| {
+            Ok(LuaChangeSet(edits::indent_lines(
+                &buf.0.slice(..),
+                &ranges(&sel.0),
+                &unit,
+            )))
+        })?,
+    )?;
+
+    bv.set(
+        "dedent_lines",
+        lua.create_function(
+            |_, (buf, sel, unit, tab_width): (LuaBuffer, LuaSelection, String, Option<usize>)| {
+                Ok(LuaChangeSet(edits::dedent_lines(
+                    &buf.0.slice(..),
+                    &ranges(&sel.0),
+                    &unit,
+                    tab_width.unwrap_or(DocumentOptions::default().tab_width),
+                )))
+            },
+        )?,
+    )?;
+
     bv.set(
         "insert_tab",
         lua.create_function(|_, (buf, sel): (LuaBuffer, LuaSelection)| {
@@ -223,5 +385,14 @@ fn register_mutations(lua: &Lua, bv: &LuaTable) -> LuaResult<()> {
         )?,
     )?;
 
+    // Turn the whole buffer into `text`, splicing only what differs so
+    // selections and other anchors in unchanged text stay put.
+    bv.set(
+        "replace_all",
+        lua.create_function(|_, (buf, text): (LuaBuffer, String)| {
+            Ok(LuaChangeSet(ChangeSet::from_diff(&buf.0.slice(..), &text)))
+        })?,
+    )?;
+
     Ok(())
 }
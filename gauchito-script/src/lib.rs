@@ -9,7 +9,14 @@
 //! key calls `bv.read_key()` (= `coroutine.yield()` under the hood); the
 //! bridge returns to its caller, then on the next dispatch resumes the same
 //! coroutine with `(ctx, key, ch)`. Sequences end when the coroutine returns.
-//! Errors and `esc` cancel cleanly.
+//! Errors and `esc` cancel cleanly — unless the coroutine yielded `"input"`
+//! (`bv.read_input()`), in which case `esc` is delivered like any other key so
+//! prompts can restore state before returning.
+//!
+//! Keys typed while a macro records are kept in a register, and the keys of
+//! the last repeatable change are kept for `.` (see `gauchito_core::macros`).
+//! A handler replaying either only queues it; once its key is done the
+//! dispatcher feeds the keys back through itself, as one undo step.
 //!
 //! Per-key dispatch:
 //! - direct handler under `modes[mode].keys[name]` → call `f(ctx)`
@@ -19,6 +26,7 @@
 use std::cell::RefCell;
 use std::path::Path;
 use std::rc::Rc;
+use std::time::Duration;
 
 use mlua::prelude::*;
 
@@ -27,7 +35,15 @@ mod kernels;
 mod userdata;
 
 pub use ctx::SharedState;
-use ctx::{Ctx, SharedEffects};
+use ctx::{Ctx, SharedEffects, SharedMacros, SharedMarks, SharedRegisters};
+use gauchito_core::changeset::ChangeSet;
+use gauchito_core::clipboard::{Commands, Provider};
+use gauchito_core::document::DocumentId;
+use gauchito_core::history::Coalesce;
+use gauchito_core::macros::{Key, Macros};
+use gauchito_core::marks::Marks;
+use gauchito_core::registers::Registers;
+use gauchito_core::theme::{Rgb, Style, Theme};
 use gauchito_ui::{Component, ComponentRegistry, EditorState, Effect};
 
 const PRELUDE: &str = include_str!("../lua/prelude.lua");
@@ -35,19 +51,38 @@ const PRELUDE: &str = include_str!("../lua/prelude.lua");
 pub struct ScriptRuntime {
     lua: Lua,
     active_thread: Option<LuaRegistryKey>,
+    /// The active coroutine asked for raw input: don't intercept `esc`.
+    raw_input: bool,
+    /// Yank / put registers, shared by every document and view.
+    registers: SharedRegisters,
+    macros: SharedMacros,
+    /// Marks and the jump list, which span documents like registers do.
+    marks: SharedMarks,
 }
 
 impl ScriptRuntime {
     pub fn new() -> Result<Self, ScriptError> {
         let lua = Lua::new();
+        let registers: SharedRegisters = Rc::new(RefCell::new(Registers::new()));
+        registers.borrow_mut().set_clipboard(Provider::detect());
+        if let Err(e) = registers
+            .borrow_mut()
+            .read_named(&gauchito_paths::registers_file())
+        {
+            tracing::warn!("read registers: {e}");
+        }
 
         kernels::register(&lua)?;
-        register_ui_constructors(&lua)?;
+        register_ui_constructors(&lua, &registers)?;
         lua.load(PRELUDE).set_name("prelude").exec()?;
 
         Ok(ScriptRuntime {
             lua,
             active_thread: None,
+            raw_input: false,
+            registers,
+            macros: Rc::new(RefCell::new(Macros::new())),
+            marks: Rc::new(RefCell::new(Marks::new())),
         })
     }
 
@@ -111,6 +146,74 @@ impl ScriptRuntime {
         registry
     }
 
+    /// The theme declared with `gauchito.theme{ capture = { fg = "#rrggbb", … } }`.
+    /// Unknown colors are logged and ignored. The prelude declares a default
+    /// one, so init.lua only calls it to replace that.
+    pub fn theme(&self) -> Theme {
+        let mut theme = Theme::new();
+
+        let table: LuaTable = match self.lua.globals().raw_get("__theme") {
+            Ok(t) => t,
+            Err(_) => return theme,
+        };
+
+        for (name, spec) in table.pairs::<String, LuaTable>().flatten() {
+            let color = |field: &str| {
+                let value: Option<String> = spec.get(field).ok()?;
+                let value = value?;
+                let rgb = Rgb::parse(&value);
+                if rgb.is_none() {
+                    tracing::warn!("theme {name}.{field}: bad color {value}");
+                }
+                rgb
+            };
+            let style = Style {
+                fg: color("fg"),
+                bg: color("bg"),
+                bold: spec.get("bold").unwrap_or(false),
+                italic: spec.get("italic").unwrap_or(false),
+                underline: spec.get("underline").unwrap_or(false),
+            };
+            theme.set(name, style);
+        }
+
+        theme
+    }
+
+    /// Undo grouping declared with `gauchito.undo{ typing = true, deleting
+    /// = true, bursts = false, idle = 1000 }` (`idle` in milliseconds).
+    /// Missing fields keep their defaults; no call means the defaults.
+    pub fn undo_rules(&self) -> Coalesce {
+        let mut rules = Coalesce::default();
+
+        let table: LuaTable = match self.lua.globals().raw_get("__undo") {
+            Ok(t) => t,
+            Err(_) => return rules,
+        };
+
+        if let Ok(Some(typing)) = table.get("typing") {
+            rules.typing = typing;
+        }
+        if let Ok(Some(deleting)) = table.get("deleting") {
+            rules.deleting = deleting;
+        }
+        if let Ok(Some(bursts)) = table.get("bursts") {
+            rules.bursts = bursts;
+        }
+        if let Ok(Some(idle)) = table.get::<Option<u64>>("idle") {
+            rules.idle = Duration::from_millis(idle);
+        }
+        rules
+    }
+
+    /// Keep the named registers, and so the macros, for the next session.
+    pub fn save_registers(&self) {
+        let path = gauchito_paths::registers_file();
+        if let Err(e) = self.registers.borrow().write_named(&path) {
+            tracing::warn!("write registers: {e}");
+        }
+    }
+
     pub fn run_initial_mode_callback(&mut self, _state: &SharedState) {
         // Reserved for an `on_init` hook; not wired yet.
     }
@@ -125,10 +228,7 @@ impl ScriptRuntime {
     ) -> Vec<Effect> {
         let effects: SharedEffects = Rc::new(RefCell::new(Vec::new()));
 
-        if let Err(e) = self.run(key_name, ch, state, &effects) {
-            tracing::warn!("lua dispatch {key_name}: {e}");
-            self.cancel();
-        }
+        self.feed(key_name, ch, state, &effects);
 
         // The Ctx userdata still holds a clone of the Rc, so try_unwrap would
         // fail. Drain in place.
@@ -136,6 +236,76 @@ impl ScriptRuntime {
         std::mem::take(&mut *bucket)
     }
 
+    /// Dispatch one key, then any macro its handler queued. Returns false
+    /// if either failed, which stops a replay feeding this key.
+    fn feed(
+        &mut self,
+        key_name: &str,
+        ch: Option<char>,
+        state: &SharedState,
+        effects: &SharedEffects,
+    ) -> bool {
+        let starts_command = self.active_thread.is_none();
+        self.macros
+            .borrow_mut()
+            .observe(Key::new(key_name, ch), starts_command);
+
+        let result = self.run(key_name, ch, state, effects);
+        if let Err(e) = &result {
+            tracing::warn!("lua dispatch {key_name}: {e}");
+            self.cancel();
+        }
+        if self.active_thread.is_none() {
+            self.macros.borrow_mut().end_command();
+        }
+        if result.is_err() {
+            return false;
+        }
+
+        let queued = self.macros.borrow_mut().take_queued();
+        match queued {
+            Some((keys, count)) => self.replay(&keys, count, state, effects),
+            None => true,
+        }
+    }
+
+    /// Feed `keys` `count` times, stopping at the first failure. The
+    /// outermost replay is one transaction on the document it started in;
+    /// the macro's own transactions are folded into it.
+    fn replay(
+        &mut self,
+        keys: &[Key],
+        count: usize,
+        state: &SharedState,
+        effects: &SharedEffects,
+    ) -> bool {
+        let outermost = !self.macros.borrow().replaying();
+        let target = outermost.then(|| {
+            let mut s = state.borrow_mut();
+            let doc_id = s.focused_doc().id;
+            let view_id = s.focused;
+            s.documents.get_mut(&doc_id).unwrap().seal_history();
+            s.transaction_start(doc_id, view_id);
+            (doc_id, view_id)
+        });
+
+        self.macros.borrow_mut().enter_replay();
+        let ok = (0..count).all(|_| {
+            keys.iter()
+                .all(|key| self.feed(&key.name, key.ch, state, effects))
+        });
+        self.macros.borrow_mut().leave_replay();
+
+        if let Some((doc_id, view_id)) = target {
+            let mut s = state.borrow_mut();
+            if s.documents.contains_key(&doc_id) && s.views.contains_key(&view_id) {
+                s.transaction_commit(doc_id, view_id);
+                s.documents.get_mut(&doc_id).unwrap().seal_history();
+            }
+        }
+        ok
+    }
+
     fn run(
         &mut self,
         key_name: &str,
@@ -144,12 +314,18 @@ impl ScriptRuntime {
         effects: &SharedEffects,
     ) -> LuaResult<()> {
         // Esc cancels any in-flight sequence with no further dispatch.
-        if self.active_thread.is_some() && key_name == "esc" {
+        if self.active_thread.is_some() && key_name == "esc" && !self.raw_input {
             self.cancel();
             return Ok(());
         }
 
-        let ctx = Ctx::new(state.clone(), effects.clone());
+        let ctx = Ctx::new(
+            state.clone(),
+            effects.clone(),
+            self.registers.clone(),
+            self.macros.clone(),
+            self.marks.clone(),
+        );
         let key_lua = self.lua.create_string(key_name)?;
         let ch_lua: LuaValue = match ch {
             Some(c) => LuaValue::String(self.lua.create_string(&c.to_string())?),
@@ -159,7 +335,8 @@ impl ScriptRuntime {
         // Resume an in-flight coroutine.
         if let Some(thread_key) = self.active_thread.as_ref() {
             let thread: LuaThread = self.lua.registry_value(thread_key)?;
-            thread.resume::<LuaMultiValue>((ctx, key_lua, ch_lua))?;
+            let yielded = thread.resume::<LuaMultiValue>((ctx, key_lua, ch_lua))?;
+            self.raw_input = wants_raw_input(&yielded);
             if !matches!(thread.status(), LuaThreadStatus::Resumable) {
                 self.clear_thread();
             }
@@ -177,14 +354,11 @@ impl ScriptRuntime {
         // Start a coroutine. Direct handlers receive `(ctx)`; fallback handlers
         // also receive `(key_name, ch)` so they can decide what to do.
         let thread = self.lua.create_thread(handler)?;
-        match kind {
-            HandlerKind::Direct => {
-                thread.resume::<LuaMultiValue>(ctx)?;
-            }
-            HandlerKind::Fallback => {
-                thread.resume::<LuaMultiValue>((ctx, key_lua, ch_lua))?;
-            }
-        }
+        let yielded = match kind {
+            HandlerKind::Direct => thread.resume::<LuaMultiValue>(ctx)?,
+            HandlerKind::Fallback => thread.resume::<LuaMultiValue>((ctx, key_lua, ch_lua))?,
+        };
+        self.raw_input = wants_raw_input(&yielded);
         if matches!(thread.status(), LuaThreadStatus::Resumable) {
             let key = self.lua.create_registry_value(thread)?;
             self.active_thread = Some(key);
@@ -197,6 +371,7 @@ impl ScriptRuntime {
     }
 
     fn clear_thread(&mut self) {
+        self.raw_input = false;
         if let Some(key) = self.active_thread.take() {
             self.lua.remove_registry_value(key).ok();
         }
@@ -234,6 +409,11 @@ impl ScriptRuntime {
     }
 }
 
+/// True if a coroutine yielded the `"input"` marker (see `bv.read_input`).
+fn wants_raw_input(yielded: &LuaMultiValue) -> bool {
+    matches!(yielded.front(), Some(LuaValue::String(s)) if s.to_string_lossy() == "input")
+}
+
 #[derive(Clone, Copy)]
 enum HandlerKind {
     /// Called as `f(ctx)`.
@@ -244,7 +424,7 @@ enum HandlerKind {
 
 // ── UI constructors ─────────────────────────────────────────────────────────
 
-fn register_ui_constructors(lua: &Lua) -> LuaResult<()> {
+fn register_ui_constructors(lua: &Lua, registers: &SharedRegisters) -> LuaResult<()> {
     let ui = lua.create_table()?;
 
     ui.set(
@@ -261,17 +441,75 @@ fn register_ui_constructors(lua: &Lua) -> LuaResult<()> {
 
     let gauchito = lua.create_table()?;
     gauchito.set("ui", ui)?;
+
+    // `gauchito.theme{ keyword = { fg = "#c678dd", bold = true }, … }`.
+    // Later calls replace earlier ones; read back by `ScriptRuntime::theme`.
+    gauchito.set(
+        "theme",
+        lua.create_function(|lua, styles: LuaTable| lua.globals().raw_set("__theme", styles))?,
+    )?;
+
+    // `gauchito.undo{ typing = true, idle = 500, … }`: how edits group into
+    // undo steps. Read back by `ScriptRuntime::undo_rules`.
+    gauchito.set(
+        "undo",
+        lua.create_function(|lua, rules: LuaTable| lua.globals().raw_set("__undo", rules))?,
+    )?;
+
+    // `gauchito.clipboard{ provider = "auto" | "osc52" | "none" }`, or
+    // `gauchito.clipboard{ copy = { "wl-copy" }, paste = { "wl-paste", "-n" },
+    // primary_copy = …, primary_paste = … }` for commands of your own.
+    // Takes effect at once; without a call the provider is "auto".
+    let registers = registers.clone();
+    gauchito.set(
+        "clipboard",
+        lua.create_function(move |_, spec: LuaTable| {
+            let provider = clipboard_provider(&spec)?;
+            registers.borrow_mut().set_clipboard(provider);
+            Ok(())
+        })?,
+    )?;
     lua.globals().set("gauchito", gauchito)?;
 
     Ok(())
 }
 
+fn clipboard_provider(spec: &LuaTable) -> LuaResult<Provider> {
+    let copy: Option<Vec<String>> = spec.get("copy")?;
+    let paste: Option<Vec<String>> = spec.get("paste")?;
+    if let (Some(copy), Some(paste)) = (copy, paste) {
+        return Ok(Provider::Command(Commands {
+            copy,
+            paste,
+            primary_copy: spec.get("primary_copy")?,
+            primary_paste: spec.get("primary_paste")?,
+        }));
+    }
+    match spec.get::<Option<String>>("provider")?.as_deref() {
+        None | Some("auto") => Ok(Provider::detect()),
+        Some("osc52") => Ok(Provider::Osc52),
+        Some("none") => Ok(Provider::None),
+        Some(other) => Err(LuaError::runtime(format!(
+            "clipboard: unknown provider {other:?}; give copy and paste commands instead"
+        ))),
+    }
+}
+
 // ── Public helpers ──────────────────────────────────────────────────────────
 
 pub fn shared(state: EditorState) -> SharedState {
     Rc::new(RefCell::new(state))
 }
 
+/// [`EditorState::apply_edit`], with the document's syntax tree told about
+/// each atom first so it's reparsed incrementally rather than from scratch.
+pub fn apply_edit(state: &mut EditorState, doc_id: DocumentId, changes: ChangeSet) {
+    let doc = state.documents.get_mut(&doc_id).unwrap();
+    let before = doc.will_edit(&changes);
+    state.apply_edit(doc_id, changes);
+    state.documents.get_mut(&doc_id).unwrap().edited(before);
+}
+
 #[derive(Debug)]
 pub struct ScriptError(pub String);
 
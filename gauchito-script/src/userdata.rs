@@ -1,11 +1,16 @@
 //! Plain-data userdata types passed between Lua and Rust.
 //!
-//! - [`LuaBuffer`] — a clone of a document's [`Rope`]. Cheap (Arc-shared).
+//! - [`LuaBuffer`] — a clone of a document's [`Rope`] and, when the document
+//!   has a grammar, its syntax tree. Cheap (Arc-shared / ref-counted).
+//! - [`LuaNode`] — one node of that tree, addressed by its child-index path
+//!   from the root so it can outlive the borrow that produced it.
 //! - [`LuaSelection`] — a [`SelectionSnapshot`] (resolved offsets). Lua never
 //!   sees [`AnchorId`](gauchito_core::anchor::AnchorId)s; the bridge rehydrates
 //!   on `ctx:set_selection`.
 //! - [`LuaChangeSet`] — opaque [`ChangeSet`] handle. Lua passes it back via
 //!   `ctx:edit`.
+//! - [`LuaDocument`] — a document's file-level state (name, path, modified,
+//!   read-only, encoding) at the moment `ctx:document()` was called.
 //!
 //! These are deliberately dumb wrappers. All algebra lives in `prelude.lua`
 //! and user presets; all mutation goes through `Ctx` methods.
@@ -14,13 +19,16 @@ use mlua::prelude::*;
 use ropey::Rope;
 
 use gauchito_core::changeset::ChangeSet;
+use gauchito_core::document::Document;
 use gauchito_core::history::SelectionSnapshot;
+use gauchito_core::syntax::{Node, SyntaxTree};
 
 // ── LuaBuffer ──────────────────────────────────────────────────────────────
 
 /// Read-only view of a rope. Cloned cheaply (B-tree of `Arc` nodes).
+/// Carries the document's syntax tree, if any, for structural kernels.
 #[derive(Clone)]
-pub struct LuaBuffer(pub Rope);
+pub struct LuaBuffer(pub Rope, pub Option<SyntaxTree>);
 
 impl FromLua for LuaBuffer {
     fn from_lua(value: LuaValue, _lua: &Lua) -> LuaResult<Self> {
@@ -55,6 +63,127 @@ impl LuaUserData for LuaBuffer {
             let pos = if len == 0 { 0 } else { pos.min(len - 1) };
             Ok(this.0.char_to_line(pos))
         });
+
+        methods.add_method("language", |_, this, ()| {
+            Ok(this.1.as_ref().map(|t| t.language.name()))
+        });
+
+        methods.add_method("syntax", |_, this, ()| {
+            Ok(this
+                .1
+                .clone()
+                .map(|tree| LuaNode::root(this.0.clone(), tree)))
+        });
+    }
+}
+
+// ── LuaNode ────────────────────────────────────────────────────────────────
+
+/// A syntax node. Tree-sitter nodes borrow their tree, so this keeps the
+/// (ref-counted) tree and re-walks `path` — child indices from the root —
+/// on every call. Ranges are char offsets into `text`.
+#[derive(Clone)]
+pub struct LuaNode {
+    text: Rope,
+    tree: SyntaxTree,
+    path: Vec<usize>,
+}
+
+impl LuaNode {
+    pub fn root(text: Rope, tree: SyntaxTree) -> Self {
+        LuaNode {
+            text,
+            tree,
+            path: Vec::new(),
+        }
+    }
+
+    fn node(&self) -> Node<'_> {
+        let mut node = self.tree.root();
+        for &i in &self.path {
+            node = node.child(i).expect("LuaNode path is valid for its tree");
+        }
+        node
+    }
+
+    /// The node's char range, clamped to the text so a tree parsed from
+    /// older text can't index past its end.
+    fn char_range(&self) -> (usize, usize) {
+        let node = self.node();
+        let len = self.text.len_bytes();
+        (
+            self.text.byte_to_char(node.start_byte().min(len)),
+            self.text.byte_to_char(node.end_byte().min(len)),
+        )
+    }
+
+    fn with_path(&self, path: Vec<usize>) -> Self {
+        LuaNode {
+            text: self.text.clone(),
+            tree: self.tree.clone(),
+            path,
+        }
+    }
+
+    fn child_at(&self, i: usize) -> Self {
+        let mut path = self.path.clone();
+        path.push(i);
+        self.with_path(path)
+    }
+
+    /// The sibling `offset` positions away under the same parent.
+    fn sibling_at(&self, offset: isize) -> Option<Self> {
+        let (&last, parent) = self.path.split_last()?;
+        let i = last.checked_add_signed(offset)?;
+        let parent = self.with_path(parent.to_vec());
+        (i < parent.node().child_count()).then(|| parent.child_at(i))
+    }
+}
+
+impl LuaUserData for LuaNode {
+    fn add_methods<M: LuaUserDataMethods<Self>>(methods: &mut M) {
+        methods.add_method("kind", |_, this, ()| Ok(this.node().kind().to_string()));
+
+        methods.add_method("is_named", |_, this, ()| Ok(this.node().is_named()));
+
+        methods.add_method("has_error", |_, this, ()| Ok(this.node().has_error()));
+
+        // (from, to) as a half-open char range.
+        methods.add_method("range", |_, this, ()| Ok(this.char_range()));
+
+        methods.add_method("text", |_, this, ()| {
+            let (from, to) = this.char_range();
+            Ok(this.text.slice(from..to).to_string())
+        });
+
+        methods.add_method("child_count", |_, this, ()| Ok(this.node().child_count()));
+
+        methods.add_method("child", |_, this, i: usize| {
+            Ok((i < this.node().child_count()).then(|| this.child_at(i)))
+        });
+
+        methods.add_method("named_child", |_, this, i: usize| {
+            let node = this.node();
+            let mut cursor = node.walk();
+            let index = node
+                .children(&mut cursor)
+                .enumerate()
+                .filter(|(_, c)| c.is_named())
+                .nth(i)
+                .map(|(index, _)| index);
+            Ok(index.map(|index| this.child_at(index)))
+        });
+
+        methods.add_method("parent", |_, this, ()| {
+            Ok(this
+                .path
+                .split_last()
+                .map(|(_, parent)| this.with_path(parent.to_vec())))
+        });
+
+        methods.add_method("next_sibling", |_, this, ()| Ok(this.sibling_at(1)));
+
+        methods.add_method("prev_sibling", |_, this, ()| Ok(this.sibling_at(-1)));
     }
 }
 
@@ -117,3 +246,48 @@ impl FromLua for LuaChangeSet {
 }
 
 impl LuaUserData for LuaChangeSet {}
+
+// ── LuaDocument ────────────────────────────────────────────────────────────
+
+/// Snapshot of a document's file-level state. Query again after edits or
+/// saves; it doesn't track the live document.
+#[derive(Clone)]
+pub struct LuaDocument {
+    pub id: usize,
+    pub name: String,
+    pub path: Option<String>,
+    pub modified: bool,
+    pub read_only: bool,
+    pub encoding: &'static str,
+}
+
+impl LuaDocument {
+    pub fn of(doc: &Document) -> Self {
+        LuaDocument {
+            id: doc.id.0,
+            name: doc.name().to_string(),
+            path: doc.path.as_ref().map(|p| p.display().to_string()),
+            modified: doc.is_modified(),
+            read_only: doc.read_only,
+            encoding: doc.options.encoding.name(),
+        }
+    }
+}
+
+impl LuaUserData for LuaDocument {
+    fn add_methods<M: LuaUserDataMethods<Self>>(methods: &mut M) {
+        methods.add_method("id", |_, this, ()| Ok(this.id));
+
+        methods.add_method("name", |_, this, ()| Ok(this.name.clone()));
+
+        // Absolute path, or nil for a scratch buffer.
+        methods.add_method("path", |_, this, ()| Ok(this.path.clone()));
+
+        // Differs from the last save; undoing back to it makes it clean.
+        methods.add_method("is_modified", |_, this, ()| Ok(this.modified));
+
+        methods.add_method("is_read_only", |_, this, ()| Ok(this.read_only));
+
+        methods.add_method("encoding", |_, this, ()| Ok(this.encoding));
+    }
+}